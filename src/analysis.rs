@@ -0,0 +1,530 @@
+use crate::image_utils::unpremultiply;
+use crate::math::equirect_row_weight;
+
+use clap::ValueEnum;
+use glam::Vec3;
+use image::Rgba32FImage;
+use rayon::prelude::*;
+use serde::Serialize;
+use std::fmt;
+
+/// Histogram range for percentile queries, in stops (log2 luminance).
+const HISTOGRAM_MIN_STOPS: f32 = -24.0;
+const HISTOGRAM_MAX_STOPS: f32 = 24.0;
+const BINS_PER_STOP: usize = 16;
+const HISTOGRAM_BINS: usize = (HISTOGRAM_MAX_STOPS - HISTOGRAM_MIN_STOPS) as usize * BINS_PER_STOP;
+
+/// Offset that keeps the log of black pixels finite in log averages.
+const LOG_EPSILON: f32 = 1e-6;
+/// Percentiles listed in an [`AnalysisReport`].
+const REPORT_PERCENTILES: [f32; 9] = [0.1, 1.0, 5.0, 25.0, 50.0, 75.0, 95.0, 99.0, 99.9];
+/// Pixels whose brightest channel reaches this fraction of the image maximum count as at the peak.
+const PEAK_RATIO: f32 = 0.999;
+
+/// Statistic the automatic exposure is based on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, ValueEnum)]
+pub enum ExposureMethod {
+    /// Geometric mean of the luminance (Reinhard's "log-average").
+    #[default]
+    LogAverage,
+    /// Luminance at a given percentile of the sphere.
+    Percentile,
+}
+
+/// Settings for deriving the exposure from the scene itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AutoExposure {
+    pub method: ExposureMethod,
+    /// Value the measured luminance is mapped to (0.18 is middle grey).
+    pub key: f32,
+    /// Percentile (0-100) used by `ExposureMethod::Percentile`.
+    pub percentile: f32,
+    /// Exposure compensation in stops, applied on top of the computed value.
+    pub compensation: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            method: ExposureMethod::LogAverage,
+            key: 0.18,
+            percentile: 50.0,
+            compensation: 0.0,
+        }
+    }
+}
+
+impl AutoExposure {
+    /// Computes the exposure multiplier for an equirectangular image.
+    ///
+    /// Returns the multiplier together with the measured luminance. `luminance` are the weights
+    /// of the image's RGB channels. Empty or black images yield an exposure of 1.
+    pub fn compute(&self, image: &Rgba32FImage, luminance: Vec3) -> (f32, f32) {
+        let measured = match self.method {
+            ExposureMethod::LogAverage => log_average_luminance(image, luminance),
+            ExposureMethod::Percentile => luminance_percentile(image, luminance, self.percentile),
+        };
+
+        if !(measured.is_finite() && measured > 0.0) {
+            return (1.0, measured);
+        }
+
+        (self.key / measured * self.compensation.exp2(), measured)
+    }
+}
+
+/// Calls `f` with the luminance and solid-angle weight of every pixel of one row, skipping
+/// transparent pixels and non-finite values.
+fn for_each_sample(image: &Rgba32FImage, luminance: Vec3, y: u32, mut f: impl FnMut(f32, f32)) {
+    let row_weight = equirect_row_weight(y, image.height());
+    let width = image.width() as usize;
+    let start = y as usize * width * 4;
+
+    for p in image.as_raw()[start..start + width * 4].chunks_exact(4) {
+        let [r, g, b, a] = unpremultiply([p[0], p[1], p[2], p[3]]);
+        let lum = Vec3::new(r, g, b).dot(luminance);
+        let weight = row_weight * a;
+
+        if weight > 0.0 && lum.is_finite() {
+            f(lum.max(0.0), weight);
+        }
+    }
+}
+
+/// Solid-angle-weighted geometric mean of the luminance of an equirectangular image.
+pub fn log_average_luminance(image: &Rgba32FImage, luminance: Vec3) -> f32 {
+    let (sum, total) = (0..image.height())
+        .into_par_iter()
+        .map(|y| {
+            let mut sum = 0.0f64;
+            let mut total = 0.0f64;
+            for_each_sample(image, luminance, y, |lum, weight| {
+                sum += (weight * (LOG_EPSILON + lum).ln()) as f64;
+                total += weight as f64;
+            });
+            (sum, total)
+        })
+        .reduce(|| (0.0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    (sum / total).exp() as f32
+}
+
+/// Solid-angle-weighted luminance percentile (0-100) of an equirectangular image.
+///
+/// Uses a log histogram with 1/16 stop bins, interpolating inside the bin; the result stays
+/// within the measured luminance range.
+pub fn luminance_percentile(image: &Rgba32FImage, luminance: Vec3, percentile: f32) -> f32 {
+    let empty = || (vec![0.0f64; HISTOGRAM_BINS], f32::INFINITY, 0.0f32);
+    let (histogram, min, max) = (0..image.height())
+        .into_par_iter()
+        .map(|y| {
+            let (mut bins, mut min, mut max) = empty();
+            for_each_sample(image, luminance, y, |lum, weight| {
+                bins[histogram_bin(lum)] += weight as f64;
+                min = min.min(lum);
+                max = max.max(lum);
+            });
+            (bins, min, max)
+        })
+        .reduce(empty, |a, b| {
+            (add_bins(a.0, b.0), a.1.min(b.1), a.2.max(b.2))
+        });
+
+    histogram_percentile(&histogram, percentile, (min, max))
+}
+
+fn histogram_bin(lum: f32) -> usize {
+    let stops = lum.max(f32::MIN_POSITIVE).log2();
+    ((stops - HISTOGRAM_MIN_STOPS) * BINS_PER_STOP as f32).clamp(0.0, (HISTOGRAM_BINS - 1) as f32)
+        as usize
+}
+
+fn add_bins(mut a: Vec<f64>, b: Vec<f64>) -> Vec<f64> {
+    a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+    a
+}
+
+/// Luminance at `percentile` (0-100) of a weighted log histogram, or 0 if it is empty. The
+/// interpolated value is clamped to `range`, the measured minimum and maximum luminance.
+fn histogram_percentile(histogram: &[f64], percentile: f32, (min, max): (f32, f32)) -> f32 {
+    let total: f64 = histogram.iter().sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    let target = total * (percentile.clamp(0.0, 100.0) as f64 / 100.0);
+    let mut cumulative = 0.0;
+
+    for (bin, &weight) in histogram.iter().enumerate() {
+        if weight > 0.0 && cumulative + weight >= target {
+            let t = ((target - cumulative) / weight) as f32;
+            let stops = HISTOGRAM_MIN_STOPS + (bin as f32 + t) / BINS_PER_STOP as f32;
+            return stops.exp2().clamp(min, max);
+        }
+        cumulative += weight;
+    }
+
+    max
+}
+
+/// Statistics of an equirectangular image, see [`analyze`].
+///
+/// Luminance statistics are weighted by solid angle and skip transparent pixels; the invalid
+/// value counts are plain pixel counts.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnalysisReport {
+    pub width: u32,
+    pub height: u32,
+    pub luminance: LuminanceStats,
+    pub percentiles: Vec<PercentileValue>,
+    /// Fraction of the sphere per 1-stop bin of log2 luminance, from the darkest to the brightest
+    /// occupied bin.
+    pub histogram: Vec<HistogramBin>,
+    /// Pixels with at least one NaN component.
+    pub nan_pixels: u64,
+    /// Pixels with at least one infinite component.
+    pub infinite_pixels: u64,
+    /// Pixels with at least one negative colour component.
+    pub negative_pixels: u64,
+    /// Largest finite colour component in the image.
+    pub max_channel: f32,
+    /// Fraction of the sphere whose brightest channel is within 0.1% of `max_channel`. Every
+    /// non-black image has some; a large fraction (a flat top rather than a small sun) usually
+    /// means the capture saturated there.
+    pub peak_fraction: f32,
+    /// Stops between the darkest non-black and the brightest luminance.
+    pub dynamic_range_stops: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LuminanceStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub log_average: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PercentileValue {
+    pub percentile: f32,
+    pub luminance: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HistogramBin {
+    /// Lower edge of the bin in stops (log2 luminance); the bin spans one stop.
+    pub stops: i32,
+    pub fraction: f32,
+}
+
+/// Per-row partial sums of [`analyze`].
+struct Accumulator {
+    nan: u64,
+    infinite: u64,
+    negative: u64,
+    min: f32,
+    min_positive: f32,
+    max: f32,
+    max_channel: f32,
+    weight: f64,
+    sum: f64,
+    log_sum: f64,
+    bins: Vec<f64>,
+}
+
+impl Accumulator {
+    fn new() -> Self {
+        Self {
+            nan: 0,
+            infinite: 0,
+            negative: 0,
+            min: f32::INFINITY,
+            min_positive: f32::INFINITY,
+            max: 0.0,
+            max_channel: 0.0,
+            weight: 0.0,
+            sum: 0.0,
+            log_sum: 0.0,
+            bins: vec![0.0; HISTOGRAM_BINS],
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        self.nan += other.nan;
+        self.infinite += other.infinite;
+        self.negative += other.negative;
+        self.min = self.min.min(other.min);
+        self.min_positive = self.min_positive.min(other.min_positive);
+        self.max = self.max.max(other.max);
+        self.max_channel = self.max_channel.max(other.max_channel);
+        self.weight += other.weight;
+        self.sum += other.sum;
+        self.log_sum += other.log_sum;
+        self.bins = add_bins(self.bins, other.bins);
+        self
+    }
+}
+
+/// Analyzes an equirectangular image: luminance statistics, percentiles and a histogram (all
+/// weighted by solid angle), invalid values, clipping and dynamic range.
+///
+/// `luminance` are the luminance weights of the image's colour space.
+pub fn analyze(image: &Rgba32FImage, luminance: Vec3) -> AnalysisReport {
+    let width = image.width() as usize;
+
+    let stats = (0..image.height())
+        .into_par_iter()
+        .map(|y| {
+            let mut acc = Accumulator::new();
+            let start = y as usize * width * 4;
+
+            for p in image.as_raw()[start..start + width * 4].chunks_exact(4) {
+                acc.nan += p.iter().any(|v| v.is_nan()) as u64;
+                acc.infinite += p.iter().any(|v| v.is_infinite()) as u64;
+                acc.negative += p.iter().any(|&v| v < 0.0) as u64;
+                for &v in p[..3].iter().filter(|v| v.is_finite()) {
+                    acc.max_channel = acc.max_channel.max(v);
+                }
+            }
+
+            for_each_sample(image, luminance, y, |lum, weight| {
+                acc.min = acc.min.min(lum);
+                if lum > 0.0 {
+                    acc.min_positive = acc.min_positive.min(lum);
+                }
+                acc.max = acc.max.max(lum);
+                acc.weight += weight as f64;
+                acc.sum += (weight * lum) as f64;
+                acc.log_sum += (weight * (LOG_EPSILON + lum).ln()) as f64;
+                acc.bins[histogram_bin(lum)] += weight as f64;
+            });
+
+            acc
+        })
+        .reduce(Accumulator::new, Accumulator::merge);
+
+    let peak_level = stats.max_channel * PEAK_RATIO;
+    let at_peak = if stats.max_channel > 0.0 {
+        (0..image.height())
+            .into_par_iter()
+            .map(|y| {
+                let row_weight = equirect_row_weight(y, image.height());
+                let start = y as usize * width * 4;
+                image.as_raw()[start..start + width * 4]
+                    .chunks_exact(4)
+                    .filter(|p| p[3] > 0.0 && p[..3].iter().any(|&v| v >= peak_level))
+                    .map(|p| (row_weight * p[3]) as f64)
+                    .sum::<f64>()
+            })
+            .sum::<f64>()
+    } else {
+        0.0
+    };
+
+    let (mean, log_average, peak_fraction) = if stats.weight > 0.0 {
+        (
+            (stats.sum / stats.weight) as f32,
+            (stats.log_sum / stats.weight).exp() as f32,
+            (at_peak / stats.weight) as f32,
+        )
+    } else {
+        (0.0, 0.0, 0.0)
+    };
+
+    let percentiles = REPORT_PERCENTILES
+        .iter()
+        .map(|&percentile| PercentileValue {
+            percentile,
+            luminance: histogram_percentile(&stats.bins, percentile, (stats.min, stats.max)),
+        })
+        .collect();
+
+    // Sum the fine bins into whole stops and trim the empty ends.
+    let stops: Vec<f64> = stats
+        .bins
+        .chunks(BINS_PER_STOP)
+        .map(|c| c.iter().sum())
+        .collect();
+    let first = stops.iter().position(|&w| w > 0.0).unwrap_or(0);
+    let last = stops.iter().rposition(|&w| w > 0.0).unwrap_or(0);
+    let histogram = if stats.weight > 0.0 {
+        (first..=last)
+            .map(|i| HistogramBin {
+                stops: HISTOGRAM_MIN_STOPS as i32 + i as i32,
+                fraction: (stops[i] / stats.weight) as f32,
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let dynamic_range_stops = if stats.min_positive < stats.max {
+        (stats.max / stats.min_positive).log2()
+    } else {
+        0.0
+    };
+
+    AnalysisReport {
+        width: image.width(),
+        height: image.height(),
+        luminance: LuminanceStats {
+            min: if stats.weight > 0.0 { stats.min } else { 0.0 },
+            max: stats.max,
+            mean,
+            log_average,
+        },
+        percentiles,
+        histogram,
+        nan_pixels: stats.nan,
+        infinite_pixels: stats.infinite,
+        negative_pixels: stats.negative,
+        max_channel: stats.max_channel,
+        peak_fraction,
+        dynamic_range_stops,
+    }
+}
+
+impl AnalysisReport {
+    /// The report as pretty-printed JSON.
+    pub fn to_json(&self) -> String {
+        // Plain structs of numbers always serialize; non-finite floats become `null`.
+        serde_json::to_string_pretty(self).expect("analysis report serializes")
+    }
+}
+
+impl fmt::Display for AnalysisReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const BAR_WIDTH: f32 = 40.0;
+        let lum = &self.luminance;
+
+        writeln!(f, "Size:           {}x{}", self.width, self.height)?;
+        writeln!(f, "Luminance (solid-angle weighted):")?;
+        writeln!(f, "  min           {:.6}", lum.min)?;
+        writeln!(f, "  max           {:.4}", lum.max)?;
+        writeln!(f, "  mean          {:.4}", lum.mean)?;
+        writeln!(f, "  log-average   {:.4}", lum.log_average)?;
+        writeln!(f, "  dynamic range {:.1} stops", self.dynamic_range_stops)?;
+
+        writeln!(f, "Percentiles:")?;
+        for p in &self.percentiles {
+            writeln!(
+                f,
+                "  {:<5} {:.6}",
+                format!("p{}", p.percentile),
+                p.luminance
+            )?;
+        }
+
+        writeln!(
+            f,
+            "Invalid pixels: {} NaN, {} Inf, {} negative",
+            self.nan_pixels, self.infinite_pixels, self.negative_pixels
+        )?;
+        writeln!(
+            f,
+            "At peak:        {:.4}% of the sphere within 0.1% of max channel {:.4}",
+            self.peak_fraction * 100.0,
+            self.max_channel
+        )?;
+
+        writeln!(f, "Histogram (EV = log2 luminance):")?;
+        let peak = self
+            .histogram
+            .iter()
+            .map(|b| b.fraction)
+            .fold(0.0, f32::max);
+        for bin in &self.histogram {
+            let bar = if peak > 0.0 {
+                (bin.fraction / peak * BAR_WIDTH).round() as usize
+            } else {
+                0
+            };
+            writeln!(
+                f,
+                "  {:+4} {:<40} {:6.2}%",
+                bin.stops,
+                "#".repeat(bar),
+                bin.fraction * 100.0
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LUMA: Vec3 = Vec3::new(0.2126, 0.7152, 0.0722);
+
+    #[test]
+    fn test_uniform_scene_exposure() {
+        let img = Rgba32FImage::from_pixel(8, 4, image::Rgba([0.36, 0.36, 0.36, 1.0]));
+
+        let (exposure, measured) = AutoExposure::default().compute(&img, LUMA);
+        assert!((measured - 0.36).abs() < 1e-3);
+        assert!((exposure - 0.5).abs() < 1e-3);
+
+        let median = AutoExposure {
+            method: ExposureMethod::Percentile,
+            compensation: 1.0,
+            ..Default::default()
+        };
+        let (exposure, _) = median.compute(&img, LUMA);
+        assert!((exposure - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_poles_count_less() {
+        // Bright polar rows are half the pixels but well under half the sphere.
+        let mut img = Rgba32FImage::from_pixel(8, 4, image::Rgba([0.01, 0.01, 0.01, 1.0]));
+        for x in 0..8 {
+            img.put_pixel(x, 0, image::Rgba([100.0, 100.0, 100.0, 1.0]));
+            img.put_pixel(x, 3, image::Rgba([100.0, 100.0, 100.0, 1.0]));
+        }
+
+        let p60 = luminance_percentile(&img, LUMA, 60.0);
+        assert!(p60 < 0.02);
+    }
+
+    #[test]
+    fn test_report_counts_and_stats() {
+        let mut img = Rgba32FImage::from_pixel(8, 4, image::Rgba([0.5, 0.5, 0.5, 1.0]));
+        img.put_pixel(0, 1, image::Rgba([f32::NAN, 0.0, 0.0, 1.0]));
+        img.put_pixel(1, 1, image::Rgba([f32::INFINITY, 1.0, 1.0, 1.0]));
+        img.put_pixel(2, 1, image::Rgba([-0.1, 0.5, 0.5, 1.0]));
+        img.put_pixel(3, 2, image::Rgba([8.0, 8.0, 8.0, 1.0]));
+        img.put_pixel(4, 2, image::Rgba([0.25, 0.25, 0.25, 1.0]));
+
+        let report = analyze(&img, LUMA);
+
+        assert_eq!(
+            (
+                report.nan_pixels,
+                report.infinite_pixels,
+                report.negative_pixels
+            ),
+            (1, 1, 1)
+        );
+        assert_eq!(report.max_channel, 8.0);
+        assert!((report.luminance.max - 8.0).abs() < 1e-4);
+        assert!((report.dynamic_range_stops - 5.0).abs() < 1e-3);
+        for p in &report.percentiles {
+            assert!(
+                (report.luminance.min..=report.luminance.max).contains(&p.luminance),
+                "{p:?}"
+            );
+        }
+        // Only the single 8.0 pixel is at the peak.
+        assert!(report.peak_fraction > 0.0 && report.peak_fraction < 0.1);
+
+        let total: f32 = report.histogram.iter().map(|b| b.fraction).sum();
+        assert!((total - 1.0).abs() < 1e-4);
+        assert!(report.to_json().contains("\"nan_pixels\": 1"));
+    }
+}
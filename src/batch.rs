@@ -0,0 +1,503 @@
+use crate::codecs::OutputFormat;
+use crate::error::{Eq2cError, Result};
+use crate::layouts::LayoutType;
+use crate::{Config, NoProgress, RunReport};
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Extensions picked up when a directory is given as input.
+const INPUT_EXTENSIONS: [&str; 9] = [
+    "hdr", "exr", "pfm", "tif", "tiff", "png", "jpg", "jpeg", "webp",
+];
+
+/// Output file name used when none is given.
+pub const DEFAULT_NAME_TEMPLATE: &str = "{stem}.{ext}";
+
+/// How a batch of inputs is written.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub output_dir: PathBuf,
+    /// Output file name with `{stem}` (input file name without extension), `{ext}` (output
+    /// format extension), `{layout}` and `{size}` placeholders.
+    pub name_template: String,
+    /// Number of images converted at the same time.
+    pub jobs: usize,
+    /// Convert every input, even if its outputs are newer than the input.
+    pub rebuild: bool,
+}
+
+/// Outcome of one input of a batch.
+#[derive(Debug)]
+pub enum BatchStatus {
+    Converted(RunReport),
+    /// All outputs were newer than the input (and LUT).
+    Skipped,
+    Failed(Eq2cError),
+}
+
+#[derive(Debug)]
+pub struct BatchItem {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub status: BatchStatus,
+    pub elapsed: Duration,
+}
+
+/// Result of [`run_batch`], in input order.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    pub items: Vec<BatchItem>,
+}
+
+impl BatchReport {
+    fn count(&self, f: impl Fn(&BatchStatus) -> bool) -> usize {
+        self.items.iter().filter(|item| f(&item.status)).count()
+    }
+
+    pub fn converted(&self) -> usize {
+        self.count(|s| matches!(s, BatchStatus::Converted(_)))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|s| matches!(s, BatchStatus::Skipped))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|s| matches!(s, BatchStatus::Failed(_)))
+    }
+
+    /// The errors of the failed inputs.
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &Eq2cError)> {
+        self.items.iter().filter_map(|item| match &item.status {
+            BatchStatus::Failed(e) => Some((item.input.as_path(), e)),
+            _ => None,
+        })
+    }
+
+    /// Exit code for the batch: `None` if nothing failed, the code `code_of` gives the failures
+    /// if they all agree, and 1 otherwise.
+    pub fn exit_code(&self, code_of: impl Fn(&Eq2cError) -> i32) -> Option<i32> {
+        let mut codes = self.errors().map(|(_, e)| code_of(e));
+        let first = codes.next()?;
+        Some(if codes.all(|code| code == first) {
+            first
+        } else {
+            1
+        })
+    }
+}
+
+/// Expands files, directories (their supported images, not recursively) and glob patterns into a
+/// list of input files without duplicates, in argument order (and sorted within each directory or
+/// pattern).
+///
+/// # Errors
+///
+/// Returns `Eq2cError::ArgumentError` for an invalid pattern or one that matches nothing, and
+/// `Eq2cError::Io` if a directory cannot be read.
+pub fn expand_inputs(patterns: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+
+    for pattern in patterns {
+        let text = pattern.to_string_lossy();
+
+        if pattern.is_dir() {
+            let mut found: Vec<PathBuf> = std::fs::read_dir(pattern)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<_>>()?;
+            found.retain(|path| path.is_file() && is_supported(path));
+            found.sort();
+            inputs.extend(found);
+        } else if text.contains(['*', '?', '[']) {
+            let matches = glob::glob(&text)
+                .map_err(|e| {
+                    Eq2cError::ArgumentError(format!("invalid pattern '{}': {}", text, e))
+                })?
+                .filter_map(|entry| entry.ok())
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+
+            if matches.is_empty() {
+                return Err(Eq2cError::ArgumentError(format!(
+                    "'{}' matches no files",
+                    text
+                )));
+            }
+            inputs.extend(matches);
+        } else {
+            inputs.push(pattern.clone());
+        }
+    }
+
+    let mut seen = HashSet::new();
+    inputs.retain(|path| seen.insert(path.clone()));
+    Ok(inputs)
+}
+
+fn is_supported(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|e| INPUT_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// Fills in the placeholders of a name template for one input.
+///
+/// # Errors
+///
+/// Returns `Eq2cError::ArgumentError` for an unknown or unclosed placeholder.
+pub fn output_name(
+    template: &str,
+    input: &Path,
+    format: OutputFormat,
+    layout: LayoutType,
+    size: u32,
+) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        name.push_str(&rest[..open]);
+        let close = rest[open..].find('}').ok_or_else(|| {
+            Eq2cError::ArgumentError(format!("unclosed '{{' in name template '{}'", template))
+        })?;
+
+        match &rest[open + 1..open + close] {
+            "stem" => name.push_str(&input.file_stem().unwrap_or_default().to_string_lossy()),
+            "ext" => name.push_str(format.extension()),
+            "layout" => name.push_str(layout.name()),
+            "size" => name.push_str(&size.to_string()),
+            other => {
+                return Err(Eq2cError::ArgumentError(format!(
+                    "unknown placeholder '{{{}}}' in name template (use stem, ext, layout or size)",
+                    other
+                )));
+            }
+        }
+        rest = &rest[open + close + 1..];
+    }
+
+    name.push_str(rest);
+    Ok(name)
+}
+
+/// Whether every output of `config` exists and is newer than its input and LUT.
+fn is_up_to_date(config: &Config) -> bool {
+    let Some(outputs) = config.output_paths() else {
+        return false;
+    };
+
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    let newest_source = std::iter::once(&config.input)
+        .chain(&config.lut)
+        .map(|path| modified(path))
+        .try_fold(SystemTime::UNIX_EPOCH, |newest, time| {
+            Some(newest.max(time?))
+        });
+
+    let Some(newest_source) = newest_source else {
+        return false;
+    };
+
+    outputs
+        .iter()
+        .all(|output| modified(output).is_some_and(|time| time >= newest_source))
+}
+
+/// Converts every input with the settings of `template` into `options.output_dir`, running up
+/// to `options.jobs` conversions at a time. `on_done` is called (from the worker threads) as
+/// each input finishes.
+///
+/// Failures of single inputs are collected in the report rather than stopping the batch.
+///
+/// # Errors
+///
+/// Returns `Eq2cError::ArgumentError` for a bad name template or if two inputs would be written
+/// to the same output, and `Eq2cError::Io` if the output directory cannot be created.
+pub fn run_batch(
+    template: &Config,
+    inputs: &[PathBuf],
+    options: &BatchOptions,
+    on_done: &(dyn Fn(&BatchItem) + Sync),
+) -> Result<BatchReport> {
+    let layout = template.options.effective_layout();
+    let mut configs = Vec::with_capacity(inputs.len());
+    let mut outputs = HashSet::new();
+
+    for input in inputs {
+        let name = output_name(
+            &options.name_template,
+            input,
+            template.options.format,
+            layout,
+            template.options.size,
+        )?;
+        // Recipe paths are templates of their own, relative to the output directory.
+        let output = match template.recipe {
+            Some(_) => options.output_dir.clone(),
+            None => options.output_dir.join(name),
+        };
+        let config = Config {
+            input: input.clone(),
+            output,
+            ..template.clone()
+        };
+
+        for path in config
+            .output_paths()
+            .unwrap_or_else(|| vec![config.output.clone()])
+        {
+            if !outputs.insert(path.clone()) {
+                return Err(Eq2cError::ArgumentError(format!(
+                    "several inputs would be written to {}; add {{layout}}, {{size}} or a \
+                     distinct {{stem}} to the name template",
+                    path.display()
+                )));
+            }
+        }
+
+        configs.push(config);
+    }
+
+    std::fs::create_dir_all(&options.output_dir)?;
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(configs.len()));
+
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.clamp(1, configs.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(config) = configs.get(index) else {
+                        break;
+                    };
+
+                    let start = Instant::now();
+                    let status = if !options.rebuild && is_up_to_date(config) {
+                        BatchStatus::Skipped
+                    } else {
+                        match crate::run_with_progress(config.clone(), &NoProgress) {
+                            Ok(report) => BatchStatus::Converted(report),
+                            Err(e) => BatchStatus::Failed(e),
+                        }
+                    };
+
+                    let item = BatchItem {
+                        input: config.input.clone(),
+                        output: config.output.clone(),
+                        status,
+                        elapsed: start.elapsed(),
+                    };
+                    on_done(&item);
+                    results.lock().unwrap().push((index, item));
+                }
+            });
+        }
+    });
+
+    let mut items = results.into_inner().unwrap();
+    items.sort_by_key(|(index, _)| *index);
+    Ok(BatchReport {
+        items: items.into_iter().map(|(_, item)| item).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::ConvertOptions;
+    use std::fs::File;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eq2c-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_png(path: &Path) {
+        image::RgbImage::from_pixel(8, 4, image::Rgb([200, 120, 40]))
+            .save(path)
+            .unwrap();
+    }
+
+    fn template() -> Config {
+        Config {
+            input: PathBuf::new(),
+            input_transfer: None,
+            exr: Default::default(),
+            all_layers: false,
+            output: PathBuf::new(),
+            lut: None,
+            lut_interpolation: Default::default(),
+            lut_shaper: None,
+            options: ConvertOptions {
+                size: 4,
+                ..Default::default()
+            },
+            recipe: None,
+            sizes: Vec::new(),
+            size_naming: Default::default(),
+            overwrite: Default::default(),
+        }
+    }
+
+    fn batch_options(output_dir: PathBuf) -> BatchOptions {
+        BatchOptions {
+            output_dir,
+            name_template: DEFAULT_NAME_TEMPLATE.to_string(),
+            jobs: 2,
+            rebuild: false,
+        }
+    }
+
+    #[test]
+    fn test_expand_inputs() {
+        let dir = scratch("batch-expand");
+        for name in ["b.png", "a.hdr", "notes.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        std::fs::create_dir(dir.join("nested.png")).unwrap();
+
+        // Directory contents are sorted and filtered to images, explicit files are taken as
+        // given, and nothing is listed twice.
+        let inputs = expand_inputs(&[
+            dir.clone(),
+            dir.join("*.png"),
+            dir.join("a.hdr"),
+            dir.join("notes.txt"),
+        ])
+        .unwrap();
+        assert_eq!(
+            inputs,
+            [dir.join("a.hdr"), dir.join("b.png"), dir.join("notes.txt")]
+        );
+
+        assert!(matches!(
+            expand_inputs(&[dir.join("*.exr")]),
+            Err(Eq2cError::ArgumentError(_))
+        ));
+        assert!(matches!(
+            expand_inputs(&[dir.join("[.exr")]),
+            Err(Eq2cError::ArgumentError(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_skips_up_to_date_outputs_and_summarizes_failures() {
+        let dir = scratch("batch-run");
+        let (good, broken) = (dir.join("good.png"), dir.join("broken.png"));
+        write_png(&good);
+        std::fs::write(&broken, b"not a png").unwrap();
+        let options = batch_options(dir.join("out"));
+        let inputs = [good.clone(), broken];
+
+        let report = run_batch(&template(), &inputs, &options, &|_| {}).unwrap();
+        assert_eq!((report.converted(), report.failed()), (1, 1));
+        assert_eq!(report.exit_code(|_| 65), Some(65));
+
+        let output = options.output_dir.join("good.png");
+        let config = Config {
+            input: good.clone(),
+            output: output.clone(),
+            ..template()
+        };
+        assert!(is_up_to_date(&config));
+        let report = run_batch(&template(), &inputs, &options, &|_| {}).unwrap();
+        assert_eq!((report.skipped(), report.failed()), (1, 1));
+
+        // An input newer than its output is converted again, as is everything with `rebuild`.
+        let later =
+            std::fs::metadata(&output).unwrap().modified().unwrap() + Duration::from_secs(5);
+        File::options()
+            .write(true)
+            .open(&good)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert!(!is_up_to_date(&config));
+        let report = run_batch(&template(), &inputs[..1], &options, &|_| {}).unwrap();
+        assert_eq!(report.converted(), 1);
+        assert_eq!(report.exit_code(|_| 65), None);
+
+        let rebuild = BatchOptions {
+            rebuild: true,
+            ..options
+        };
+        let report = run_batch(&template(), &inputs[..1], &rebuild, &|_| {}).unwrap();
+        assert_eq!(report.converted(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_duplicate_outputs() {
+        let dir = scratch("batch-duplicates");
+        let inputs = [dir.join("sky.hdr"), dir.join("sky.exr")];
+        let options = batch_options(dir.join("out"));
+
+        assert!(matches!(
+            run_batch(&template(), &inputs, &options, &|_| {}),
+            Err(Eq2cError::ArgumentError(_))
+        ));
+        // Nothing is created before the check.
+        assert!(!options.output_dir.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_exit_code_of_mixed_failures() {
+        let item = |error| BatchItem {
+            input: PathBuf::from("in.hdr"),
+            output: PathBuf::from("out.png"),
+            status: BatchStatus::Failed(error),
+            elapsed: Duration::ZERO,
+        };
+        let report = BatchReport {
+            items: vec![
+                item(Eq2cError::ArgumentError("a".to_string())),
+                item(Eq2cError::Decoding("b".to_string())),
+            ],
+        };
+        let code_of = |e: &Eq2cError| match e {
+            Eq2cError::Decoding(_) => 65,
+            _ => 64,
+        };
+
+        assert_eq!(report.exit_code(code_of), Some(1));
+        assert_eq!(BatchReport::default().exit_code(code_of), None);
+    }
+
+    #[test]
+    fn test_output_name() {
+        let input = Path::new("hdri/sunset_4k.exr");
+
+        assert_eq!(
+            output_name(
+                DEFAULT_NAME_TEMPLATE,
+                input,
+                OutputFormat::Jpeg,
+                LayoutType::Cross,
+                512
+            )
+            .unwrap(),
+            "sunset_4k.jpg"
+        );
+        assert_eq!(
+            output_name(
+                "{stem}-{layout}-{size}.{ext}",
+                input,
+                OutputFormat::Png,
+                LayoutType::StripHorizontal,
+                256
+            )
+            .unwrap(),
+            "sunset_4k-strip-h-256.png"
+        );
+        assert!(output_name("{name}.png", input, OutputFormat::Png, LayoutType::Cross, 1).is_err());
+    }
+}
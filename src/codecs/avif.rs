@@ -0,0 +1,47 @@
+use super::ldr::{self, LdrSettings};
+use super::{AVIF_SPEED, SkyboxEncoder};
+use crate::error::Result;
+
+use image::codecs::avif::AvifEncoder as ImageAvifEncoder;
+use image::{ExtendedColorType, ImageEncoder, Rgba32FImage};
+use std::io::Write;
+
+pub struct AvifEncoder {
+    pub ldr: LdrSettings,
+    pub quality: u8,
+    pub alpha: bool,
+}
+
+impl SkyboxEncoder for AvifEncoder {
+    /// Tone maps the image through the shared LDR path and writes it as an 8-bit AVIF.
+    ///
+    /// Chroma is always stored at full resolution (4:4:4), so the subsampling setting does not
+    /// apply to this encoder. Alpha is written only when `alpha` is set.
+    ///
+    /// # Errors
+    ///
+    /// Propagates I/O errors and errors from the underlying AV1 encoder.
+    fn encode_to(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        let (ldr_data, color_type) = if self.alpha {
+            (
+                ldr::tonemap_to_rgba8(image, &self.ldr)?,
+                ExtendedColorType::Rgba8,
+            )
+        } else {
+            (
+                ldr::tonemap_to_rgb8(image, &self.ldr)?,
+                ExtendedColorType::Rgb8,
+            )
+        };
+
+        let encoder = ImageAvifEncoder::new_with_speed_quality(
+            writer,
+            AVIF_SPEED,
+            self.quality.clamp(1, 100),
+        );
+
+        encoder.write_image(&ldr_data, image.width(), image.height(), color_type)?;
+
+        Ok(())
+    }
+}
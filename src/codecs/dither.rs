@@ -0,0 +1,314 @@
+use clap::ValueEnum;
+use std::sync::LazyLock;
+
+/// Side length of the tiled blue-noise threshold map.
+const BLUE_NOISE_SIZE: usize = 64;
+/// Sigma of the Gaussian used by void-and-cluster to measure how crowded a pixel is.
+const BLUE_NOISE_SIGMA: f32 = 1.5;
+
+/// Noise added to display values before they are rounded to 8 bits, to break up banding.
+///
+/// Every pattern is a pure function of the pixel position, so re-runs are bit-identical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Dither {
+    /// Plain rounding to the nearest code value.
+    #[default]
+    None,
+    /// 8x8 Bayer matrix; cheap, but leaves a visible cross-hatch.
+    Ordered,
+    /// Triangular-PDF white noise of ±1 code value, which makes the error independent of the
+    /// signal.
+    #[value(name = "triangular", alias = "tpdf")]
+    Triangular,
+    /// A tiled 64x64 void-and-cluster blue-noise mask; the least visible grain.
+    #[value(name = "blue-noise")]
+    BlueNoise,
+    /// Serpentine Floyd-Steinberg error diffusion.
+    #[value(name = "error-diffusion", alias = "floyd-steinberg")]
+    ErrorDiffusion,
+}
+
+impl Dither {
+    /// Offset, in code values, added to `channel` of the pixel at (`x`, `y`) before rounding.
+    ///
+    /// Always 0 for `None` and `ErrorDiffusion`; the latter is handled by [`ErrorDiffuser`].
+    pub fn offset(self, x: u32, y: u32, channel: u32) -> f32 {
+        match self {
+            Dither::None | Dither::ErrorDiffusion => 0.0,
+            Dither::Ordered => {
+                let rank = bayer_rank(x as usize & 7, y as usize & 7);
+                (rank as f32 + 0.5) / 64.0 - 0.5
+            }
+            Dither::Triangular => {
+                let seed = hash(x ^ hash(y ^ hash(channel)));
+                unit(seed) + unit(hash(seed)) - 1.0
+            }
+            Dither::BlueNoise => {
+                // Shift the tile per channel so the three channels don't share their grain.
+                let shift = channel as usize * 23;
+                let tx = (x as usize + shift) % BLUE_NOISE_SIZE;
+                let ty = (y as usize + shift * 2) % BLUE_NOISE_SIZE;
+                BLUE_NOISE[ty * BLUE_NOISE_SIZE + tx]
+            }
+        }
+    }
+}
+
+/// Rounds a normalized display value to 8 bits.
+pub fn quantize_u8(v: f32) -> u8 {
+    (v * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// Floyd-Steinberg state for one image, fed one row at a time from top to bottom.
+///
+/// Odd rows run right to left (serpentine order) to avoid the diagonal "worm" artifacts.
+pub struct ErrorDiffuser {
+    width: usize,
+    row: usize,
+    current: Vec<[f32; 3]>,
+    next: Vec<[f32; 3]>,
+}
+
+impl ErrorDiffuser {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            row: 0,
+            current: vec![[0.0; 3]; width],
+            next: vec![[0.0; 3]; width],
+        }
+    }
+
+    /// Quantizes a row of normalized display values, calling `write(x, rgb)` for each pixel.
+    pub fn quantize_row(&mut self, values: &[[f32; 3]], mut write: impl FnMut(usize, [u8; 3])) {
+        let width = self.width;
+        let reverse = self.row % 2 == 1;
+
+        for i in 0..width {
+            let x = if reverse { width - 1 - i } else { i };
+            // Neighbour `d` pixels ahead in the scan direction, if inside the row.
+            let ahead = |d: isize| {
+                let x = if reverse {
+                    x as isize - d
+                } else {
+                    x as isize + d
+                };
+                (0..width as isize).contains(&x).then_some(x as usize)
+            };
+
+            let mut out = [0u8; 3];
+            for c in 0..3 {
+                let value = (values[x][c] * 255.0 + self.current[x][c]).clamp(0.0, 255.0);
+                let code = value.round();
+                let error = value - code;
+                out[c] = code as u8;
+
+                if let Some(f) = ahead(1) {
+                    self.current[f][c] += error * 7.0 / 16.0;
+                    self.next[f][c] += error * 1.0 / 16.0;
+                }
+                if let Some(b) = ahead(-1) {
+                    self.next[b][c] += error * 3.0 / 16.0;
+                }
+                self.next[x][c] += error * 5.0 / 16.0;
+            }
+
+            write(x, out);
+        }
+
+        std::mem::swap(&mut self.current, &mut self.next);
+        self.next.fill([0.0; 3]);
+        self.row += 1;
+    }
+}
+
+/// Position of (`x`, `y`) in the recursive 8x8 Bayer ordering.
+fn bayer_rank(x: usize, y: usize) -> usize {
+    let mut rank = 0;
+    for bit in 0..3 {
+        let (bx, by) = ((x >> bit) & 1, (y >> bit) & 1);
+        rank |= ((bx ^ by) << 1 | by) << (2 * (2 - bit));
+    }
+    rank
+}
+
+/// Integer hash with good avalanche (Chris Wellons' "lowbias32").
+fn hash(mut v: u32) -> u32 {
+    v ^= v >> 16;
+    v = v.wrapping_mul(0x7feb_352d);
+    v ^= v >> 15;
+    v = v.wrapping_mul(0x846c_a68b);
+    v ^= v >> 16;
+    v
+}
+
+/// Maps a hash to [0, 1).
+fn unit(v: u32) -> f32 {
+    (v >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// Blue-noise thresholds in [-0.5, 0.5), generated once with Ulichney's void-and-cluster method.
+static BLUE_NOISE: LazyLock<Vec<f32>> = LazyLock::new(|| {
+    let n = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+    void_and_cluster()
+        .into_iter()
+        .map(|rank| (rank as f32 + 0.5) / n as f32 - 0.5)
+        .collect()
+});
+
+/// Toroidal Gaussian "energy" of a binary pattern, updated as pixels are toggled.
+struct Energy {
+    kernel: Vec<f32>,
+    values: Vec<f32>,
+}
+
+impl Energy {
+    fn new() -> Self {
+        let size = BLUE_NOISE_SIZE;
+        let kernel = (0..size * size)
+            .map(|i| {
+                // Wrapped distance, so the tile repeats without seams.
+                let wrap = |d: usize| d.min(size - d) as f32;
+                let (dx, dy) = (wrap(i % size), wrap(i / size));
+                (-(dx * dx + dy * dy) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+            })
+            .collect();
+
+        Self {
+            kernel,
+            values: vec![0.0; size * size],
+        }
+    }
+
+    /// Adds (`sign` 1) or removes (`sign` -1) the contribution of a pixel.
+    fn toggle(&mut self, index: usize, sign: f32) {
+        let size = BLUE_NOISE_SIZE;
+        let (px, py) = (index % size, index / size);
+        for (i, value) in self.values.iter_mut().enumerate() {
+            let dx = (i % size + size - px) % size;
+            let dy = (i / size + size - py) % size;
+            *value += sign * self.kernel[dy * size + dx];
+        }
+    }
+
+    /// The set (`tightest` cluster) or unset (largest void) pixel with the most or least energy.
+    fn extreme(&self, pattern: &[bool], set: bool) -> usize {
+        let candidates = self
+            .values
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| pattern[i] == set);
+
+        if set {
+            candidates.max_by(|a, b| a.1.total_cmp(b.1)).unwrap().0
+        } else {
+            candidates.min_by(|a, b| a.1.total_cmp(b.1)).unwrap().0
+        }
+    }
+}
+
+/// Ranks every pixel of the tile from 0 to `n - 1` so that every prefix is evenly spread.
+fn void_and_cluster() -> Vec<usize> {
+    let n = BLUE_NOISE_SIZE * BLUE_NOISE_SIZE;
+    let mut pattern = vec![false; n];
+    let mut energy = Energy::new();
+
+    // Deterministic random seed points, 10% of the tile.
+    let initial = n / 10;
+    let mut seed = 0;
+    let mut placed = 0;
+    while placed < initial {
+        seed += 1;
+        let index = hash(seed) as usize % n;
+        if !pattern[index] {
+            pattern[index] = true;
+            energy.toggle(index, 1.0);
+            placed += 1;
+        }
+    }
+
+    // Relax: move the tightest cluster into the largest void until it stops moving.
+    loop {
+        let cluster = energy.extreme(&pattern, true);
+        pattern[cluster] = false;
+        energy.toggle(cluster, -1.0);
+
+        let void = energy.extreme(&pattern, false);
+        pattern[void] = true;
+        energy.toggle(void, 1.0);
+
+        if void == cluster {
+            break;
+        }
+    }
+
+    let mut ranks = vec![0; n];
+
+    // Phase 1: strip the initial points, tightest first, for the ranks below `initial`.
+    {
+        let mut pattern = pattern.clone();
+        let mut energy = Energy {
+            kernel: energy.kernel.clone(),
+            values: energy.values.clone(),
+        };
+        for rank in (0..initial).rev() {
+            let cluster = energy.extreme(&pattern, true);
+            pattern[cluster] = false;
+            energy.toggle(cluster, -1.0);
+            ranks[cluster] = rank;
+        }
+    }
+
+    // Phases 2 and 3: fill the largest void until the tile is full. (Once more than half is set,
+    // the tightest cluster of unset pixels is the same pixel as the largest void.)
+    for rank in initial..n {
+        let void = energy.extreme(&pattern, false);
+        pattern[void] = true;
+        energy.toggle(void, 1.0);
+        ranks[void] = rank;
+    }
+
+    ranks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns_are_deterministic_and_bounded() {
+        for dither in [Dither::Ordered, Dither::Triangular, Dither::BlueNoise] {
+            let mut sum = 0.0;
+            for y in 0..64 {
+                for x in 0..64 {
+                    let offset = dither.offset(x, y, 1);
+                    assert_eq!(offset, dither.offset(x, y, 1));
+                    assert!(offset.abs() <= 1.0, "{dither:?}: {offset}");
+                    sum += offset;
+                }
+            }
+            // Zero mean, so dithering doesn't shift the brightness.
+            assert!((sum / 4096.0).abs() < 0.02, "{dither:?}: {sum}");
+        }
+
+        // The blue-noise tile is a permutation of its ranks.
+        let mut ranks = void_and_cluster();
+        ranks.sort_unstable();
+        assert!(ranks.iter().enumerate().all(|(i, &r)| i == r));
+    }
+
+    #[test]
+    fn test_error_diffusion_keeps_average() {
+        // 100.25 code values: plain rounding gives 100 everywhere.
+        let value = 100.25 / 255.0;
+        let mut diffuser = ErrorDiffuser::new(16);
+        let mut total = 0u32;
+
+        for _ in 0..16 {
+            diffuser.quantize_row(&[[value; 3]; 16], |_, rgb| total += rgb[0] as u32);
+        }
+
+        let mean = total as f32 / 256.0;
+        assert!((mean - 100.25).abs() < 0.05, "{mean}");
+    }
+}
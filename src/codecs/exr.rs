@@ -1,12 +1,25 @@
 use super::SkyboxEncoder;
+use crate::color::ColorSpace;
 use crate::error::Result;
-use image::Rgb32FImage;
-use std::path::Path;
 
-pub struct ExrEncoder;
+use ::exr::prelude::attribute::Chromaticities;
+use ::exr::prelude::{Image, SpecificChannels, Vec2, WritableImage};
+use image::Rgba32FImage;
+use std::io::{Cursor, Write};
+
+pub struct ExrEncoder {
+    /// Write an `A` channel; without it the image is stored as plain RGB.
+    pub alpha: bool,
+    /// Primaries of the encoded data, recorded in the `chromaticities` attribute.
+    pub color_space: ColorSpace,
+}
 
 impl SkyboxEncoder for ExrEncoder {
-    /// Encodes the provided RGB 32-bit floating-point image and writes it to the specified file path in EXR format.
+    /// Encodes the provided RGBA 32-bit floating-point image and writes it to the specified file path in EXR format.
+    ///
+    /// Colour is stored premultiplied, as OpenEXR expects. Unless `alpha` is set, the image is
+    /// written as plain RGB without an alpha channel. The primaries and white point of
+    /// `color_space` are recorded as `chromaticities` (XYZ has none).
     ///
     /// Returns an error if the image cannot be written to the given path.
     ///
@@ -14,14 +27,79 @@ impl SkyboxEncoder for ExrEncoder {
     ///
     /// ```ignore
     /// use std::path::Path;
-    /// // create or load an `Rgb32FImage` here
-    /// let image: image::Rgb32FImage = unimplemented!();
-    /// let encoder = ExrEncoder;
+    /// // create or load an `Rgba32FImage` here
+    /// let image: image::Rgba32FImage = unimplemented!();
+    /// let encoder = ExrEncoder { alpha: false, color_space: ColorSpace::AcesCg };
     /// encoder.encode(&image, Path::new("skybox.exr")).unwrap();
     /// ````
-    fn encode(&self, image: &Rgb32FImage, output_path: &Path) -> Result<()> {
-        image.save(output_path)?;
+    fn encode_to(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        let size = (image.width() as usize, image.height() as usize);
+        let pixel = |Vec2(x, y): Vec2<usize>| image.get_pixel(x as u32, y as u32).0;
+        let chromaticities = chromaticities(self.color_space);
 
+        // The EXR writer seeks back to patch its offset tables, so it goes through memory.
+        let mut buffer = Cursor::new(Vec::new());
+        if self.alpha {
+            let mut exr = Image::from_channels(
+                size,
+                SpecificChannels::rgba(|p| {
+                    let [r, g, b, a] = pixel(p);
+                    (r, g, b, a)
+                }),
+            );
+            exr.attributes.chromaticities = chromaticities;
+            exr.write().to_buffered(&mut buffer)?;
+        } else {
+            let mut exr = Image::from_channels(
+                size,
+                SpecificChannels::rgb(|p| {
+                    let [r, g, b, _] = pixel(p);
+                    (r, g, b)
+                }),
+            );
+            exr.attributes.chromaticities = chromaticities;
+            exr.write().to_buffered(&mut buffer)?;
+        }
+
+        writer.write_all(buffer.get_ref())?;
         Ok(())
     }
 }
+
+fn chromaticities(space: ColorSpace) -> Option<Chromaticities> {
+    space.primaries().map(|[r, g, b, w]| Chromaticities {
+        red: Vec2(r.0, r.1),
+        green: Vec2(g.0, g.1),
+        blue: Vec2(b.0, b.1),
+        white: Vec2(w.0, w.1),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::exr::prelude::{ReadChannels, ReadLayers, read};
+
+    #[test]
+    fn test_records_chromaticities() {
+        let image = Rgba32FImage::from_pixel(2, 2, image::Rgba([0.5, 1.0, 2.0, 1.0]));
+        let encoder = ExrEncoder {
+            alpha: false,
+            color_space: ColorSpace::AcesCg,
+        };
+        let data = encoder.encode_to_vec(&image).unwrap();
+
+        let exr = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(Cursor::new(data))
+            .unwrap();
+        let chromaticities = exr.attributes.chromaticities.unwrap();
+        assert_eq!(chromaticities.red, Vec2(0.713, 0.293));
+        assert_eq!(chromaticities.white, Vec2(0.32168, 0.33767));
+        assert_eq!(exr.layer_data.channel_data.list.len(), 3);
+    }
+}
@@ -0,0 +1,337 @@
+use super::png::{PngColorInfo, write_png};
+use super::{AVIF_SPEED, SkyboxEncoder};
+use crate::color::gamut::conversion_matrix;
+use crate::color::transfer::{hlg_oetf, nits_to_pq, pq_to_nits};
+use crate::color::{ColorSpace, HdrTransfer};
+use crate::error::{Eq2cError, Result};
+use crate::image_utils::unpremultiply;
+
+use avif_serialize::Aviffy;
+use avif_serialize::constants::{ColorPrimaries, MatrixCoefficients, TransferCharacteristics};
+use glam::{Mat3, Vec3};
+use image::Rgba32FImage;
+use rav1e::prelude::*;
+use rayon::prelude::*;
+use std::io::Write;
+
+/// Luminance that scene-linear 1.0 (after exposure) is mapped to, per ITU-R BT.2408.
+pub const REFERENCE_WHITE_NITS: f32 = 203.0;
+
+/// Container used for display-referred HDR output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HdrContainer {
+    /// 16-bit PNG with a `cICP` chunk.
+    Png,
+    /// 10-bit AVIF with matching `nclx` colour information.
+    Avif,
+}
+
+/// How scene-linear values become display-referred HDR signals.
+#[derive(Debug, Clone, Copy)]
+pub struct HdrSettings {
+    pub exposure: f32,
+    pub transfer: HdrTransfer,
+    /// Peak luminance of the target display; highlights roll off towards it (BT.2390 EETF).
+    pub peak_nits: f32,
+    /// Colour space of the incoming linear data to BT.2020.
+    to_rec2020: Mat3,
+}
+
+impl HdrSettings {
+    /// `source` is the colour space of the incoming linear data; it is converted to BT.2020.
+    pub fn new(exposure: f32, transfer: HdrTransfer, peak_nits: f32, source: ColorSpace) -> Self {
+        Self {
+            exposure,
+            transfer,
+            peak_nits,
+            to_rec2020: conversion_matrix(source, ColorSpace::Rec2020),
+        }
+    }
+
+    /// Maps one linear colour in the source space to a normalized BT.2020 PQ or HLG signal.
+    pub fn map_color(&self, color: Vec3) -> Vec3 {
+        let rec2020 = (self.to_rec2020 * (color * self.exposure)).max(Vec3::ZERO);
+        let nits = self.roll_off(rec2020 * REFERENCE_WHITE_NITS);
+
+        match self.transfer {
+            HdrTransfer::Pq => {
+                Vec3::new(nits_to_pq(nits.x), nits_to_pq(nits.y), nits_to_pq(nits.z))
+            }
+            HdrTransfer::Hlg => {
+                // Inverse HLG OOTF: display light back to scene light for the given peak.
+                let gamma = 1.2 + 0.42 * (self.peak_nits / 1000.0).log10();
+                let display = nits / self.peak_nits;
+                let luma = display.dot(Vec3::new(0.2627, 0.6780, 0.0593));
+                let scene = if luma > 0.0 {
+                    display * luma.powf((1.0 - gamma) / gamma)
+                } else {
+                    Vec3::ZERO
+                };
+                Vec3::new(hlg_oetf(scene.x), hlg_oetf(scene.y), hlg_oetf(scene.z))
+            }
+        }
+    }
+
+    /// BT.2390 EETF applied to the largest channel, so highlights compress towards `peak_nits`
+    /// without hue shifts.
+    fn roll_off(&self, nits: Vec3) -> Vec3 {
+        let peak = nits.max_element();
+        if peak <= 0.0 {
+            return nits;
+        }
+
+        let max_lum = nits_to_pq(self.peak_nits);
+        let knee = 1.5 * max_lum - 0.5;
+        let e1 = nits_to_pq(peak);
+
+        if e1 <= knee {
+            return nits;
+        }
+
+        let t = (e1 - knee) / (1.0 - knee);
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let e2 = (2.0 * t3 - 3.0 * t2 + 1.0) * knee
+            + (t3 - 2.0 * t2 + t) * (1.0 - knee)
+            + (-2.0 * t3 + 3.0 * t2) * max_lum;
+
+        let mapped = pq_to_nits(e2).min(self.peak_nits);
+        nits * (mapped / peak)
+    }
+}
+
+pub struct HdrEncoder {
+    pub hdr: HdrSettings,
+    pub container: HdrContainer,
+    /// AVIF quality (1-100); ignored for PNG.
+    pub quality: u8,
+    /// Write an alpha channel (PNG) or alpha plane (AVIF).
+    pub alpha: bool,
+}
+
+impl SkyboxEncoder for HdrEncoder {
+    /// Writes a display-referred HDR image with BT.2020 primaries and PQ or HLG encoding.
+    ///
+    /// PNG output is 16-bit with `cICP` (9/16 for PQ, 9/18 for HLG); AVIF output is 10-bit 4:4:4
+    /// with the same code points in its `nclx` box. Alpha is written when `alpha` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::Encoding` if the PNG or AV1 encoder fails, and propagates I/O errors.
+    fn encode_to(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        match self.container {
+            HdrContainer::Png => self.encode_png(image, writer),
+            HdrContainer::Avif => self.encode_avif(image, writer),
+        }
+    }
+}
+
+impl HdrEncoder {
+    fn cicp(&self) -> [u8; 4] {
+        [9, self.hdr.transfer.h273_code(), 0, 1]
+    }
+
+    /// Normalized straight-alpha RGBA signals for every pixel.
+    fn signals(&self, image: &Rgba32FImage) -> Vec<[f32; 4]> {
+        image
+            .as_raw()
+            .par_chunks(4)
+            .map(|p| {
+                let [r, g, b, a] = unpremultiply([p[0], p[1], p[2], p[3]]);
+                let signal = self.hdr.map_color(Vec3::new(r, g, b));
+                [signal.x, signal.y, signal.z, a.clamp(0.0, 1.0)]
+            })
+            .collect()
+    }
+
+    fn encode_png(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        let channels = if self.alpha { 4 } else { 3 };
+        let data: Vec<u8> = self
+            .signals(image)
+            .into_par_iter()
+            .flat_map_iter(|px| {
+                px.into_iter()
+                    .take(channels)
+                    .flat_map(|v| quantize(v, 65535.0).to_be_bytes())
+            })
+            .collect();
+
+        let info = PngColorInfo {
+            srgb: false,
+            gamma: None,
+            chromaticities: None,
+            cicp: Some(self.cicp()),
+        };
+        let color_type = if channels == 4 {
+            png::ColorType::Rgba
+        } else {
+            png::ColorType::Rgb
+        };
+
+        write_png(
+            writer,
+            image.width(),
+            image.height(),
+            &data,
+            color_type,
+            png::BitDepth::Sixteen,
+            &info,
+        )
+    }
+
+    fn encode_avif(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        let signals = self.signals(image);
+        let quantizer = quality_to_quantizer(self.quality);
+
+        // Identity matrix coefficients: the planes hold G, B, R directly.
+        let color = encode_av1(
+            width,
+            height,
+            ChromaSampling::Cs444,
+            quantizer,
+            Some(ColorDescription {
+                color_primaries: rav1e::color::ColorPrimaries::BT2020,
+                transfer_characteristics: match self.hdr.transfer {
+                    HdrTransfer::Pq => rav1e::color::TransferCharacteristics::SMPTE2084,
+                    HdrTransfer::Hlg => rav1e::color::TransferCharacteristics::HLG,
+                },
+                matrix_coefficients: rav1e::color::MatrixCoefficients::Identity,
+            }),
+            |frame| {
+                for (plane, channel) in frame.planes.iter_mut().zip([1, 2, 0]) {
+                    fill_plane(plane, width, &signals, channel);
+                }
+            },
+        )?;
+
+        let alpha = if self.alpha {
+            Some(encode_av1(
+                width,
+                height,
+                ChromaSampling::Cs400,
+                quantizer,
+                None,
+                |frame| fill_plane(&mut frame.planes[0], width, &signals, 3),
+            )?)
+        } else {
+            None
+        };
+
+        let avif = Aviffy::new()
+            .matrix_coefficients(MatrixCoefficients::Rgb)
+            .color_primaries(ColorPrimaries::Bt2020)
+            .transfer_characteristics(match self.hdr.transfer {
+                HdrTransfer::Pq => TransferCharacteristics::Smpte2084,
+                HdrTransfer::Hlg => TransferCharacteristics::Hlg,
+            })
+            .full_color_range(true)
+            .to_vec(&color, alpha.as_deref(), width as u32, height as u32, 10);
+
+        writer.write_all(&avif)?;
+
+        Ok(())
+    }
+}
+
+fn quantize(v: f32, max: f32) -> u16 {
+    (v * max).round().clamp(0.0, max) as u16
+}
+
+fn fill_plane(plane: &mut Plane<u16>, width: usize, signals: &[[f32; 4]], channel: usize) {
+    let mut slice = plane.mut_slice(Default::default());
+    for (row, pixels) in slice.rows_iter_mut().zip(signals.chunks(width)) {
+        for (out, px) in row.iter_mut().zip(pixels) {
+            *out = quantize(px[channel], 1023.0);
+        }
+    }
+}
+
+/// Maps quality (1-100) to an AV1 quantizer the same way ravif does.
+fn quality_to_quantizer(quality: u8) -> u8 {
+    let q = quality.clamp(1, 100) as f32 / 100.0;
+    let x = if q >= 0.85 {
+        (1.0 - q) * 3.0
+    } else if q > 0.25 {
+        1.0 - 0.125 - q * 0.5
+    } else {
+        1.0 - q
+    };
+    (x * 255.0).round() as u8
+}
+
+fn encode_av1(
+    width: usize,
+    height: usize,
+    chroma_sampling: ChromaSampling,
+    quantizer: u8,
+    color_description: Option<ColorDescription>,
+    init: impl FnOnce(&mut Frame<u16>),
+) -> Result<Vec<u8>> {
+    let av1_error = |e: &dyn std::fmt::Display| Eq2cError::Encoding(format!("AV1: {}", e));
+
+    let mut config = EncoderConfig::with_speed_preset(AVIF_SPEED);
+    config.width = width;
+    config.height = height;
+    config.bit_depth = 10;
+    config.chroma_sampling = chroma_sampling;
+    config.pixel_range = PixelRange::Full;
+    config.color_description = color_description;
+    config.still_picture = true;
+    config.quantizer = quantizer as usize;
+    config.min_quantizer = quantizer;
+
+    let mut ctx: Context<u16> = Config::new()
+        .with_encoder_config(config)
+        .new_context()
+        .map_err(|e| av1_error(&e))?;
+
+    let mut frame = ctx.new_frame();
+    init(&mut frame);
+    ctx.send_frame(frame).map_err(|e| av1_error(&e))?;
+    ctx.flush();
+
+    let mut out = Vec::new();
+    loop {
+        match ctx.receive_packet() {
+            Ok(mut packet) => {
+                if packet.frame_type == FrameType::KEY {
+                    out.append(&mut packet.data);
+                }
+            }
+            Err(EncoderStatus::Encoded) => continue,
+            Err(EncoderStatus::LimitReached) => break,
+            Err(e) => return Err(av1_error(&e)),
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_white_and_peak() {
+        let hdr = HdrSettings::new(1.0, HdrTransfer::Pq, 1000.0, ColorSpace::Srgb);
+
+        // Diffuse white lands on the BT.2408 reference level.
+        let white = hdr.map_color(Vec3::ONE);
+        assert!((white.x - nits_to_pq(REFERENCE_WHITE_NITS)).abs() < 1e-3);
+
+        // A very bright sun is rolled off to the display peak, never beyond.
+        let sun = hdr.map_color(Vec3::splat(1000.0));
+        assert!(sun.x <= nits_to_pq(1000.0) + 1e-4);
+        assert!(sun.x > nits_to_pq(900.0));
+    }
+
+    #[test]
+    fn test_hlg_reference_white() {
+        let hdr = HdrSettings::new(1.0, HdrTransfer::Hlg, 1000.0, ColorSpace::Srgb);
+
+        // BT.2408: reference white is ~75% HLG on a 1000 nit display.
+        let white = hdr.map_color(Vec3::ONE);
+        assert!((white.x - 0.75).abs() < 0.01);
+    }
+}
@@ -0,0 +1,51 @@
+use super::ldr::{self, LdrSettings};
+use super::{ChromaSubsampling, SkyboxEncoder};
+use crate::error::{Eq2cError, Result};
+
+use image::Rgba32FImage;
+use jpeg_encoder::{ColorType, Encoder, SamplingFactor};
+use std::io::Write;
+
+pub struct JpegEncoder {
+    pub ldr: LdrSettings,
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+}
+
+impl SkyboxEncoder for JpegEncoder {
+    /// Tone maps the image through the shared LDR path and writes it as a baseline JPEG.
+    /// JPEG has no alpha channel, so transparent areas are composited over black.
+    ///
+    /// # Errors
+    ///
+    /// - Returns `Eq2cError::InvalidDimensions` if either side exceeds the 65535 pixel JPEG limit.
+    /// - Returns `Eq2cError::Encoding` if the JPEG encoder fails.
+    fn encode_to(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        let (width, height) = jpeg_dimensions(image)?;
+        let ldr_data = ldr::tonemap_to_rgb8(image, &self.ldr)?;
+
+        let mut encoder = Encoder::new(writer, self.quality.clamp(1, 100));
+
+        encoder.set_sampling_factor(match self.subsampling {
+            ChromaSubsampling::Yuv444 => SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => SamplingFactor::R_4_2_0,
+        });
+
+        encoder
+            .encode(&ldr_data, width, height, ColorType::Rgb)
+            .map_err(|e| Eq2cError::Encoding(e.to_string()))?;
+
+        Ok(())
+    }
+}
+
+fn jpeg_dimensions(image: &Rgba32FImage) -> Result<(u16, u16)> {
+    match (u16::try_from(image.width()), u16::try_from(image.height())) {
+        (Ok(w), Ok(h)) => Ok((w, h)),
+        _ => Err(Eq2cError::InvalidDimensions {
+            expected: "width and height <= 65535 for JPEG".to_string(),
+            found: format!("{}x{}", image.width(), image.height()),
+        }),
+    }
+}
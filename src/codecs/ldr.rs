@@ -0,0 +1,229 @@
+use crate::codecs::dither::{Dither, ErrorDiffuser, quantize_u8};
+use crate::codecs::tonemap::{self, ToneMapType};
+use crate::color::{LutSettings, TransferFunction};
+use crate::error::{Eq2cError, Result};
+use crate::image_utils::unpremultiply;
+
+use glam::Vec3;
+use image::Rgba32FImage;
+use rayon::prelude::*;
+
+/// How HDR scene values are turned into display code values by the LDR encoders.
+#[derive(Debug, Clone)]
+pub struct LdrSettings {
+    pub tonemap: ToneMapType,
+    pub exposure: f32,
+    /// Output transfer function applied after tone mapping.
+    pub transfer: TransferFunction,
+    /// Optional 3D LUT applied before quantization.
+    pub lut: Option<LutSettings>,
+    /// Dithering applied when the display values are rounded to 8 bits.
+    pub dither: Dither,
+}
+
+impl LdrSettings {
+    /// Applies exposure, the tone mapping curve, the transfer function and the LUT to one linear
+    /// colour, returning normalized display values.
+    ///
+    /// A LUT with a log shaper is fed the exposed scene values directly and takes the place of
+    /// the tonemap and transfer function.
+    pub fn map_color(&self, color: Vec3) -> Vec3 {
+        let hdr = color * self.exposure;
+
+        if let Some(lut) = self.lut.as_ref().filter(|lut| lut.shaper.is_some()) {
+            return lut.apply(hdr);
+        }
+
+        let mapped = tonemap::apply_tonemap(hdr, self.tonemap).max(Vec3::ZERO);
+        let encoded = Vec3::new(
+            self.transfer.from_linear(mapped.x),
+            self.transfer.from_linear(mapped.y),
+            self.transfer.from_linear(mapped.z),
+        );
+
+        match &self.lut {
+            Some(lut) => lut.apply(encoded),
+            None => encoded,
+        }
+    }
+}
+
+/// Converts a premultiplied HDR RGBA32F image into tightly packed 8-bit RGB data.
+///
+/// Applies exposure, the selected tone mapping curve and the output transfer function, in that
+/// order, then rounds with the configured dither. Transparent areas are composited over black.
+/// This is the shared LDR path used by every 8-bit encoder (PNG, JPEG, WebP, AVIF) when alpha is
+/// not written.
+///
+/// # Errors
+///
+/// Returns `Eq2cError::InvalidDimensions` if width*height or the buffer size overflows, or if the
+/// source buffer is smaller than expected.
+pub fn tonemap_to_rgb8(image: &Rgba32FImage, settings: &LdrSettings) -> Result<Vec<u8>> {
+    tonemap_to_8bit(image, settings, false)
+}
+
+/// Like [`tonemap_to_rgb8`], but keeps alpha and writes 8-bit straight (non-premultiplied) RGBA,
+/// which is what PNG, WebP and AVIF store. Alpha is rounded but never dithered.
+pub fn tonemap_to_rgba8(image: &Rgba32FImage, settings: &LdrSettings) -> Result<Vec<u8>> {
+    tonemap_to_8bit(image, settings, true)
+}
+
+fn tonemap_to_8bit(image: &Rgba32FImage, settings: &LdrSettings, alpha: bool) -> Result<Vec<u8>> {
+    let num_pixels = checked_pixel_count(image)?;
+    let width = image.width() as usize;
+    let src = &image.as_raw()[..num_pixels * 4];
+    let channels = if alpha { 4 } else { 3 };
+    let mut ldr_data = vec![0u8; num_pixels * channels];
+
+    // Display values and alpha of one source pixel.
+    let map = |p: &[f32]| {
+        let (rgb, a) = if alpha {
+            let [r, g, b, a] = unpremultiply([p[0], p[1], p[2], p[3]]);
+            (Vec3::new(r, g, b), a)
+        } else {
+            (Vec3::new(p[0], p[1], p[2]), 1.0)
+        };
+        (settings.map_color(rgb), a)
+    };
+
+    if width == 0 {
+        return Ok(ldr_data);
+    }
+
+    if settings.dither == Dither::ErrorDiffusion {
+        // The error travels to the next pixel and row, so rows are quantized in order; mapping the
+        // colours within a row still runs in parallel.
+        let mut diffuser = ErrorDiffuser::new(width);
+        let mut values = vec![[0.0; 3]; width];
+        let mut alphas = vec![0.0; width];
+
+        for (src_row, out_row) in src
+            .chunks_exact(width * 4)
+            .zip(ldr_data.chunks_exact_mut(width * channels))
+        {
+            values
+                .par_iter_mut()
+                .zip(alphas.par_iter_mut())
+                .zip(src_row.par_chunks_exact(4))
+                .for_each(|((value, a), p)| {
+                    let (rgb, alpha) = map(p);
+                    *value = rgb.to_array();
+                    *a = alpha;
+                });
+
+            diffuser.quantize_row(&values, |x, rgb| {
+                let out = &mut out_row[x * channels..(x + 1) * channels];
+                out[..3].copy_from_slice(&rgb);
+                if alpha {
+                    out[3] = quantize_u8(alphas[x]);
+                }
+            });
+        }
+
+        return Ok(ldr_data);
+    }
+
+    ldr_data
+        .par_chunks_mut(channels)
+        .zip(src.par_chunks(4))
+        .enumerate()
+        .for_each(|(i, (out_pixel, p))| {
+            let (x, y) = ((i % width) as u32, (i / width) as u32);
+            let (rgb, a) = map(p);
+
+            for (c, value) in rgb.to_array().into_iter().enumerate() {
+                let offset = settings.dither.offset(x, y, c as u32);
+                out_pixel[c] = quantize_u8(value + offset / 255.0);
+            }
+            if alpha {
+                out_pixel[3] = quantize_u8(a);
+            }
+        });
+
+    Ok(ldr_data)
+}
+
+fn checked_pixel_count(image: &Rgba32FImage) -> Result<usize> {
+    let width = image.width() as usize;
+    let height = image.height() as usize;
+
+    let num_pixels = width
+        .checked_mul(height)
+        .ok_or_else(|| Eq2cError::InvalidDimensions {
+            expected: "valid dimensions".to_string(),
+            found: "overflow".to_string(),
+        })?;
+
+    let expected = num_pixels
+        .checked_mul(4)
+        .ok_or_else(|| Eq2cError::InvalidDimensions {
+            expected: "valid buffer size".to_string(),
+            found: "overflow".to_string(),
+        })?;
+
+    let src = image.as_raw();
+    if src.len() < expected {
+        return Err(Eq2cError::InvalidDimensions {
+            expected: format!("buffer size >= {}", expected),
+            found: format!("{}", src.len()),
+        });
+    }
+
+    Ok(num_pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINEAR: LdrSettings = LdrSettings {
+        tonemap: ToneMapType::None,
+        exposure: 1.0,
+        transfer: TransferFunction::Linear,
+        lut: None,
+        dither: Dither::None,
+    };
+
+    #[test]
+    fn test_tonemap_to_rgb8_layout() {
+        let mut img = Rgba32FImage::new(2, 1);
+        img.put_pixel(0, 0, image::Rgba([0.0, 0.0, 0.0, 1.0]));
+        img.put_pixel(1, 0, image::Rgba([1.0, 1.0, 1.0, 1.0]));
+
+        let data = tonemap_to_rgb8(&img, &LINEAR).unwrap();
+
+        assert_eq!(data.len(), 6);
+        assert_eq!(&data[0..3], &[0, 0, 0]);
+        assert_eq!(&data[3..6], &[255, 255, 255]);
+    }
+
+    #[test]
+    fn test_rounds_to_nearest() {
+        let mut img = Rgba32FImage::new(2, 1);
+        img.put_pixel(
+            0,
+            0,
+            image::Rgba([0.6 / 255.0, 0.6 / 255.0, 0.6 / 255.0, 1.0]),
+        );
+        img.put_pixel(
+            1,
+            0,
+            image::Rgba([254.4 / 255.0, 254.4 / 255.0, 254.4 / 255.0, 1.0]),
+        );
+
+        let data = tonemap_to_rgb8(&img, &LINEAR).unwrap();
+
+        assert_eq!(data, vec![1, 1, 1, 254, 254, 254]);
+    }
+
+    #[test]
+    fn test_tonemap_to_rgba8_unpremultiplies() {
+        let mut img = Rgba32FImage::new(1, 1);
+        img.put_pixel(0, 0, image::Rgba([0.5, 0.5, 0.5, 0.5]));
+
+        let data = tonemap_to_rgba8(&img, &LINEAR).unwrap();
+
+        assert_eq!(data, vec![255, 255, 255, 128]);
+    }
+}
@@ -1,46 +1,250 @@
+use crate::color::{ColorSpace, HdrTransfer, LutSettings, TransferFunction};
 use crate::error::Result;
-use image::Rgb32FImage;
+use clap::ValueEnum;
+use image::Rgba32FImage;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
+pub mod avif;
+pub mod dither;
 pub mod exr;
+pub mod hdr;
+pub mod jpeg;
+pub mod ldr;
 pub mod png;
 pub mod tonemap;
+pub mod webp;
 
+pub use dither::Dither;
+pub use hdr::HdrSettings;
+pub use ldr::LdrSettings;
 pub use tonemap::ToneMapType;
 
+/// Encoder speed passed to rav1e for SDR and HDR AVIF (1 = slowest/best, 10 = fastest).
+pub(crate) const AVIF_SPEED: u8 = 4;
+
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Png,
     Exr,
+    Jpeg,
+    Webp,
+    Avif,
+}
+
+impl OutputFormat {
+    /// The usual file extension, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Exr => "exr",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::Webp => "webp",
+            OutputFormat::Avif => "avif",
+        }
+    }
+
+    pub const ALL: [OutputFormat; 5] = [
+        OutputFormat::Png,
+        OutputFormat::Exr,
+        OutputFormat::Jpeg,
+        OutputFormat::Webp,
+        OutputFormat::Avif,
+    ];
+
+    /// The format written to files with `extension` (case-insensitive), if any.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "jpeg" => Some(OutputFormat::Jpeg),
+            ext => OutputFormat::ALL
+                .into_iter()
+                .find(|format| format.extension() == ext),
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    /// Accepts the command line names (`png`, `exr`, `jpeg`, `webp`, `avif`) and `jpg`.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        OutputFormat::from_extension(s.trim()).ok_or_else(|| {
+            format!(
+                "unknown format '{}' (expected one of: png, exr, jpeg, webp, avif)",
+                s
+            )
+        })
+    }
+}
+
+/// Chroma subsampling used by the lossy encoders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ChromaSubsampling {
+    /// Full resolution chroma.
+    #[default]
+    #[value(name = "444")]
+    Yuv444,
+    /// Half horizontal chroma resolution.
+    #[value(name = "422")]
+    Yuv422,
+    /// Half horizontal and vertical chroma resolution.
+    #[value(name = "420")]
+    Yuv420,
+}
+
+/// Settings shared by every encoder; each encoder reads the fields that apply to it.
+#[derive(Debug, Clone)]
+pub struct EncoderSettings {
+    pub tonemap: ToneMapType,
+    pub exposure: f32,
+    /// Output transfer function of the LDR formats.
+    pub transfer: TransferFunction,
+    /// Quality for lossy formats, from 1 (smallest) to 100 (best).
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+    /// Colour space of the linear image handed to the encoder, recorded in PNG (`cHRM` / `cICP`)
+    /// and EXR (`chromaticities`). JPEG, WebP and AVIF files carry no primaries.
+    pub color_space: ColorSpace,
+    /// 3D LUT applied by the LDR encoders.
+    pub lut: Option<LutSettings>,
+    /// Display-referred HDR encoding for PNG and AVIF; `None` keeps the tone-mapped SDR path.
+    pub hdr: Option<HdrTransfer>,
+    /// Peak luminance of the HDR target display, in nits.
+    pub peak_nits: f32,
+    /// Dithering used when the LDR encoders round to 8 bits.
+    pub dither: Dither,
+    /// Write an alpha channel; set when the source is not fully opaque.
+    pub alpha: bool,
+}
+
+impl Default for EncoderSettings {
+    fn default() -> Self {
+        Self {
+            tonemap: ToneMapType::Aces,
+            exposure: 1.0,
+            transfer: TransferFunction::Srgb,
+            quality: 90,
+            subsampling: ChromaSubsampling::default(),
+            color_space: ColorSpace::default(),
+            lut: None,
+            hdr: None,
+            peak_nits: 1000.0,
+            dither: Dither::None,
+            alpha: false,
+        }
+    }
+}
+
+impl EncoderSettings {
+    /// The subset of the settings used by the shared LDR path.
+    pub fn ldr(&self) -> LdrSettings {
+        LdrSettings {
+            tonemap: self.tonemap,
+            exposure: self.exposure,
+            transfer: self.transfer,
+            lut: self.lut.clone(),
+            dither: self.dither,
+        }
+    }
+
+    /// The HDR settings, if display-referred HDR output was requested.
+    pub fn hdr(&self) -> Option<HdrSettings> {
+        self.hdr.map(|transfer| {
+            HdrSettings::new(self.exposure, transfer, self.peak_nits, self.color_space)
+        })
+    }
 }
 
 pub trait SkyboxEncoder {
-    fn encode(&self, image: &Rgb32FImage, output_path: &Path) -> Result<()>;
+    /// Encodes `image` into `writer`.
+    fn encode_to(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()>;
+
+    /// Encodes `image` into a new file at `output_path`.
+    fn encode(&self, image: &Rgba32FImage, output_path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(output_path)?);
+        self.encode_to(image, &mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Encodes `image` into a byte buffer.
+    fn encode_to_vec(&self, image: &Rgba32FImage) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        self.encode_to(image, &mut buffer)?;
+        Ok(buffer)
+    }
 }
 
 /// Selects and returns a boxed skybox encoder for the requested output format.
 ///
-/// The returned encoder implements `SkyboxEncoder`. For `OutputFormat::Png` the encoder
-/// is configured with the provided `tonemap` and `exposure`; for `OutputFormat::Exr`
-/// an EXR encoder is returned.
+/// The returned encoder implements `SkyboxEncoder`. LDR formats (PNG, JPEG, WebP, AVIF) are
+/// configured with the tonemap, exposure and transfer function from `settings`, and the lossy ones additionally
+/// with its quality and chroma subsampling; for `OutputFormat::Exr` an EXR encoder is returned.
+/// When `settings.hdr` is set, PNG and AVIF instead get a PQ/HLG encoder; other formats ignore it.
 ///
 /// # Examples
 ///
 /// ```ignore no_run
 /// use std::path::Path;
-/// use crate::codecs::{get_encoder, OutputFormat};
+/// use crate::codecs::{get_encoder, EncoderSettings, OutputFormat};
 /// use crate::tonemap::ToneMapType;
 ///
-/// let encoder = get_encoder(OutputFormat::Png, ToneMapType::Reinhard, 1.0);
+/// let settings = EncoderSettings { tonemap: ToneMapType::Reinhard, ..Default::default() };
+/// let encoder = get_encoder(OutputFormat::Png, &settings);
 /// // encoder.encode(&image, Path::new("out.png")).unwrap();
 /// ```
-pub fn get_encoder(
-    format: OutputFormat,
-    tonemap: ToneMapType,
-    exposure: f32,
-) -> Box<dyn SkyboxEncoder> {
+pub fn get_encoder(format: OutputFormat, settings: &EncoderSettings) -> Box<dyn SkyboxEncoder> {
+    let ldr = settings.ldr();
+    let quality = settings.quality;
+    let subsampling = settings.subsampling;
+
+    if let Some(hdr) = settings.hdr() {
+        match format {
+            OutputFormat::Png => {
+                return Box::new(hdr::HdrEncoder {
+                    hdr,
+                    container: hdr::HdrContainer::Png,
+                    quality,
+                    alpha: settings.alpha,
+                });
+            }
+            OutputFormat::Avif => {
+                return Box::new(hdr::HdrEncoder {
+                    hdr,
+                    container: hdr::HdrContainer::Avif,
+                    quality,
+                    alpha: settings.alpha,
+                });
+            }
+            _ => {}
+        }
+    }
+
     match format {
-        OutputFormat::Png => Box::new(png::PngEncoder { tonemap, exposure }),
-        OutputFormat::Exr => Box::new(exr::ExrEncoder),
+        OutputFormat::Png => Box::new(png::PngEncoder {
+            ldr,
+            color_space: settings.color_space,
+            alpha: settings.alpha,
+        }),
+        OutputFormat::Exr => Box::new(exr::ExrEncoder {
+            alpha: settings.alpha,
+            color_space: settings.color_space,
+        }),
+        OutputFormat::Jpeg => Box::new(jpeg::JpegEncoder {
+            ldr,
+            quality,
+            subsampling,
+        }),
+        OutputFormat::Webp => Box::new(webp::WebpEncoder {
+            ldr,
+            quality,
+            alpha: settings.alpha,
+        }),
+        OutputFormat::Avif => Box::new(avif::AvifEncoder {
+            ldr,
+            quality,
+            alpha: settings.alpha,
+        }),
     }
 }
@@ -1,112 +1,202 @@
 use super::SkyboxEncoder;
-use crate::codecs::tonemap::{self, ToneMapType};
+use super::ldr::{self, LdrSettings};
+use crate::color::{ColorSpace, TransferFunction};
 use crate::error::{Eq2cError, Result};
 
-use glam::Vec3;
-use image::{ImageBuffer, Rgb, Rgb32FImage};
-use rayon::prelude::*;
-use std::path::Path;
+use image::Rgba32FImage;
+use png::{BitDepth, ColorType, ScaledFloat, SourceChromaticities, SrgbRenderingIntent};
+use std::io::Write;
 
 pub struct PngEncoder {
-    pub tonemap: ToneMapType,
-    pub exposure: f32,
+    pub ldr: LdrSettings,
+    /// Primaries of the encoded data, recorded in `cHRM` / `cICP`.
+    pub color_space: ColorSpace,
+    /// Write 8-bit straight RGBA instead of RGB.
+    pub alpha: bool,
 }
 
 impl SkyboxEncoder for PngEncoder {
-    /// Encodes an HDR RGB32F image to an 8-bit PNG file using the encoder's exposure and tonemap settings.
+    /// Encodes an HDR RGBA32F image as an 8-bit PNG using the encoder's exposure, tonemap and
+    /// transfer function settings.
     ///
-    /// On success, writes the resulting PNG to `output_path` and returns `Ok(())`.
+    /// Alpha is written (as 8-bit straight RGBA) only when `alpha` is set. The transfer function is
+    /// recorded in the file, see [`write_png`].
     ///
     /// # Errors
     ///
     /// - Returns `Eq2cError::InvalidDimensions` if width*height or buffer size overflows, or if the source buffer is smaller than expected.
-    /// - Returns `Eq2cError::Image` with `ParameterErrorKind::DimensionMismatch` if an output image buffer cannot be created from the processed data.
-    /// - Propagates errors returned by the underlying image saving operation.
+    /// - Returns `Eq2cError::Encoding` if the PNG encoder rejects the data.
+    /// - Propagates I/O errors from the writer.
     ///
     /// # Examples
     ///
     /// ```ignore
-    /// use std::path::Path;
-    /// use image::Rgb32FImage;
+    /// use image::Rgba32FImage;
+    /// use crate::codecs::dither::Dither;
+    /// use crate::codecs::ldr::LdrSettings;
     /// use crate::codecs::png::PngEncoder;
+    /// use crate::color::{ColorSpace, TransferFunction};
     /// use crate::tonemap::ToneMapType;
     ///
     /// // Create a 1x1 HDR image with a single white pixel.
-    /// let mut img: Rgb32FImage = Rgb32FImage::new(1, 1);
-    /// img.put_pixel(0, 0, image::Rgb([1.0f32, 1.0f32, 1.0f32]));
+    /// let mut img: Rgba32FImage = Rgba32FImage::new(1, 1);
+    /// img.put_pixel(0, 0, image::Rgba([1.0f32, 1.0f32, 1.0f32, 1.0f32]));
     ///
-    /// let encoder = PngEncoder { tonemap: ToneMapType::Linear, exposure: 1.0 };
-    /// let out_path = Path::new("test_out.png");
+    /// let ldr = LdrSettings { tonemap: ToneMapType::None, exposure: 1.0, transfer: TransferFunction::Srgb, lut: None, dither: Dither::None };
+    /// let encoder = PngEncoder { ldr, color_space: ColorSpace::Srgb, alpha: false };
     ///
-    /// // Writes a PNG file; returns Ok(()) on success.
-    /// let result = encoder.encode(&img, out_path);
-    /// assert!(result.is_ok());
+    /// // Encodes into memory; `encode` writes a file instead.
+    /// let bytes = encoder.encode_to_vec(&img).unwrap();
+    /// assert!(bytes.starts_with(b"\x89PNG"));
     /// ```
-    fn encode(&self, image: &Rgb32FImage, output_path: &Path) -> Result<()> {
-        let width = image.width() as usize;
-        let height = image.height() as usize;
-
-        let num_pixels = width
-            .checked_mul(height)
-            .ok_or_else(|| Eq2cError::InvalidDimensions {
-                expected: "valid dimensions".to_string(),
-                found: "overflow".to_string(),
-            })?;
-
-        let src = image.as_raw();
-        let expected = num_pixels
-            .checked_mul(3)
-            .ok_or_else(|| Eq2cError::InvalidDimensions {
-                expected: "valid buffer size".to_string(),
-                found: "overflow".to_string(),
-            })?;
-
-        if src.len() < expected {
-            return Err(Eq2cError::InvalidDimensions {
-                expected: format!("buffer size >= {}", expected),
-                found: format!("{}", src.len()),
-            });
+    fn encode_to(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        let (width, height) = image.dimensions();
+
+        let (data, color_type) = if self.alpha {
+            (ldr::tonemap_to_rgba8(image, &self.ldr)?, ColorType::Rgba)
+        } else {
+            (ldr::tonemap_to_rgb8(image, &self.ldr)?, ColorType::Rgb)
+        };
+
+        let info = PngColorInfo::for_transfer(self.ldr.transfer).with_primaries(self.color_space);
+        write_png(
+            writer,
+            width,
+            height,
+            &data,
+            color_type,
+            BitDepth::Eight,
+            &info,
+        )
+    }
+}
+
+/// Colour metadata recorded ahead of the image data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PngColorInfo {
+    /// Write an `sRGB` chunk (with the matching `gAMA` fallback for older decoders).
+    pub srgb: bool,
+    /// Decoding gamma written to `gAMA`, as the encoding exponent (e.g. `1/2.2`).
+    pub gamma: Option<f32>,
+    /// White, red, green and blue chromaticities written to `cHRM`.
+    pub chromaticities: Option<[(f32, f32); 4]>,
+    /// `cICP` code points: primaries, transfer characteristics, matrix, full range flag.
+    pub cicp: Option<[u8; 4]>,
+}
+
+impl PngColorInfo {
+    /// Chunks describing `transfer` with BT.709 primaries.
+    ///
+    /// - sRGB: `sRGB` + `gAMA` fallback + `cICP`
+    /// - gamma 2.2 / 2.4: `gAMA` (+ `cICP` for 2.2, which has an H.273 code point)
+    /// - Rec.709 / BT.1886: `cICP`, plus `gAMA` 1/2.4 for BT.1886
+    /// - linear: `gAMA` 1.0 + `cICP`
+    pub fn for_transfer(transfer: TransferFunction) -> Self {
+        let gamma = match transfer {
+            TransferFunction::Linear => Some(1.0),
+            TransferFunction::Gamma22 => Some(1.0 / 2.2),
+            TransferFunction::Gamma24 | TransferFunction::Bt1886 => Some(1.0 / 2.4),
+            TransferFunction::Srgb | TransferFunction::Rec709 => None,
+        };
+
+        Self {
+            srgb: transfer == TransferFunction::Srgb,
+            gamma,
+            chromaticities: None,
+            cicp: transfer.h273_code().map(|code| [1, code, 0, 1]),
         }
+    }
+
+    /// Replaces the BT.709 primaries with those of `space`.
+    ///
+    /// `sRGB` only describes BT.709 data, so for any other space it is dropped in favour of its
+    /// `gAMA` fallback plus `cHRM`; `cICP` is kept only if both code points exist.
+    pub fn with_primaries(mut self, space: ColorSpace) -> Self {
+        if space == ColorSpace::Srgb {
+            return self;
+        }
+
+        if self.srgb {
+            self.srgb = false;
+            self.gamma = Some(1.0 / 2.2);
+        }
+
+        self.chromaticities = space.primaries().map(|[r, g, b, w]| [w, r, g, b]);
+        self.cicp = self
+            .cicp
+            .zip(space.h273_code())
+            .map(|([_, transfer, matrix, range], primaries)| [primaries, transfer, matrix, range]);
+
+        self
+    }
+}
+
+/// Writes raw 8 or 16-bit (big endian) samples as a PNG stream together with `info`.
+pub fn write_png(
+    writer: impl Write,
+    width: u32,
+    height: u32,
+    data: &[u8],
+    color_type: ColorType,
+    bit_depth: BitDepth,
+    info: &PngColorInfo,
+) -> Result<()> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(color_type);
+    encoder.set_depth(bit_depth);
+
+    if info.srgb {
+        encoder.set_source_srgb(SrgbRenderingIntent::Perceptual);
+        // The exact fallback value from the PNG spec; anything else is dropped next to `sRGB`.
+        encoder.set_source_gamma(ScaledFloat::from_scaled(45455));
+    } else if let Some(gamma) = info.gamma {
+        encoder.set_source_gamma(ScaledFloat::new(gamma));
+    }
+
+    if let Some([white, red, green, blue]) = info.chromaticities {
+        encoder.set_source_chromaticities(SourceChromaticities::new(white, red, green, blue));
+    }
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+
+    if let Some(cicp) = info.cicp {
+        writer
+            .write_chunk(png::chunk::cICP, &cicp)
+            .map_err(png_error)?;
+    }
+
+    writer.write_image_data(data).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+
+    Ok(())
+}
+
+fn png_error(e: png::EncodingError) -> Eq2cError {
+    match e {
+        png::EncodingError::IoError(e) => Eq2cError::Io(e),
+        other => Eq2cError::Encoding(format!("PNG: {}", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_info_for_transfer() {
+        let srgb = PngColorInfo::for_transfer(TransferFunction::Srgb);
+        assert!(srgb.srgb);
+        assert_eq!(srgb.cicp, Some([1, 13, 0, 1]));
+
+        let gamma24 = PngColorInfo::for_transfer(TransferFunction::Gamma24);
+        assert!(!gamma24.srgb);
+        assert_eq!(gamma24.cicp, None);
+        assert!((gamma24.gamma.unwrap() - 1.0 / 2.4).abs() < 1e-6);
 
-        let mut ldr_data = vec![0u8; num_pixels * 3];
-
-        let exposure = self.exposure;
-        let tonemap_type = self.tonemap;
-
-        ldr_data
-            .par_chunks_mut(3)
-            .enumerate()
-            .for_each(|(i, out_pixel)| {
-                let base = i * 3;
-                let r = src[base];
-                let g = src[base + 1];
-                let b = src[base + 2];
-
-                let hdr = Vec3::new(r, g, b) * exposure;
-                let mapped = tonemap::apply_tonemap(hdr, tonemap_type);
-
-                let final_color = Vec3::new(
-                    mapped.x.max(0.0).sqrt(),
-                    mapped.y.max(0.0).sqrt(),
-                    mapped.z.max(0.0).sqrt(),
-                );
-
-                out_pixel[0] = (final_color.x * 255.0).clamp(0.0, 255.0) as u8;
-                out_pixel[1] = (final_color.y * 255.0).clamp(0.0, 255.0) as u8;
-                out_pixel[2] = (final_color.z * 255.0).clamp(0.0, 255.0) as u8;
-            });
-
-        let out: ImageBuffer<Rgb<u8>, Vec<u8>> =
-            ImageBuffer::from_raw(width as u32, height as u32, ldr_data).ok_or_else(|| {
-                Eq2cError::Image(image::ImageError::Parameter(
-                    image::error::ParameterError::from_kind(
-                        image::error::ParameterErrorKind::DimensionMismatch,
-                    ),
-                ))
-            })?;
-
-        out.save(output_path)?;
-
-        Ok(())
+        let p3 = PngColorInfo::for_transfer(TransferFunction::Srgb)
+            .with_primaries(ColorSpace::DisplayP3);
+        assert!(!p3.srgb);
+        assert_eq!(p3.cicp, Some([12, 13, 0, 1]));
+        assert_eq!(p3.chromaticities.unwrap()[1], (0.680, 0.320));
     }
 }
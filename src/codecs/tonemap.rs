@@ -1,21 +1,379 @@
-use clap::ValueEnum;
-use glam::{Mat3, Vec3};
+use crate::color::ColorSpace;
+use crate::color::gamut::conversion_matrix;
 
-#[derive(Debug, Clone, Copy, ValueEnum)]
+use glam::{Mat3, Vec3};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+/// Tone mapping operator together with its curve parameters.
+///
+/// Parsed from `name[:key=value,...]`, e.g. `reinhard-extended:white=4` or
+/// `agx:look=punchy,contrast=1.2`; omitted parameters keep their defaults.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ToneMapType {
     Reinhard,
+    /// Reinhard with a white point: `white` maps exactly to 1.0.
+    ReinhardExtended {
+        white: f32,
+    },
+    /// Stephen Hill's fit of the ACES RRT + sRGB ODT.
     Aces,
+    /// The full ACES 1.x Reference Rendering Transform followed by the 100 nit dim-surround
+    /// sRGB / Rec.709 Output Device Transform, returning linear display values. Pair it with
+    /// `TransferFunction::Srgb` for the sRGB ODT or `Bt1886` for the Rec.709 one.
+    AcesRrt,
     Khronos,
-    Agx,
+    /// AgX base curve with an optional look and a contrast multiplier around middle grey.
+    Agx {
+        look: AgxLook,
+        contrast: f32,
+    },
+    /// John Hable's Uncharted 2 filmic curve.
+    Hable(HableParams),
+    /// Timothy Lottes' curve from "Advanced Techniques and Optimization of HDR Color Pipelines".
+    Lottes(LottesParams),
+    /// Hajime Uchimura's Gran Turismo curve.
+    Uchimura(UchimuraParams),
+    None,
+}
+
+/// Looks applied after the AgX base curve, as in Blender.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AgxLook {
+    #[default]
     None,
+    Punchy,
+    Golden,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HableParams {
+    /// Shoulder strength.
+    pub a: f32,
+    /// Linear strength.
+    pub b: f32,
+    /// Linear angle.
+    pub c: f32,
+    /// Toe strength.
+    pub d: f32,
+    /// Toe numerator.
+    pub e: f32,
+    /// Toe denominator.
+    pub f: f32,
+    /// Linear white point.
+    pub white: f32,
+}
+
+impl HableParams {
+    /// The curve before normalizing by the white point.
+    fn curve(&self, x: Vec3) -> Vec3 {
+        (x * (self.a * x + self.c * self.b) + self.d * self.e)
+            / (x * (self.a * x + self.b) + self.d * self.f)
+            - self.e / self.f
+    }
+}
+
+impl Default for HableParams {
+    fn default() -> Self {
+        Self {
+            a: 0.15,
+            b: 0.5,
+            c: 0.1,
+            d: 0.2,
+            e: 0.02,
+            f: 0.3,
+            white: 11.2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LottesParams {
+    pub contrast: f32,
+    pub shoulder: f32,
+    /// Scene value that maps to 1.0.
+    pub hdr_max: f32,
+    pub mid_in: f32,
+    pub mid_out: f32,
+}
+
+impl Default for LottesParams {
+    fn default() -> Self {
+        Self {
+            contrast: 1.6,
+            shoulder: 0.977,
+            hdr_max: 8.0,
+            mid_in: 0.18,
+            mid_out: 0.267,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UchimuraParams {
+    /// Maximum display brightness.
+    pub max: f32,
+    pub contrast: f32,
+    /// Start of the linear section.
+    pub linear_start: f32,
+    /// Length of the linear section.
+    pub linear_length: f32,
+    /// Black tightness.
+    pub black: f32,
+    pub pedestal: f32,
+}
+
+impl Default for UchimuraParams {
+    fn default() -> Self {
+        Self {
+            max: 1.0,
+            contrast: 1.0,
+            linear_start: 0.22,
+            linear_length: 0.4,
+            black: 1.33,
+            pedestal: 0.0,
+        }
+    }
+}
+
+impl ToneMapType {
+    /// Every operator with default parameters.
+    pub fn all() -> [ToneMapType; 10] {
+        [
+            ToneMapType::Reinhard,
+            ToneMapType::ReinhardExtended { white: 4.0 },
+            ToneMapType::Aces,
+            ToneMapType::AcesRrt,
+            ToneMapType::Khronos,
+            ToneMapType::Agx {
+                look: AgxLook::None,
+                contrast: 1.0,
+            },
+            ToneMapType::Hable(HableParams::default()),
+            ToneMapType::Lottes(LottesParams::default()),
+            ToneMapType::Uchimura(UchimuraParams::default()),
+            ToneMapType::None,
+        ]
+    }
+
+    /// Operator name as used on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapType::Reinhard => "reinhard",
+            ToneMapType::ReinhardExtended { .. } => "reinhard-extended",
+            ToneMapType::Aces => "aces",
+            ToneMapType::AcesRrt => "aces-rrt",
+            ToneMapType::Khronos => "khronos",
+            ToneMapType::Agx { .. } => "agx",
+            ToneMapType::Hable(_) => "hable",
+            ToneMapType::Lottes(_) => "lottes",
+            ToneMapType::Uchimura(_) => "uchimura",
+            ToneMapType::None => "none",
+        }
+    }
+
+    /// Checks that the curve parameters describe a usable curve: positive where they scale or
+    /// divide, and ordered where they mark points along the curve.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending parameter.
+    pub fn validate(&self) -> Result<(), String> {
+        let mut this = *self;
+        if let Some((key, _)) = this.params_mut().into_iter().find(|(key, value)| {
+            let may_be_zero = matches!(
+                (self, *key),
+                (ToneMapType::Uchimura(_), "pedestal") | (ToneMapType::Hable(_), "e")
+            );
+            !(**value > 0.0 || may_be_zero && **value == 0.0)
+        }) {
+            return Err(format!("'{}' needs a positive '{}'", self.name(), key));
+        }
+
+        match self {
+            ToneMapType::Hable(p) if p.curve(Vec3::splat(p.white)).x <= 0.0 => Err(format!(
+                "'hable' maps white={} to zero or below; raise 'white' or 'c'",
+                p.white
+            )),
+            ToneMapType::Lottes(p) if p.mid_in >= p.hdr_max => {
+                Err("'lottes' needs 'mid-in' below 'hdr-max'".to_string())
+            }
+            ToneMapType::Lottes(p) if p.mid_out >= 1.0 => {
+                Err("'lottes' needs 'mid-out' below 1".to_string())
+            }
+            ToneMapType::Uchimura(p) if p.linear_start >= p.max => {
+                Err("'uchimura' needs 'linear-start' below 'max'".to_string())
+            }
+            ToneMapType::Uchimura(p) if p.linear_length >= 1.0 => Err(
+                "'uchimura' needs 'linear-length' below 1, so the shoulder ends at 'max'"
+                    .to_string(),
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    /// Operator with default parameters for a name (or alias).
+    fn from_name(name: &str) -> Option<Self> {
+        let name = match name {
+            "uncharted2" => "hable",
+            "gt" => "uchimura",
+            "linear" => "none",
+            other => other,
+        };
+
+        ToneMapType::all().into_iter().find(|t| t.name() == name)
+    }
+
+    /// Numeric parameters by name, for parsing and printing.
+    fn params_mut(&mut self) -> Vec<(&'static str, &mut f32)> {
+        match self {
+            ToneMapType::ReinhardExtended { white } => vec![("white", white)],
+            ToneMapType::Agx { contrast, .. } => vec![("contrast", contrast)],
+            ToneMapType::Hable(p) => vec![
+                ("a", &mut p.a),
+                ("b", &mut p.b),
+                ("c", &mut p.c),
+                ("d", &mut p.d),
+                ("e", &mut p.e),
+                ("f", &mut p.f),
+                ("white", &mut p.white),
+            ],
+            ToneMapType::Lottes(p) => vec![
+                ("contrast", &mut p.contrast),
+                ("shoulder", &mut p.shoulder),
+                ("hdr-max", &mut p.hdr_max),
+                ("mid-in", &mut p.mid_in),
+                ("mid-out", &mut p.mid_out),
+            ],
+            ToneMapType::Uchimura(p) => vec![
+                ("max", &mut p.max),
+                ("contrast", &mut p.contrast),
+                ("linear-start", &mut p.linear_start),
+                ("linear-length", &mut p.linear_length),
+                ("black", &mut p.black),
+                ("pedestal", &mut p.pedestal),
+            ],
+            ToneMapType::Reinhard
+            | ToneMapType::Aces
+            | ToneMapType::AcesRrt
+            | ToneMapType::Khronos
+            | ToneMapType::None => Vec::new(),
+        }
+    }
+}
+
+impl FromStr for ToneMapType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        let name = name.trim().to_ascii_lowercase();
+
+        let mut tonemap = ToneMapType::from_name(&name).ok_or_else(|| {
+            let names: Vec<_> = ToneMapType::all().iter().map(|t| t.name()).collect();
+            format!(
+                "unknown tonemap '{}' (expected one of: {})",
+                name,
+                names.join(", ")
+            )
+        })?;
+
+        for param in params.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = param
+                .split_once('=')
+                .ok_or_else(|| format!("expected key=value, got '{}'", param))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            if let ToneMapType::Agx { look, .. } = &mut tonemap
+                && key == "look"
+            {
+                *look = match value {
+                    "none" => AgxLook::None,
+                    "punchy" => AgxLook::Punchy,
+                    "golden" => AgxLook::Golden,
+                    _ => {
+                        return Err(format!(
+                            "unknown AgX look '{}' (none, punchy, golden)",
+                            value
+                        ));
+                    }
+                };
+                continue;
+            }
+
+            let mut params = tonemap.params_mut();
+            let known: Vec<_> = params.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+            let slot = params.iter_mut().find(|(k, _)| *k == key).ok_or_else(|| {
+                format!(
+                    "'{}' has no parameter '{}' (parameters: {})",
+                    name,
+                    key,
+                    if known.is_empty() {
+                        "none".to_string()
+                    } else {
+                        known.join(", ")
+                    }
+                )
+            })?;
+
+            *slot.1 = value
+                .parse()
+                .ok()
+                .filter(|v: &f32| v.is_finite())
+                .ok_or_else(|| format!("invalid value '{}' for '{}'", value, key))?;
+        }
+
+        tonemap.validate()?;
+        Ok(tonemap)
+    }
+}
+
+impl fmt::Display for ToneMapType {
+    /// Prints the operator in the same `name:key=value` syntax `from_str` accepts, listing only
+    /// parameters that differ from the defaults.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut defaults = ToneMapType::from_name(self.name()).unwrap_or(*self);
+        let mut this = *self;
+
+        let mut changed: Vec<String> = this
+            .params_mut()
+            .into_iter()
+            .zip(defaults.params_mut())
+            .filter(|((_, v), (_, d))| **v != **d)
+            .map(|((k, v), _)| format!("{}={}", k, v))
+            .collect();
+
+        if let ToneMapType::Agx { look, .. } = self
+            && *look != AgxLook::None
+        {
+            let look = if *look == AgxLook::Punchy {
+                "punchy"
+            } else {
+                "golden"
+            };
+            changed.insert(0, format!("look={}", look));
+        }
+
+        if changed.is_empty() {
+            write!(f, "{}", self.name())
+        } else {
+            write!(f, "{}:{}", self.name(), changed.join(","))
+        }
+    }
 }
 
 pub fn apply_tonemap(color: Vec3, method: ToneMapType) -> Vec3 {
     match method {
         ToneMapType::Reinhard => reinhard(color),
+        ToneMapType::ReinhardExtended { white } => reinhard_extended(color, white),
         ToneMapType::Aces => aces_tonemap(color),
+        ToneMapType::AcesRrt => aces_rrt_odt(color),
         ToneMapType::Khronos => khronos_pbr_neutral(color),
-        ToneMapType::Agx => agx_tonemap(color),
+        ToneMapType::Agx { look, contrast } => agx_tonemap(color, look, contrast),
+        ToneMapType::Hable(params) => hable(color, &params),
+        ToneMapType::Lottes(params) => lottes(color, &params),
+        ToneMapType::Uchimura(params) => uchimura(color, &params),
         ToneMapType::None => color.clamp(Vec3::ZERO, Vec3::ONE),
     }
 }
@@ -26,6 +384,68 @@ fn reinhard(v: Vec3) -> Vec3 {
     v / (v + 1.0)
 }
 
+fn reinhard_extended(v: Vec3, white: f32) -> Vec3 {
+    let v = v.max(Vec3::ZERO);
+    (v * (1.0 + v / (white * white)) / (v + 1.0)).min(Vec3::ONE)
+}
+
+fn hable(color: Vec3, p: &HableParams) -> Vec3 {
+    let white = p.curve(Vec3::splat(p.white));
+
+    (p.curve(color.max(Vec3::ZERO)) / white).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+fn lottes(color: Vec3, p: &LottesParams) -> Vec3 {
+    let (a, d) = (p.contrast, p.shoulder);
+    let ad = a * d;
+
+    let denom = (p.hdr_max.powf(ad) - p.mid_in.powf(ad)) * p.mid_out;
+    let b = (-p.mid_in.powf(a) + p.hdr_max.powf(a) * p.mid_out) / denom;
+    let c = (p.hdr_max.powf(ad) * p.mid_in.powf(a)
+        - p.hdr_max.powf(a) * p.mid_in.powf(ad) * p.mid_out)
+        / denom;
+
+    let curve = |x: f32| {
+        let x = x.max(0.0);
+        x.powf(a) / (x.powf(ad) * b + c)
+    };
+
+    Vec3::new(curve(color.x), curve(color.y), curve(color.z)).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+fn uchimura(color: Vec3, p: &UchimuraParams) -> Vec3 {
+    let (max, a, m, l, c, b) = (
+        p.max,
+        p.contrast,
+        p.linear_start,
+        p.linear_length,
+        p.black,
+        p.pedestal,
+    );
+
+    let l0 = (max - m) * l / a;
+    let s0 = m + l0;
+    let s1 = m + a * l0;
+    let c2 = a * max / (max - s1);
+    let cp = -c2 / max;
+
+    let curve = |x: f32| {
+        let x = x.max(0.0);
+        let t = (x / m).clamp(0.0, 1.0);
+        let w0 = 1.0 - t * t * (3.0 - 2.0 * t);
+        let w2 = if x >= s0 { 1.0 } else { 0.0 };
+        let w1 = 1.0 - w0 - w2;
+
+        let toe = m * (x / m).powf(c) + b;
+        let shoulder = max - (max - s1) * (cp * (x - s0)).exp();
+        let linear = m + a * (x - m);
+
+        toe * w0 + linear * w1 + shoulder * w2
+    };
+
+    Vec3::new(curve(color.x), curve(color.y), curve(color.z)).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
 pub fn aces_tonemap(color: Vec3) -> Vec3 {
     let m1 = Mat3::from_cols_array(&[
         0.59719, 0.07600, 0.02840, 0.35458, 0.90834, 0.13383, 0.04823, 0.01566, 0.83777,
@@ -44,6 +464,275 @@ pub fn aces_tonemap(color: Vec3) -> Vec3 {
     result.clamp(Vec3::ZERO, Vec3::ONE)
 }
 
+// --- ACES 1.x RRT + ODT ---
+//
+// Transcribed from the aces-dev CTL (RRT.ctl, ODT.Academic.sRGB_100nits_dim.ctl and the
+// ACESlib Tonescales / RRT_Common / ODT_Common libraries).
+
+/// Input (Rec.709 / sRGB linear) to ACES2065-1, with a Bradford D65 -> ACES white adaptation.
+static REC709_TO_AP0: LazyLock<Mat3> =
+    LazyLock::new(|| conversion_matrix(ColorSpace::Srgb, ColorSpace::Aces2065));
+static AP0_TO_AP1: LazyLock<Mat3> =
+    LazyLock::new(|| conversion_matrix(ColorSpace::Aces2065, ColorSpace::AcesCg));
+static AP1_TO_AP0: LazyLock<Mat3> = LazyLock::new(|| AP0_TO_AP1.inverse());
+static AP1_TO_XYZ: LazyLock<Mat3> = LazyLock::new(|| ColorSpace::AcesCg.to_xyz());
+static XYZ_TO_AP1: LazyLock<Mat3> = LazyLock::new(|| AP1_TO_XYZ.inverse());
+/// AP1 to Rec.709 display primaries, including the ACES white -> D65 Bradford CAT.
+static AP1_TO_REC709: LazyLock<Mat3> =
+    LazyLock::new(|| conversion_matrix(ColorSpace::AcesCg, ColorSpace::Srgb));
+
+const RRT_GLOW_GAIN: f32 = 0.05;
+const RRT_GLOW_MID: f32 = 0.08;
+const RRT_RED_SCALE: f32 = 0.82;
+const RRT_RED_PIVOT: f32 = 0.03;
+const RRT_RED_HUE: f32 = 0.0;
+const RRT_RED_WIDTH: f32 = 135.0;
+const RRT_SAT_FACTOR: f32 = 0.96;
+const ODT_SAT_FACTOR: f32 = 0.93;
+const DIM_SURROUND_GAMMA: f32 = 0.9811;
+const CINEMA_WHITE: f32 = 48.0;
+const CINEMA_BLACK: f32 = 0.02;
+const HALF_MAX: f32 = 65504.0;
+
+/// Coefficients and end points of one of the CTL's segmented quadratic B-splines (in log10).
+struct SegmentedSpline<const N: usize> {
+    coefs_low: [f32; N],
+    coefs_high: [f32; N],
+    min_point: (f32, f32),
+    mid_point: (f32, f32),
+    max_point: (f32, f32),
+    slope_low: f32,
+    slope_high: f32,
+}
+
+impl<const N: usize> SegmentedSpline<N> {
+    /// Evaluates the spline at `x`; `floor` replaces non-positive input before the log.
+    fn eval(&self, x: f32, floor: f32) -> f32 {
+        let knots = N - 3;
+        let logx = (if x <= 0.0 { floor } else { x }).log10();
+
+        let (min_x, min_y) = (self.min_point.0.log10(), self.min_point.1.log10());
+        let mid_x = self.mid_point.0.log10();
+        let (max_x, max_y) = (self.max_point.0.log10(), self.max_point.1.log10());
+
+        let segment = |coefs: &[f32; N], start: f32, end: f32| {
+            let knot_coord = knots as f32 * (logx - start) / (end - start);
+            let j = (knot_coord as usize).min(knots - 1);
+            let t = knot_coord - j as f32;
+            let (c0, c1, c2) = (coefs[j], coefs[j + 1], coefs[j + 2]);
+
+            t * t * (0.5 * c0 - c1 + 0.5 * c2) + t * (c1 - c0) + 0.5 * (c0 + c1)
+        };
+
+        let logy = if logx <= min_x {
+            logx * self.slope_low + (min_y - self.slope_low * min_x)
+        } else if logx < mid_x {
+            segment(&self.coefs_low, min_x, mid_x)
+        } else if logx < max_x {
+            segment(&self.coefs_high, mid_x, max_x)
+        } else {
+            logx * self.slope_high + (max_y - self.slope_high * max_x)
+        };
+
+        10f32.powf(logy)
+    }
+}
+
+/// RRT tone scale (`segmented_spline_c5_fwd`).
+const RRT_SPLINE: SegmentedSpline<6> = SegmentedSpline {
+    coefs_low: [
+        -4.0,
+        -4.0,
+        -3.157_376_6,
+        -0.485_249_98,
+        1.847_732_5,
+        1.847_732_5,
+    ],
+    coefs_high: [-0.718_548_24, 2.081_030_7, 3.668_124, 4.0, 4.0, 4.0],
+    min_point: (0.18 * 3.051_757_8e-5, 0.0001),
+    mid_point: (0.18, 4.8),
+    max_point: (0.18 * 262_144.0, 10000.0),
+    slope_low: 0.0,
+    slope_high: 0.0,
+};
+
+fn segmented_spline_c5(x: f32) -> f32 {
+    RRT_SPLINE.eval(x, 2f32.powi(-14))
+}
+
+/// ODT tone scale for a 48 nit cinema white (`segmented_spline_c9_fwd`, `ODT_48nits`).
+static ODT_SPLINE: LazyLock<SegmentedSpline<10>> = LazyLock::new(|| SegmentedSpline {
+    coefs_low: [
+        -1.698_97,
+        -1.698_97,
+        -1.4779,
+        -1.2291,
+        -0.8648,
+        -0.448,
+        0.00518,
+        0.451_108_03,
+        0.911_374_4,
+        0.911_374_4,
+    ],
+    coefs_high: [
+        0.515_438_7,
+        0.847_043_8,
+        1.1358,
+        1.3802,
+        1.5197,
+        1.5985,
+        1.6467,
+        1.674_609_1,
+        1.687_873_3,
+        1.687_873_3,
+    ],
+    min_point: (segmented_spline_c5(0.18 * 2f32.powf(-6.5)), 0.02),
+    mid_point: (segmented_spline_c5(0.18), 4.8),
+    max_point: (segmented_spline_c5(0.18 * 2f32.powf(6.5)), 48.0),
+    slope_low: 0.0,
+    slope_high: 0.04,
+});
+
+fn segmented_spline_c9(x: f32) -> f32 {
+    ODT_SPLINE.eval(x, 1e-4)
+}
+
+fn rgb_to_saturation(rgb: Vec3) -> f32 {
+    const TINY: f32 = 1e-10;
+    let max = rgb.max_element();
+    (max.max(TINY) - rgb.min_element().max(TINY)) / max.max(1e-2)
+}
+
+fn rgb_to_yc(rgb: Vec3) -> f32 {
+    const YC_RADIUS_WEIGHT: f32 = 1.75;
+    let (r, g, b) = (rgb.x, rgb.y, rgb.z);
+    let chroma = (b * (b - g) + g * (g - r) + r * (r - b)).max(0.0).sqrt();
+    (b + g + r + YC_RADIUS_WEIGHT * chroma) / 3.0
+}
+
+fn rgb_to_hue(rgb: Vec3) -> f32 {
+    if rgb.x == rgb.y && rgb.y == rgb.z {
+        return 0.0;
+    }
+
+    let hue = (3f32.sqrt() * (rgb.y - rgb.z))
+        .atan2(2.0 * rgb.x - rgb.y - rgb.z)
+        .to_degrees();
+    if hue < 0.0 { hue + 360.0 } else { hue }
+}
+
+fn sigmoid_shaper(x: f32) -> f32 {
+    let t = (1.0 - (x / 2.0).abs()).max(0.0);
+    (1.0 + x.signum() * (1.0 - t * t)) / 2.0
+}
+
+fn glow_fwd(yc: f32, gain: f32, mid: f32) -> f32 {
+    if yc <= 2.0 / 3.0 * mid {
+        gain
+    } else if yc >= 2.0 * mid {
+        0.0
+    } else {
+        gain * (mid / yc - 0.5)
+    }
+}
+
+fn center_hue(hue: f32, center: f32) -> f32 {
+    let centered = hue - center;
+    if centered < -180.0 {
+        centered + 360.0
+    } else if centered > 180.0 {
+        centered - 360.0
+    } else {
+        centered
+    }
+}
+
+/// Uniform cubic B-spline bump of total width `width`, peaking at 1 in the middle.
+fn cubic_basis_shaper(x: f32, width: f32) -> f32 {
+    const M: [[f32; 4]; 4] = [
+        [-1.0 / 6.0, 3.0 / 6.0, -3.0 / 6.0, 1.0 / 6.0],
+        [3.0 / 6.0, -6.0 / 6.0, 3.0 / 6.0, 0.0],
+        [-3.0 / 6.0, 0.0, 3.0 / 6.0, 0.0],
+        [1.0 / 6.0, 4.0 / 6.0, 1.0 / 6.0, 0.0],
+    ];
+
+    let (start, end) = (-width / 2.0, width / 2.0);
+    if x <= start || x >= end {
+        return 0.0;
+    }
+
+    let knot_coord = (x - start) * 4.0 / width;
+    let j = (knot_coord as usize).min(3);
+    let t = knot_coord - j as f32;
+    let monomials = [t * t * t, t * t, t, 1.0];
+    let column = 3 - j;
+
+    let y: f32 = (0..4).map(|i| monomials[i] * M[i][column]).sum();
+    y * 1.5
+}
+
+/// Blends towards AP1 luminance: `factor` 1 keeps the colour, 0 makes it grey.
+fn ap1_saturation(rgb: Vec3, factor: f32) -> Vec3 {
+    let y = AP1_TO_XYZ.row(1).dot(rgb);
+    Vec3::splat(y).lerp(rgb, factor)
+}
+
+/// Reference Rendering Transform: ACES2065-1 scene values to OCES.
+fn rrt(mut aces: Vec3) -> Vec3 {
+    // Glow module.
+    let saturation = rgb_to_saturation(aces);
+    let yc = rgb_to_yc(aces);
+    let s = sigmoid_shaper((saturation - 0.4) / 0.2);
+    aces *= 1.0 + glow_fwd(yc, RRT_GLOW_GAIN * s, RRT_GLOW_MID);
+
+    // Red modifier.
+    let hue = center_hue(rgb_to_hue(aces), RRT_RED_HUE);
+    let hue_weight = cubic_basis_shaper(hue, RRT_RED_WIDTH);
+    aces.x += hue_weight * saturation * (RRT_RED_PIVOT - aces.x) * (1.0 - RRT_RED_SCALE);
+
+    // Rendering space, global desaturation and tone scale.
+    let aces = aces.max(Vec3::ZERO);
+    let rgb = (*AP0_TO_AP1 * aces).clamp(Vec3::ZERO, Vec3::splat(HALF_MAX));
+    let rgb = ap1_saturation(rgb, RRT_SAT_FACTOR);
+    let toned = Vec3::new(
+        segmented_spline_c5(rgb.x),
+        segmented_spline_c5(rgb.y),
+        segmented_spline_c5(rgb.z),
+    );
+
+    *AP1_TO_AP0 * toned
+}
+
+/// 100 nit dim-surround ODT with Rec.709 primaries, up to (but excluding) the display encoding.
+fn odt_rec709_dim(oces: Vec3) -> Vec3 {
+    let rgb = *AP0_TO_AP1 * oces;
+    let toned = Vec3::new(
+        segmented_spline_c9(rgb.x),
+        segmented_spline_c9(rgb.y),
+        segmented_spline_c9(rgb.z),
+    );
+    let linear = (toned - CINEMA_BLACK) / (CINEMA_WHITE - CINEMA_BLACK);
+
+    // Dark to dim surround: a gamma on luminance only, keeping the chromaticity.
+    let xyz = *AP1_TO_XYZ * linear;
+    let sum = xyz.x + xyz.y + xyz.z;
+    let sum = if sum == 0.0 { 1e-10 } else { sum };
+    let (x, y) = (xyz.x / sum, xyz.y / sum);
+    let luminance = xyz.y.clamp(0.0, HALF_MAX).powf(DIM_SURROUND_GAMMA);
+    let y = y.max(1e-10);
+    let xyz = Vec3::new(x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+
+    let linear = ap1_saturation(*XYZ_TO_AP1 * xyz, ODT_SAT_FACTOR);
+
+    (*AP1_TO_REC709 * linear).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+/// Full ACES 1.x RRT + sRGB / Rec.709 100 nit dim ODT for linear Rec.709 input, returning
+/// linear display values.
+pub fn aces_rrt_odt(color: Vec3) -> Vec3 {
+    odt_rec709_dim(rrt(*REC709_TO_AP0 * color))
+}
+
 // Khronos PBR Neutral Tone Mapper
 fn khronos_pbr_neutral(mut color: Vec3) -> Vec3 {
     const START_COMPRESSION: f32 = 0.8 - 0.04;
@@ -95,7 +784,7 @@ const AGX_OUTPUT_MAT: Mat3 = Mat3::from_cols_array(&[
     1.1510737,
 ]);
 
-fn agx_tonemap(color: Vec3) -> Vec3 {
+fn agx_tonemap(color: Vec3, look: AgxLook, contrast: f32) -> Vec3 {
     let val = AGX_INPUT_MAT * color;
 
     const MIN_EV: f32 = -12.47393;
@@ -108,7 +797,12 @@ fn agx_tonemap(color: Vec3) -> Vec3 {
     );
 
     let val_norm = (val_log - MIN_EV) / (MAX_EV - MIN_EV);
-    let result = agx_default_contrast_approx(val_norm);
+
+    // Contrast pivots around middle grey in the log domain, so 0.18 keeps its brightness.
+    let pivot = (0.18f32.log2() - MIN_EV) / (MAX_EV - MIN_EV);
+    let val_norm = ((val_norm - pivot) * contrast + pivot).clamp(Vec3::ZERO, Vec3::ONE);
+
+    let result = agx_look(agx_default_contrast_approx(val_norm), look);
     let linear_result = AGX_OUTPUT_MAT * result;
 
     linear_result.clamp(Vec3::ZERO, Vec3::ONE)
@@ -125,6 +819,20 @@ fn agx_default_contrast_approx(x: Vec3) -> Vec3 {
         - Vec3::splat(0.00232)
 }
 
+/// ASC CDL looks from Blender's AgX configuration.
+fn agx_look(value: Vec3, look: AgxLook) -> Vec3 {
+    let (slope, power, saturation) = match look {
+        AgxLook::None => return value,
+        AgxLook::Punchy => (Vec3::ONE, 1.35, 1.4),
+        AgxLook::Golden => (Vec3::new(1.0, 0.9, 0.5), 0.8, 1.3),
+    };
+
+    let graded = (value * slope).max(Vec3::ZERO).powf(power);
+    let luma = graded.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+
+    Vec3::splat(luma) + saturation * (graded - Vec3::splat(luma))
+}
+
 // TESTS
 
 #[cfg(test)]
@@ -149,15 +857,111 @@ mod tests {
 
     #[test]
     fn test_agx_sanity() {
-        let black = apply_tonemap(Vec3::ZERO, ToneMapType::Agx);
+        let agx = ToneMapType::from_str("agx").unwrap();
+        let black = apply_tonemap(Vec3::ZERO, agx);
         assert!(black.length() < 0.01);
 
         let val = Vec3::new(0.0, 0.5, 10.0);
-        let result = apply_tonemap(val, ToneMapType::Agx);
+        let result = apply_tonemap(val, agx);
 
         assert!(result.z > 0.8); // Should be bright
         assert!(result.z <= 1.0); // Should be clamped
 
         assert!(result.x > 0.0);
     }
+
+    #[test]
+    fn test_white_points_map_to_one() {
+        let extended = ToneMapType::from_str("reinhard-extended:white=4").unwrap();
+        assert!((apply_tonemap(Vec3::splat(4.0), extended).x - 1.0).abs() < 1e-5);
+
+        let hable = ToneMapType::from_str("uncharted2:white=6").unwrap();
+        assert!((apply_tonemap(Vec3::splat(6.0), hable).x - 1.0).abs() < 1e-5);
+
+        let lottes = ToneMapType::from_str("lottes").unwrap();
+        assert!((apply_tonemap(Vec3::splat(8.0), lottes).x - 1.0).abs() < 1e-4);
+        assert!((apply_tonemap(Vec3::splat(0.18), lottes).x - 0.267).abs() < 1e-4);
+
+        let gt = ToneMapType::from_str("gt").unwrap();
+        assert!((apply_tonemap(Vec3::splat(0.5), gt).x - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_aces_rrt_odt_reference_points() {
+        // From the CTL constants: scene grey 0.18 becomes 4.8 nits out of the 48 nit ODT range,
+        // i.e. ((4.8 - 0.02) / 47.98)^0.9811 after the dim surround compensation.
+        let grey = aces_rrt_odt(Vec3::splat(0.18));
+        assert!(
+            (grey - Vec3::splat(0.104_063_5)).abs().max_element() < 2e-4,
+            "{grey}"
+        );
+
+        // The ODT spline reaches cinema white 6.5 stops above grey and black 6.5 stops below.
+        let white = aces_rrt_odt(Vec3::splat(0.18 * 2f32.powf(6.5)));
+        assert!(white.min_element() > 0.999, "{white}");
+        let black = aces_rrt_odt(Vec3::splat(0.18 * 2f32.powf(-6.5)));
+        assert!(black.max_element() < 1e-4, "{black}");
+
+        // Saturated colours exercise the glow, red modifier and ODT gamut handling. Linear
+        // Rec.709 in, linear Rec.709 display values out, from a double-precision run of the CTL
+        // (RRT + ODT.Academic.Rec709_100nits_dim, before the display encoding).
+        let references = [
+            ([1.0, 0.0, 0.0], [0.610_212, 0.0, 0.001_896]),
+            ([4.0, 0.0, 0.0], [1.0, 0.168_826, 0.029_957]),
+            ([0.0, 0.0, 1.0], [0.0, 0.0, 0.644_266]),
+            ([0.0, 0.0, 4.0], [0.107_966, 0.004_642, 0.985_482]),
+            ([0.0, 1.0, 0.0], [0.088_896, 0.635_152, 0.024_262]),
+            ([2.0, 0.8, 0.1], [0.846_345, 0.572_726, 0.127_347]),
+        ];
+        for (input, expected) in references {
+            let output = aces_rrt_odt(Vec3::from_array(input));
+            assert!(
+                (output - Vec3::from_array(expected)).abs().max_element() < 1e-4,
+                "{input:?}: {output}"
+            );
+        }
+
+        // Monotonic along the neutral axis.
+        let ramp: Vec<f32> = (0..40)
+            .map(|i| aces_rrt_odt(Vec3::splat(0.001 * 1.3f32.powi(i))).y)
+            .collect();
+        assert!(ramp.windows(2).all(|w| w[1] >= w[0]));
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let agx = ToneMapType::from_str("agx:look=punchy, contrast=1.2").unwrap();
+        assert_eq!(
+            agx,
+            ToneMapType::Agx {
+                look: AgxLook::Punchy,
+                contrast: 1.2
+            }
+        );
+        assert_eq!(agx.to_string(), "agx:look=punchy,contrast=1.2");
+        assert_eq!(ToneMapType::Aces.to_string(), "aces");
+
+        assert!(ToneMapType::from_str("aces:white=2").is_err());
+        assert!(ToneMapType::from_str("hable:a=x").is_err());
+        assert!(ToneMapType::from_str("filmic").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_unusable_curves() {
+        for tonemap in [
+            "reinhard-extended:white=0",
+            "agx:contrast=-1",
+            "lottes:mid-in=8,hdr-max=8",
+            "uchimura:max=0.2,linear-start=0.22",
+            "uchimura:linear-length=1",
+            "hable:f=0",
+            // Linear angle below the toe ratio pulls the curve under zero near black.
+            "hable:c=0.01,white=0.01",
+        ] {
+            assert!(ToneMapType::from_str(tonemap).is_err(), "{tonemap}");
+        }
+
+        assert!(ToneMapType::from_str("uchimura:pedestal=0").is_ok());
+        assert!(ToneMapType::all().iter().all(|t| t.validate().is_ok()));
+    }
 }
@@ -0,0 +1,50 @@
+use super::SkyboxEncoder;
+use super::ldr::{self, LdrSettings};
+use crate::error::{Eq2cError, Result};
+
+use image::Rgba32FImage;
+use std::io::Write;
+use webp::{Encoder, WebPConfig};
+
+pub struct WebpEncoder {
+    pub ldr: LdrSettings,
+    pub quality: u8,
+    pub alpha: bool,
+}
+
+impl SkyboxEncoder for WebpEncoder {
+    /// Tone maps the image through the shared LDR path and writes it as a lossy WebP.
+    ///
+    /// Lossy WebP always stores chroma at 4:2:0; libwebp's "sharp YUV" conversion keeps chroma
+    /// edges noticeably crisper at that resolution. Alpha is written only when `alpha` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::Encoding` if libwebp rejects the configuration or the image.
+    fn encode_to(&self, image: &Rgba32FImage, writer: &mut dyn Write) -> Result<()> {
+        let ldr_data = if self.alpha {
+            ldr::tonemap_to_rgba8(image, &self.ldr)?
+        } else {
+            ldr::tonemap_to_rgb8(image, &self.ldr)?
+        };
+
+        let mut config = WebPConfig::new()
+            .map_err(|_| Eq2cError::Encoding("failed to initialize WebP config".to_string()))?;
+        config.quality = self.quality.clamp(1, 100) as f32;
+        config.use_sharp_yuv = 1;
+
+        let encoder = if self.alpha {
+            Encoder::from_rgba(&ldr_data, image.width(), image.height())
+        } else {
+            Encoder::from_rgb(&ldr_data, image.width(), image.height())
+        };
+
+        let memory = encoder
+            .encode_advanced(&config)
+            .map_err(|e| Eq2cError::Encoding(format!("WebP: {:?}", e)))?;
+
+        writer.write_all(&memory)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use clap::ValueEnum;
+use glam::{Mat3, Vec3};
+use image::Rgba32FImage;
+use rayon::prelude::*;
+
+/// CIE 1931 xy chromaticity.
+type Xy = (f32, f32);
+
+const D65: Xy = (0.3127, 0.3290);
+const ACES_WHITE: Xy = (0.32168, 0.33767);
+
+/// Bradford cone response matrix (rows), used for chromatic adaptation.
+const BRADFORD: Mat3 = Mat3::from_cols_array(&[
+    0.8951, -0.7502, 0.0389, 0.2664, 1.7135, -0.0685, -0.1614, 0.0367, 1.0296,
+]);
+
+/// RGB colour spaces (primaries and white point) that linear image data can be expressed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum ColorSpace {
+    /// ITU-R BT.709 / sRGB primaries, D65 white.
+    #[default]
+    #[value(name = "srgb", alias = "rec709")]
+    Srgb,
+    /// DCI-P3 primaries with a D65 white, as used by Apple displays.
+    #[value(name = "display-p3")]
+    DisplayP3,
+    /// ITU-R BT.2020 primaries, D65 white.
+    Rec2020,
+    /// ACES AP1 primaries (ACEScg working space), ACES white.
+    #[value(name = "acescg")]
+    AcesCg,
+    /// ACES AP0 primaries (ACES2065-1 interchange), ACES white.
+    #[value(name = "aces2065-1")]
+    Aces2065,
+    /// CIE 1931 XYZ, relative to a D65 white.
+    Xyz,
+}
+
+impl ColorSpace {
+    /// Red, green and blue chromaticities plus the white point, or `None` for XYZ.
+    pub fn primaries(self) -> Option<[Xy; 4]> {
+        match self {
+            ColorSpace::Srgb => Some([(0.64, 0.33), (0.30, 0.60), (0.15, 0.06), D65]),
+            ColorSpace::DisplayP3 => Some([(0.680, 0.320), (0.265, 0.690), (0.150, 0.060), D65]),
+            ColorSpace::Rec2020 => Some([(0.708, 0.292), (0.170, 0.797), (0.131, 0.046), D65]),
+            ColorSpace::AcesCg => {
+                Some([(0.713, 0.293), (0.165, 0.830), (0.128, 0.044), ACES_WHITE])
+            }
+            ColorSpace::Aces2065 => {
+                Some([(0.7347, 0.2653), (0.0, 1.0), (0.0001, -0.0770), ACES_WHITE])
+            }
+            ColorSpace::Xyz => None,
+        }
+    }
+
+    /// White point chromaticity.
+    pub fn white(self) -> Xy {
+        match self {
+            ColorSpace::AcesCg | ColorSpace::Aces2065 => ACES_WHITE,
+            _ => D65,
+        }
+    }
+
+    /// ITU-T H.273 `ColourPrimaries` code point, if the space has one.
+    pub fn h273_code(self) -> Option<u8> {
+        match self {
+            ColorSpace::Srgb => Some(1),
+            ColorSpace::Rec2020 => Some(9),
+            ColorSpace::DisplayP3 => Some(12),
+            ColorSpace::AcesCg | ColorSpace::Aces2065 | ColorSpace::Xyz => None,
+        }
+    }
+
+    /// Matrix taking linear RGB in this space to CIE XYZ (relative to its own white).
+    pub fn to_xyz(self) -> Mat3 {
+        let Some([r, g, b, w]) = self.primaries() else {
+            return Mat3::IDENTITY;
+        };
+
+        let rgb = Mat3::from_cols(xy_to_xyz(r), xy_to_xyz(g), xy_to_xyz(b));
+        let scale = rgb.inverse() * xy_to_xyz(w);
+
+        Mat3::from_cols(
+            rgb.x_axis * scale.x,
+            rgb.y_axis * scale.y,
+            rgb.z_axis * scale.z,
+        )
+    }
+
+    /// Relative luminance weights of the RGB channels.
+    pub fn luminance(self) -> Vec3 {
+        self.to_xyz().row(1)
+    }
+}
+
+/// What to do with colours that fall outside the target gamut (negative components) after a
+/// colour space conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum GamutMapping {
+    /// Keep negative values; float outputs store them, LDR outputs clip them when encoding.
+    #[default]
+    None,
+    /// Clamp negative components to zero.
+    Clip,
+    /// Blend towards the colour's own luminance until no component is negative, keeping
+    /// luminance and hue.
+    Desaturate,
+}
+
+/// Y-normalized XYZ of a chromaticity.
+fn xy_to_xyz((x, y): Xy) -> Vec3 {
+    Vec3::new(x / y, 1.0, (1.0 - x - y) / y)
+}
+
+/// Bradford chromatic adaptation from one white point to another, in XYZ.
+fn bradford(from: Xy, to: Xy) -> Mat3 {
+    if from == to {
+        return Mat3::IDENTITY;
+    }
+
+    let src = BRADFORD * xy_to_xyz(from);
+    let dst = BRADFORD * xy_to_xyz(to);
+
+    BRADFORD.inverse() * Mat3::from_diagonal(dst / src) * BRADFORD
+}
+
+/// Matrix converting linear RGB in `from` to linear RGB in `to`, with Bradford adaptation when
+/// the white points differ.
+pub fn conversion_matrix(from: ColorSpace, to: ColorSpace) -> Mat3 {
+    if from == to {
+        return Mat3::IDENTITY;
+    }
+
+    to.to_xyz().inverse() * bradford(from.white(), to.white()) * from.to_xyz()
+}
+
+/// Brings one colour back inside the gamut according to `mapping`. `luminance` are the luminance
+/// weights of the colour's space.
+pub fn map_gamut(color: Vec3, mapping: GamutMapping, luminance: Vec3) -> Vec3 {
+    match mapping {
+        GamutMapping::None => color,
+        GamutMapping::Clip => color.max(Vec3::ZERO),
+        GamutMapping::Desaturate => {
+            let min = color.min_element();
+            if min >= 0.0 {
+                return color;
+            }
+
+            let y = color.dot(luminance);
+            if y <= 0.0 {
+                return Vec3::ZERO;
+            }
+
+            // Smallest blend towards grey that lifts the lowest channel to zero.
+            let t = -min / (y - min);
+            (color + (Vec3::splat(y) - color) * t).max(Vec3::ZERO)
+        }
+    }
+}
+
+/// Converts a premultiplied linear image from `from` to `to` in place and applies `mapping`.
+///
+/// Does nothing when the spaces match and no gamut mapping is requested.
+pub fn convert_image(
+    image: &mut Rgba32FImage,
+    from: ColorSpace,
+    to: ColorSpace,
+    mapping: GamutMapping,
+) {
+    if from == to && mapping == GamutMapping::None {
+        return;
+    }
+
+    let matrix = conversion_matrix(from, to);
+    let luminance = to.luminance();
+
+    image.par_chunks_mut(4).for_each(|p| {
+        let rgb = map_gamut(matrix * Vec3::new(p[0], p[1], p[2]), mapping, luminance);
+        p[..3].copy_from_slice(&rgb.to_array());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).abs().max_element() < 1e-4, "{a} != {b}");
+    }
+
+    #[test]
+    fn test_reference_matrices() {
+        // BT.2087 Rec.709 -> Rec.2020.
+        let m = conversion_matrix(ColorSpace::Srgb, ColorSpace::Rec2020);
+        assert_close(m.row(0), Vec3::new(0.6274, 0.3293, 0.0433));
+
+        // ACES TB-2014-004: AP0 -> XYZ (ACES white, no adaptation).
+        let ap0 = ColorSpace::Aces2065.to_xyz();
+        assert_close(ap0.row(0), Vec3::new(0.952_552_4, 0.0, 0.000_093_68));
+
+        // White survives a Bradford-adapted round trip through ACEScg.
+        let there = conversion_matrix(ColorSpace::Srgb, ColorSpace::AcesCg);
+        let back = conversion_matrix(ColorSpace::AcesCg, ColorSpace::Srgb);
+        assert_close(there * Vec3::ONE, Vec3::ONE);
+        assert_close(
+            back * there * Vec3::new(0.2, 0.5, 0.9),
+            Vec3::new(0.2, 0.5, 0.9),
+        );
+    }
+
+    #[test]
+    fn test_desaturate_keeps_luminance() {
+        let lum = ColorSpace::Srgb.luminance();
+        let color = Vec3::new(1.2, -0.1, 0.3);
+
+        let mapped = map_gamut(color, GamutMapping::Desaturate, lum);
+
+        assert!(mapped.min_element() >= 0.0);
+        assert!((mapped.dot(lum) - color.dot(lum)).abs() < 1e-5);
+    }
+}
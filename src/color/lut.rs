@@ -0,0 +1,311 @@
+use crate::error::{Eq2cError, Result};
+
+use clap::ValueEnum;
+use glam::Vec3;
+use log::debug;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Interpolation used between the lattice points of a 3D LUT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LutInterpolation {
+    /// Blends the 8 surrounding lattice points.
+    Trilinear,
+    /// Blends the 4 points of the enclosing tetrahedron; keeps the neutral axis exact, as Resolve
+    /// does.
+    #[default]
+    Tetrahedral,
+}
+
+/// Log2 shaper that squeezes scene-linear HDR values into the LUT's 0-1 input range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogShaper {
+    /// Lowest encoded value, in stops relative to 18% grey.
+    pub min_stops: f32,
+    /// Highest encoded value, in stops relative to 18% grey.
+    pub max_stops: f32,
+}
+
+impl LogShaper {
+    /// Encodes a linear value to 0-1 (values outside the range are clamped).
+    pub fn encode(&self, v: f32) -> f32 {
+        let stops = (v.max(f32::MIN_POSITIVE) / 0.18).log2();
+        ((stops - self.min_stops) / (self.max_stops - self.min_stops)).clamp(0.0, 1.0)
+    }
+}
+
+/// A 3D lookup table read from an Adobe/Resolve `.cube` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lut3D {
+    pub title: Option<String>,
+    /// Number of lattice points per axis.
+    pub size: usize,
+    pub domain_min: Vec3,
+    pub domain_max: Vec3,
+    /// Output colours with red changing fastest, then green, then blue.
+    pub table: Vec<Vec3>,
+}
+
+impl Lut3D {
+    /// Reads and parses a `.cube` file.
+    ///
+    /// # Errors
+    ///
+    /// Propagates I/O errors and returns `Eq2cError::InvalidLut` if the file is malformed.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses the text of a `.cube` file.
+    ///
+    /// Supports `TITLE`, `LUT_3D_SIZE`, `DOMAIN_MIN` / `DOMAIN_MAX` and Resolve's
+    /// `LUT_3D_INPUT_RANGE`. Other keywords (upper case words such as `LUT_1D_INPUT_RANGE` or
+    /// `LUT_IN_VIDEO_RANGE`) are skipped; 1D LUTs are rejected.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::InvalidLut` with the offending line number.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = Vec3::ZERO;
+        let mut domain_max = Vec3::ONE;
+        let mut table = Vec::new();
+        let mut last_line = 0;
+
+        for (index, raw) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let line = raw.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            last_line = line_no;
+
+            let err = |message: String| Eq2cError::InvalidLut {
+                line: line_no,
+                message,
+            };
+
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+
+            match keyword {
+                "TITLE" => {
+                    let rest = line["TITLE".len()..].trim();
+                    title = Some(rest.trim_matches('"').to_string());
+                }
+                "LUT_3D_SIZE" => {
+                    let n = parse_numbers::<1>(words, line)
+                        .ok()
+                        .and_then(|[n]| (n.fract() == 0.0).then_some(n as usize))
+                        .filter(|n| (2..=256).contains(n))
+                        .ok_or_else(|| err(format!("invalid LUT_3D_SIZE '{}'", line)))?;
+                    size = Some(n);
+                    table.reserve(n * n * n);
+                }
+                "LUT_1D_SIZE" => {
+                    return Err(err("1D LUTs are not supported".to_string()));
+                }
+                "DOMAIN_MIN" => {
+                    domain_min = Vec3::from(parse_numbers::<3>(words, line).map_err(err)?)
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = Vec3::from(parse_numbers::<3>(words, line).map_err(err)?)
+                }
+                "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_numbers::<2>(words, line).map_err(err)?;
+                    domain_min = Vec3::splat(min);
+                    domain_max = Vec3::splat(max);
+                }
+                _ if is_keyword(keyword) => {
+                    debug!("Ignoring .cube keyword '{}' on line {}", keyword, line_no);
+                }
+                _ => {
+                    let Some(n) = size else {
+                        return Err(err("table data before LUT_3D_SIZE".to_string()));
+                    };
+                    if table.len() == n * n * n {
+                        return Err(err(format!("more than {} table entries", n * n * n)));
+                    }
+                    table.push(Vec3::from(
+                        parse_numbers::<3>(line.split_whitespace(), line).map_err(err)?,
+                    ));
+                }
+            }
+        }
+
+        let Some(size) = size else {
+            return Err(Eq2cError::InvalidLut {
+                line: last_line,
+                message: "missing LUT_3D_SIZE".to_string(),
+            });
+        };
+
+        if table.len() != size * size * size {
+            return Err(Eq2cError::InvalidLut {
+                line: last_line,
+                message: format!(
+                    "expected {} table entries, found {}",
+                    size * size * size,
+                    table.len()
+                ),
+            });
+        }
+
+        if domain_min.cmpge(domain_max).any() {
+            return Err(Eq2cError::InvalidLut {
+                line: last_line,
+                message: "DOMAIN_MIN must be below DOMAIN_MAX".to_string(),
+            });
+        }
+
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    fn at(&self, r: usize, g: usize, b: usize) -> Vec3 {
+        self.table[r + self.size * (g + self.size * b)]
+    }
+
+    /// Looks up one colour; inputs outside the domain are clamped to its edges.
+    pub fn sample(&self, color: Vec3, interpolation: LutInterpolation) -> Vec3 {
+        let n = self.size;
+        let scaled = ((color - self.domain_min) / (self.domain_max - self.domain_min))
+            .clamp(Vec3::ZERO, Vec3::ONE)
+            * (n - 1) as f32;
+
+        // NaN inputs fall through `clamp` unchanged; send them to the first lattice point.
+        let scaled = Vec3::select(scaled.is_nan_mask(), Vec3::ZERO, scaled);
+        let base = scaled.floor().min(Vec3::splat((n - 2) as f32));
+        let f = scaled - base;
+        let (r, g, b) = (base.x as usize, base.y as usize, base.z as usize);
+
+        let c = |dr: usize, dg: usize, db: usize| self.at(r + dr, g + dg, b + db);
+
+        match interpolation {
+            LutInterpolation::Trilinear => {
+                let c00 = c(0, 0, 0).lerp(c(1, 0, 0), f.x);
+                let c10 = c(0, 1, 0).lerp(c(1, 1, 0), f.x);
+                let c01 = c(0, 0, 1).lerp(c(1, 0, 1), f.x);
+                let c11 = c(0, 1, 1).lerp(c(1, 1, 1), f.x);
+                c00.lerp(c10, f.y).lerp(c01.lerp(c11, f.y), f.z)
+            }
+            LutInterpolation::Tetrahedral => {
+                let (c000, c111) = (c(0, 0, 0), c(1, 1, 1));
+                let (x, y, z) = (f.x, f.y, f.z);
+
+                if x >= y {
+                    if y >= z {
+                        c000 * (1.0 - x) + c(1, 0, 0) * (x - y) + c(1, 1, 0) * (y - z) + c111 * z
+                    } else if x >= z {
+                        c000 * (1.0 - x) + c(1, 0, 0) * (x - z) + c(1, 0, 1) * (z - y) + c111 * y
+                    } else {
+                        c000 * (1.0 - z) + c(0, 0, 1) * (z - x) + c(1, 0, 1) * (x - y) + c111 * y
+                    }
+                } else if z >= y {
+                    c000 * (1.0 - z) + c(0, 0, 1) * (z - y) + c(0, 1, 1) * (y - x) + c111 * x
+                } else if z >= x {
+                    c000 * (1.0 - y) + c(0, 1, 0) * (y - z) + c(0, 1, 1) * (z - x) + c111 * x
+                } else {
+                    c000 * (1.0 - y) + c(0, 1, 0) * (y - x) + c(1, 1, 0) * (x - z) + c111 * z
+                }
+            }
+        }
+    }
+}
+
+/// A loaded LUT plus how the LDR encoders apply it.
+#[derive(Debug, Clone)]
+pub struct LutSettings {
+    pub lut: Arc<Lut3D>,
+    pub interpolation: LutInterpolation,
+    /// With a shaper the LUT receives log-encoded scene values and replaces tone mapping and the
+    /// transfer function; without one it is applied to the display-encoded values.
+    pub shaper: Option<LogShaper>,
+}
+
+impl LutSettings {
+    /// Runs `color` through the shaper (if any) and the LUT.
+    pub fn apply(&self, color: Vec3) -> Vec3 {
+        let input = match self.shaper {
+            Some(shaper) => Vec3::new(
+                shaper.encode(color.x),
+                shaper.encode(color.y),
+                shaper.encode(color.z),
+            ),
+            None => color,
+        };
+
+        self.lut.sample(input, self.interpolation)
+    }
+}
+
+/// Whether `word` looks like a `.cube` keyword rather than the start of a table row.
+fn is_keyword(word: &str) -> bool {
+    word.starts_with(|c: char| c.is_ascii_uppercase())
+        && word
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Parses exactly `N` numbers following a keyword.
+fn parse_numbers<'a, const N: usize>(
+    words: impl Iterator<Item = &'a str>,
+    line: &str,
+) -> std::result::Result<[f32; N], String> {
+    let values: Vec<f32> = words
+        .map(str::parse)
+        .collect::<std::result::Result<_, _>>()
+        .map_err(|_| format!("invalid number in '{}'", line))?;
+
+    values
+        .try_into()
+        .map_err(|_| format!("expected {} values in '{}'", N, line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2-point LUT that inverts every channel.
+    const INVERT: &str = "TITLE \"invert\"\n# comment\nLUT_3D_SIZE 2\n\
+        1 1 1\n0 1 1\n1 0 1\n0 0 1\n1 1 0\n0 1 0\n1 0 0\n0 0 0\n";
+
+    #[test]
+    fn test_parse_and_sample() {
+        let lut = Lut3D::parse(INVERT).unwrap();
+        assert_eq!(lut.title.as_deref(), Some("invert"));
+
+        let color = Vec3::new(0.25, 0.5, 0.9);
+        for interpolation in [LutInterpolation::Trilinear, LutInterpolation::Tetrahedral] {
+            let out = lut.sample(color, interpolation);
+            assert!((out - (Vec3::ONE - color)).abs().max_element() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_unknown_keywords_are_skipped() {
+        let resolve = format!("LUT_1D_INPUT_RANGE 0.0 1.0\nLUT_IN_VIDEO_RANGE\n{}", INVERT);
+        assert_eq!(Lut3D::parse(&resolve).unwrap().size, 2);
+    }
+
+    #[test]
+    fn test_malformed_lut_reports_line() {
+        let truncated = "LUT_3D_SIZE 2\n0 0 0\n1 0 0\n";
+        let bad_number = "LUT_3D_SIZE 2\n0 0 zero\n";
+
+        assert!(matches!(
+            Lut3D::parse(truncated),
+            Err(Eq2cError::InvalidLut { line: 3, .. })
+        ));
+        assert!(matches!(
+            Lut3D::parse(bad_number),
+            Err(Eq2cError::InvalidLut { line: 2, .. })
+        ));
+    }
+}
@@ -0,0 +1,7 @@
+pub mod gamut;
+pub mod lut;
+pub mod transfer;
+
+pub use gamut::{ColorSpace, GamutMapping};
+pub use lut::{LogShaper, Lut3D, LutInterpolation, LutSettings};
+pub use transfer::{HdrTransfer, TransferFunction};
@@ -0,0 +1,179 @@
+use clap::ValueEnum;
+
+/// Transfer function (a.k.a. "gamma") relating encoded values to linear light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TransferFunction {
+    /// Values are already linear.
+    Linear,
+    /// IEC 61966-2-1 piecewise sRGB curve.
+    Srgb,
+    /// Pure power 2.2.
+    #[value(name = "gamma22")]
+    Gamma22,
+    /// Pure power 2.4.
+    #[value(name = "gamma24")]
+    Gamma24,
+    /// ITU-R BT.709 camera OETF (linear toe, 0.45 power).
+    Rec709,
+    /// Inverse of the ITU-R BT.1886 display EOTF (2.4 power, zero black level).
+    Bt1886,
+}
+
+impl TransferFunction {
+    /// Decodes a normalized encoded value to linear light.
+    pub fn to_linear(self, v: f32) -> f32 {
+        match self {
+            TransferFunction::Linear => v,
+            TransferFunction::Srgb => srgb_to_linear(v),
+            TransferFunction::Gamma22 => v.max(0.0).powf(2.2),
+            TransferFunction::Gamma24 | TransferFunction::Bt1886 => v.max(0.0).powf(2.4),
+            TransferFunction::Rec709 => rec709_to_linear(v),
+        }
+    }
+
+    /// Encodes a linear value with this transfer function.
+    pub fn from_linear(self, v: f32) -> f32 {
+        match self {
+            TransferFunction::Linear => v,
+            TransferFunction::Srgb => linear_to_srgb(v),
+            TransferFunction::Gamma22 => v.max(0.0).powf(1.0 / 2.2),
+            TransferFunction::Gamma24 | TransferFunction::Bt1886 => v.max(0.0).powf(1.0 / 2.4),
+            TransferFunction::Rec709 => linear_to_rec709(v),
+        }
+    }
+
+    /// ITU-T H.273 `TransferCharacteristics` code point, if the curve has one.
+    pub fn h273_code(self) -> Option<u8> {
+        match self {
+            TransferFunction::Linear => Some(8),
+            TransferFunction::Srgb => Some(13),
+            TransferFunction::Gamma22 => Some(4),
+            TransferFunction::Gamma24 => None,
+            // BT.1886 displays are signalled with the BT.709 code point.
+            TransferFunction::Rec709 | TransferFunction::Bt1886 => Some(1),
+        }
+    }
+}
+
+/// Display-referred HDR transfer functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HdrTransfer {
+    /// SMPTE ST 2084 perceptual quantizer (HDR10), absolute luminance up to 10000 nits.
+    Pq,
+    /// ARIB STD-B67 / BT.2100 hybrid log-gamma, relative to the display peak.
+    Hlg,
+}
+
+impl HdrTransfer {
+    /// ITU-T H.273 `TransferCharacteristics` code point.
+    pub fn h273_code(self) -> u8 {
+        match self {
+            HdrTransfer::Pq => 16,
+            HdrTransfer::Hlg => 18,
+        }
+    }
+}
+
+/// PQ inverse EOTF: absolute luminance in nits to a normalized signal.
+pub fn nits_to_pq(nits: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let y = (nits / 10000.0).clamp(0.0, 1.0).powf(M1);
+    ((C1 + C2 * y) / (1.0 + C3 * y)).powf(M2)
+}
+
+/// PQ EOTF: normalized signal to absolute luminance in nits.
+pub fn pq_to_nits(v: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let p = v.clamp(0.0, 1.0).powf(1.0 / M2);
+    10000.0 * ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1)
+}
+
+/// HLG OETF: normalized scene light (`0..=1`) to a normalized signal.
+pub fn hlg_oetf(e: f32) -> f32 {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 1.0 - 4.0 * A;
+    const C: f32 = 0.559_910_7;
+
+    let e = e.max(0.0);
+    if e <= 1.0 / 12.0 {
+        (3.0 * e).sqrt()
+    } else {
+        A * (12.0 * e - B).ln() + C
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f32) -> f32 {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn rec709_to_linear(v: f32) -> f32 {
+    if v < 0.081 {
+        v / 4.5
+    } else {
+        ((v + 0.099) / 1.099).powf(1.0 / 0.45)
+    }
+}
+
+pub fn linear_to_rec709(v: f32) -> f32 {
+    if v < 0.018 {
+        v * 4.5
+    } else {
+        1.099 * v.powf(0.45) - 0.099
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_round_trip() {
+        for tf in TransferFunction::value_variants() {
+            for i in 0..=20 {
+                let v = i as f32 / 20.0;
+                let back = tf.from_linear(tf.to_linear(v));
+                assert!((back - v).abs() < 1e-5, "{:?} failed at {}", tf, v);
+            }
+        }
+    }
+
+    #[test]
+    fn test_srgb_reference_values() {
+        // 50% sRGB grey is ~21.4% linear reflectance.
+        assert!((srgb_to_linear(0.5) - 0.21404).abs() < 1e-4);
+        assert!((linear_to_srgb(0.18) - 0.46135).abs() < 1e-4);
+        assert!((linear_to_rec709(0.18) - 0.40901).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_hdr_reference_values() {
+        // BT.2408 reference white (203 nits) sits at ~58% PQ and 75% HLG.
+        assert!((nits_to_pq(203.0) - 0.5807).abs() < 1e-3);
+        assert!((nits_to_pq(10000.0) - 1.0).abs() < 1e-5);
+        assert!((pq_to_nits(nits_to_pq(1000.0)) - 1000.0).abs() < 0.5);
+        assert!((hlg_oetf(1.0) - 1.0).abs() < 1e-5);
+        assert!((hlg_oetf(1.0 / 12.0) - 0.5).abs() < 1e-5);
+    }
+}
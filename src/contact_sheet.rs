@@ -0,0 +1,260 @@
+use crate::codecs::ToneMapType;
+use crate::codecs::ldr::{self, LdrSettings};
+use crate::error::Result;
+
+use clap::ValueEnum;
+use image::imageops::{self, FilterType};
+use image::{Rgb, RgbImage, Rgba32FImage};
+
+/// Gap between tiles and around the sheet, in pixels.
+const GAP: u32 = 4;
+/// Integer scale of the 5x7 label font; if any label is too long for its tile, the whole sheet
+/// drops to scale 1.
+const FONT_SCALE: u32 = 2;
+const LABEL_HEIGHT: u32 = 7 * FONT_SCALE + 2 * GAP;
+const BACKGROUND: Rgb<u8> = Rgb([16, 16, 16]);
+const LABEL_BACKGROUND: Rgb<u8> = Rgb([40, 40, 40]);
+const LABEL_COLOR: Rgb<u8> = Rgb([235, 235, 235]);
+
+/// What each tile of a contact sheet shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SheetSource {
+    /// The selected cubemap layout (the cross for `separate`).
+    #[default]
+    Layout,
+    /// The equirectangular input itself.
+    Equirect,
+}
+
+/// A labeled grid of the same image rendered through every [`ToneMapType`], optionally at several
+/// exposures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactSheet {
+    pub source: SheetSource,
+    /// Exposure offsets in stops; with more than one, each tonemap gets a row with a column per
+    /// offset.
+    pub bracket: Vec<f32>,
+    /// Width of one tile in pixels.
+    pub tile_width: u32,
+}
+
+impl Default for ContactSheet {
+    fn default() -> Self {
+        Self {
+            source: SheetSource::Layout,
+            bracket: vec![0.0],
+            tile_width: 384,
+        }
+    }
+}
+
+impl ContactSheet {
+    /// Downscales `image` to the tile size and renders it through every tonemap (and bracket
+    /// offset) with the rest of `settings`, returning the labeled sheet.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::InvalidDimensions` if a tile cannot be converted.
+    pub fn render(&self, image: &Rgba32FImage, settings: &LdrSettings) -> Result<RgbImage> {
+        let tile_width = self.tile_width.max(1);
+        let tile_height = ((tile_width as u64 * image.height() as u64)
+            / image.width().max(1) as u64)
+            .max(1) as u32;
+        let tile = imageops::resize(image, tile_width, tile_height, FilterType::Triangle);
+
+        let bracket: &[f32] = if self.bracket.is_empty() {
+            &[0.0]
+        } else {
+            &self.bracket
+        };
+
+        let mut cells = Vec::new();
+        for tonemap in ToneMapType::all() {
+            for &ev in bracket {
+                let label = if bracket.len() > 1 {
+                    format!("{} {:+} EV", tonemap, ev)
+                } else {
+                    tonemap.to_string()
+                };
+                cells.push((tonemap, ev, label));
+            }
+        }
+
+        // One row per tonemap when bracketing, otherwise a roughly square grid.
+        let columns = if bracket.len() > 1 {
+            bracket.len()
+        } else {
+            (cells.len() as f32).sqrt().ceil() as usize
+        };
+        let rows = cells.len().div_ceil(columns);
+
+        let fits =
+            |(_, _, label): &(_, _, String)| text_width(label, FONT_SCALE) + 2 * GAP <= tile_width;
+        let scale = if cells.iter().all(fits) {
+            FONT_SCALE
+        } else {
+            1
+        };
+
+        let cell_width = tile_width + GAP;
+        let cell_height = tile_height + LABEL_HEIGHT + GAP;
+        let mut sheet = RgbImage::from_pixel(
+            GAP + columns as u32 * cell_width,
+            GAP + rows as u32 * cell_height,
+            BACKGROUND,
+        );
+
+        for (index, (tonemap, ev, label)) in cells.into_iter().enumerate() {
+            let x = GAP + (index % columns) as u32 * cell_width;
+            let y = GAP + (index / columns) as u32 * cell_height;
+
+            let tile_settings = LdrSettings {
+                tonemap,
+                exposure: settings.exposure * ev.exp2(),
+                ..settings.clone()
+            };
+            let data = ldr::tonemap_to_rgb8(&tile, &tile_settings)?;
+            let rendered = RgbImage::from_raw(tile_width, tile_height, data)
+                .expect("tonemap_to_rgb8 returns one RGB triple per pixel");
+
+            for ly in 0..LABEL_HEIGHT {
+                for lx in 0..tile_width {
+                    sheet.put_pixel(x + lx, y + ly, LABEL_BACKGROUND);
+                }
+            }
+            // Centre the smaller font vertically in the label bar.
+            let text_y = y + GAP + (FONT_SCALE - scale) * 7 / 2;
+            draw_text(&mut sheet, x + GAP, text_y, x + tile_width, &label, scale);
+            imageops::replace(&mut sheet, &rendered, x as i64, (y + LABEL_HEIGHT) as i64);
+        }
+
+        Ok(sheet)
+    }
+}
+
+fn text_width(text: &str, scale: u32) -> u32 {
+    (text.chars().count() as u32 * 6).saturating_sub(1) * scale
+}
+
+/// Draws `text` (upper-cased) with its top-left corner at (`x`, `y`), clipped at `max_x`.
+fn draw_text(image: &mut RgbImage, x: u32, y: u32, max_x: u32, text: &str, scale: u32) {
+    let advance = 6 * scale;
+
+    for (i, c) in text.chars().enumerate() {
+        let gx = x + i as u32 * advance;
+        if gx + 5 * scale > max_x {
+            break;
+        }
+
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0b10000 >> col) == 0 {
+                    continue;
+                }
+                for sy in 0..scale {
+                    for sx in 0..scale {
+                        image.put_pixel(
+                            gx + col * scale + sx,
+                            y + row as u32 * scale + sy,
+                            LABEL_COLOR,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 5x7 bitmap of a character, one row per byte with the leftmost pixel in bit 4.
+fn glyph(c: char) -> [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    GLYPHS
+        .iter()
+        .find(|(g, _)| *g == c)
+        .map_or(UNKNOWN_GLYPH, |(_, bits)| *bits)
+}
+
+const UNKNOWN_GLYPH: [u8; 7] = [
+    0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+];
+
+#[rustfmt::skip]
+const GLYPHS: &[(char, [u8; 7])] = &[
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('B', [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110]),
+    ('E', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111]),
+    ('F', [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111]),
+    ('H', [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('K', [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001]),
+    ('N', [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001]),
+    ('S', [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010]),
+    ('X', [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001]),
+    ('Y', [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100]),
+    ('Z', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111]),
+    ('0', [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110]),
+    ('2', [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111]),
+    ('3', [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110]),
+    ('6', [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000]),
+    ('8', [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100]),
+    ('+', [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000]),
+    (':', [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::EncoderSettings;
+
+    #[test]
+    fn test_sheet_grid_size() {
+        let img = Rgba32FImage::from_pixel(64, 32, image::Rgba([0.5, 0.5, 0.5, 1.0]));
+        let sheet = ContactSheet {
+            bracket: vec![-1.0, 0.0, 1.0],
+            tile_width: 32,
+            ..Default::default()
+        };
+
+        let out = sheet
+            .render(&img, &EncoderSettings::default().ldr())
+            .unwrap();
+
+        // 3 columns of brackets, one row per tonemap.
+        let rows = ToneMapType::all().len() as u32;
+        assert_eq!(out.width(), GAP + 3 * (32 + GAP));
+        assert_eq!(out.height(), GAP + rows * (16 + LABEL_HEIGHT + GAP));
+    }
+
+    #[test]
+    fn test_labels_have_glyphs() {
+        for tonemap in ToneMapType::all() {
+            for c in format!("{} -1.5 EV", tonemap).chars() {
+                assert_ne!(glyph(c), UNKNOWN_GLYPH, "{c:?} in {tonemap}");
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::sanitize::InvalidPixels;
+
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,17 +10,46 @@ pub enum Eq2cError {
     #[error("Image processing error: {0}")]
     Image(#[from] image::ImageError),
 
-    // #[error("EXR error: {0}")]
-    // Exr(#[from] exr::error::Error),
+    #[error("EXR error: {0}")]
+    Exr(#[from] exr::error::Error),
+
+    #[error("EXR layer '{name}' not found (available layers: {available})")]
+    LayerNotFound { name: String, available: String },
+
+    #[error("EXR channel '{name}' not found in layer '{layer}' (available channels: {available})")]
+    ChannelNotFound {
+        name: String,
+        layer: String,
+        available: String,
+    },
+
     #[error("Unsupported file format: {0}")]
     UnsupportedFormat(String),
 
     #[error("Invalid layout dimensions: expected {expected}, got {found}")]
     InvalidDimensions { expected: String, found: String },
 
+    #[error("Decoding error: {0}")]
+    Decoding(String),
+
+    #[error("Encoding error: {0}")]
+    Encoding(String),
+
+    #[error("Invalid .cube LUT (line {line}): {message}")]
+    InvalidLut { line: usize, message: String },
+
+    #[error("Invalid recipe: {0}")]
+    InvalidRecipe(String),
+
+    #[error("Input contains invalid values: {0}")]
+    InvalidValues(InvalidPixels),
+
     #[error("Tone mapping error: {0}")]
     ToneMapError(String),
 
+    #[error("Output file exists: {}", .0.display())]
+    OutputExists(std::path::PathBuf),
+
     #[error("CLI argument error: {0}")]
     ArgumentError(String),
 }
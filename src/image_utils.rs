@@ -1,7 +1,12 @@
-use glam::Vec3;
-use image::{Rgb, Rgb32FImage};
+use glam::Vec4;
+use image::{Rgba, Rgba32FImage};
+use rayon::prelude::*;
 
-pub fn sample_bilinear(source: &Rgb32FImage, u: f32, v: f32) -> Rgb<f32> {
+/// Samples an equirectangular image with wrap-around in `u` and clamping in `v`.
+///
+/// The pipeline keeps colour premultiplied by alpha, so plain bilinear interpolation of the four
+/// texels is already correct and transparent texels cannot bleed their colour into the result.
+pub fn sample_bilinear(source: &Rgba32FImage, u: f32, v: f32) -> Rgba<f32> {
     let width = source.width() as f32;
     let height = source.height() as f32;
 
@@ -13,11 +18,10 @@ pub fn sample_bilinear(source: &Rgb32FImage, u: f32, v: f32) -> Rgb<f32> {
     let tx = x - x0;
     let ty = y - y0;
 
-    let get_pixel = |ix: f32, iy: f32| -> Vec3 {
+    let get_pixel = |ix: f32, iy: f32| -> Vec4 {
         let final_x = (ix as i32).rem_euclid(width as i32) as u32;
         let final_y = (iy as i32).clamp(0, height as i32 - 1) as u32;
-        let p = source.get_pixel(final_x, final_y);
-        Vec3::new(p[0], p[1], p[2])
+        Vec4::from_array(source.get_pixel(final_x, final_y).0)
     };
 
     let c00 = get_pixel(x0, y0);
@@ -29,5 +33,63 @@ pub fn sample_bilinear(source: &Rgb32FImage, u: f32, v: f32) -> Rgb<f32> {
     let bottom = c01.lerp(c11, tx);
     let final_color = top.lerp(bottom, ty);
 
-    Rgb([final_color.x, final_color.y, final_color.z])
+    Rgba(final_color.to_array())
+}
+
+/// Halves an image with a 2x2 box filter; an odd last row or column is averaged with itself.
+///
+/// Colour is premultiplied, so averaging is correct for transparent texels too.
+pub fn downsample_half(image: &Rgba32FImage) -> Rgba32FImage {
+    let (width, height) = image.dimensions();
+    let mut half = Rgba32FImage::new(width.div_ceil(2), height.div_ceil(2));
+
+    half.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
+        let (x0, y0) = (2 * x, 2 * y);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let sum = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)]
+            .iter()
+            .map(|&(x, y)| Vec4::from_array(image.get_pixel(x, y).0))
+            .sum::<Vec4>();
+        *pixel = Rgba((sum * 0.25).to_array());
+    });
+
+    half
+}
+
+/// Multiplies colour by alpha in place (straight -> premultiplied).
+pub fn premultiply(image: &mut Rgba32FImage) {
+    image.par_chunks_mut(4).for_each(|p| {
+        p[0] *= p[3];
+        p[1] *= p[3];
+        p[2] *= p[3];
+    });
+}
+
+/// Converts one premultiplied pixel back to straight alpha. Fully transparent pixels become black.
+pub fn unpremultiply(p: [f32; 4]) -> [f32; 4] {
+    if p[3] > 0.0 {
+        [p[0] / p[3], p[1] / p[3], p[2] / p[3], p[3]]
+    } else {
+        [0.0, 0.0, 0.0, 0.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bilinear_does_not_fringe_transparent_texels() {
+        // An opaque red texel next to a fully transparent green one.
+        let mut img = Rgba32FImage::new(2, 1);
+        img.put_pixel(0, 0, Rgba([1.0, 0.0, 0.0, 1.0]));
+        img.put_pixel(1, 0, Rgba([0.0, 1.0, 0.0, 0.0]));
+        premultiply(&mut img);
+
+        let mid = unpremultiply(sample_bilinear(&img, 0.5, 0.5).0);
+
+        assert!((mid[0] - 1.0).abs() < 1e-6);
+        assert_eq!(mid[1], 0.0);
+        assert!((mid[3] - 0.5).abs() < 1e-6);
+    }
 }
@@ -0,0 +1,384 @@
+use crate::error::{Eq2cError, Result};
+
+use ::exr::prelude::{
+    FlatImage, FlatSamples, ReadChannels, ReadLayers, read, read_all_flat_layers_from_file,
+};
+use image::{Rgba, Rgba32FImage};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Which data to read from a (possibly multi-layer) OpenEXR file.
+#[derive(Debug, Clone, Default)]
+pub struct ExrSelection {
+    /// Layer (or part) name; `None` reads the default, unprefixed `R`/`G`/`B` channels.
+    pub layer: Option<String>,
+    /// Explicit channels for red, green and blue, relative to `layer` (e.g. `["R", "G", "B"]`)
+    /// or as full channel names (e.g. `["diffuse.R", "diffuse.G", "diffuse.B"]`). Alpha always
+    /// comes from the layer's `A` channel, if it has one.
+    pub channels: Option<[String; 3]>,
+}
+
+impl ExrSelection {
+    pub fn is_default(&self) -> bool {
+        self.layer.is_none() && self.channels.is_none()
+    }
+}
+
+/// A group of channels sharing a name prefix, e.g. `diffuse` for `diffuse.R`/`diffuse.G`.
+/// The default layer (channels without a prefix) has an empty name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExrLayerInfo {
+    pub name: String,
+    pub channels: Vec<String>,
+}
+
+struct FlatChannel {
+    /// Full channel name including the part name, if the file is multi-part.
+    name: String,
+    width: usize,
+    height: usize,
+    samples: FlatSamples,
+}
+
+/// All channels of an OpenEXR file, decoded once so several layers can be extracted cheaply.
+pub struct ExrFile {
+    channels: Vec<FlatChannel>,
+}
+
+impl ExrFile {
+    /// Reads every channel of every part at the largest resolution level.
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self::from_image(read_all_flat_layers_from_file(path)?))
+    }
+
+    /// Like [`ExrFile::open`], for a file that is already in memory (e.g. read from stdin).
+    pub fn from_memory(data: &[u8]) -> Result<Self> {
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .all_layers()
+            .all_attributes()
+            .from_buffered(Cursor::new(data))?;
+        Ok(Self::from_image(image))
+    }
+
+    fn from_image(image: FlatImage) -> Self {
+        let mut channels = Vec::new();
+        for part in image.layer_data {
+            let prefix = part
+                .attributes
+                .layer_name
+                .as_ref()
+                .map(|name| name.to_string());
+
+            for channel in part.channel_data.list {
+                let name = match &prefix {
+                    Some(prefix) => format!("{}.{}", prefix, channel.name),
+                    None => channel.name.to_string(),
+                };
+
+                channels.push(FlatChannel {
+                    name,
+                    width: part.size.width(),
+                    height: part.size.height(),
+                    samples: channel.sample_data,
+                });
+            }
+        }
+
+        Self { channels }
+    }
+
+    /// Lists the layers of the file in name order, the default layer first.
+    pub fn layers(&self) -> Vec<ExrLayerInfo> {
+        let mut layers: Vec<ExrLayerInfo> = Vec::new();
+
+        for channel in &self.channels {
+            let (layer, suffix) = split_channel_name(&channel.name);
+            match layers.iter_mut().find(|l| l.name == layer) {
+                Some(info) => info.channels.push(suffix.to_string()),
+                None => layers.push(ExrLayerInfo {
+                    name: layer.to_string(),
+                    channels: vec![suffix.to_string()],
+                }),
+            }
+        }
+
+        layers.sort_by(|a, b| a.name.cmp(&b.name));
+        layers
+    }
+
+    /// Extracts the selected layer/channels as linear, premultiplied RGBA32F.
+    ///
+    /// Without explicit channels, the layer's `R`/`G`/`B` channels are used (case-insensitive);
+    /// a layer with a single channel (e.g. `Y` or `depth.Z`) is expanded to grey. The layer's
+    /// `A` channel becomes alpha; layers without one are fully opaque.
+    ///
+    /// # Errors
+    ///
+    /// - `Eq2cError::LayerNotFound` if the requested layer does not exist.
+    /// - `Eq2cError::ChannelNotFound` if a requested or default channel is missing.
+    /// - `Eq2cError::InvalidDimensions` if the chosen channels differ in resolution.
+    pub fn extract(&self, selection: &ExrSelection) -> Result<Rgba32FImage> {
+        let layer_name = selection.layer.as_deref().unwrap_or("");
+        let layers = self.layers();
+
+        let layer = layers
+            .iter()
+            .find(|l| l.name == layer_name)
+            .ok_or_else(|| Eq2cError::LayerNotFound {
+                name: match layer_name {
+                    "" => "default RGB layer".to_string(),
+                    name => name.to_string(),
+                },
+                available: format_layer_names(&layers),
+            })?;
+
+        let mapping: [String; 3] = match &selection.channels {
+            Some(channels) => channels.clone(),
+            None => default_mapping(layer)?,
+        };
+
+        let rgb = [
+            self.find_channel(layer, &mapping[0])?,
+            self.find_channel(layer, &mapping[1])?,
+            self.find_channel(layer, &mapping[2])?,
+        ];
+
+        let alpha = match layer.channels.iter().find(|c| c.eq_ignore_ascii_case("A")) {
+            Some(name) if !mapping.contains(name) => Some(self.find_channel(layer, name)?),
+            _ => None,
+        };
+
+        let (width, height) = (rgb[0].width, rgb[0].height);
+        for channel in rgb[1..].iter().chain(alpha.as_ref()) {
+            if (channel.width, channel.height) != (width, height) {
+                return Err(Eq2cError::InvalidDimensions {
+                    expected: format!("{}x{} for channel '{}'", width, height, channel.name),
+                    found: format!("{}x{}", channel.width, channel.height),
+                });
+            }
+        }
+
+        for channel in rgb.iter().chain(alpha.as_ref()) {
+            if channel.samples.len() != width * height {
+                return Err(Eq2cError::UnsupportedFormat(format!(
+                    "subsampled EXR channel '{}'",
+                    channel.name
+                )));
+            }
+        }
+
+        Ok(Rgba32FImage::from_fn(
+            width as u32,
+            height as u32,
+            |x, y| {
+                let index = y as usize * width + x as usize;
+                let [r, g, b] = rgb.map(|c| c.samples.value_by_flat_index(index).to_f32());
+                let a = alpha.map_or(1.0, |c| c.samples.value_by_flat_index(index).to_f32());
+                Rgba([r, g, b, a])
+            },
+        ))
+    }
+
+    /// Extracts every layer that can be mapped to RGB, as [`load_all_layers`] does.
+    pub fn extract_all(&self) -> Result<Vec<(String, Rgba32FImage)>> {
+        self.layers()
+            .into_iter()
+            .filter(|layer| default_mapping(layer).is_ok())
+            .map(|layer| {
+                let selection = ExrSelection {
+                    layer: Some(layer.name.clone()),
+                    channels: None,
+                };
+                Ok((layer.name, self.extract(&selection)?))
+            })
+            .collect()
+    }
+
+    fn find_channel(&self, layer: &ExrLayerInfo, name: &str) -> Result<&FlatChannel> {
+        let full_name = if layer.name.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", layer.name, name)
+        };
+
+        self.channels
+            .iter()
+            .find(|c| c.name == full_name)
+            // A dotted name may be a full channel name; a bare one never leaves the layer.
+            .or_else(|| {
+                self.channels
+                    .iter()
+                    .find(|c| name.contains('.') && c.name == name)
+            })
+            .ok_or_else(|| Eq2cError::ChannelNotFound {
+                name: name.to_string(),
+                layer: display_layer_name(&layer.name).to_string(),
+                available: layer.channels.join(", "),
+            })
+    }
+}
+
+/// Reads one layer of an OpenEXR file as linear, premultiplied RGBA32F.
+pub fn load_layer(path: &Path, selection: &ExrSelection) -> Result<Rgba32FImage> {
+    ExrFile::open(path)?.extract(selection)
+}
+
+/// Reads every layer that can be mapped to RGB, returning `(layer name, image)` pairs.
+/// Layers without a usable default mapping (e.g. two-channel motion vectors) are skipped.
+pub fn load_all_layers(path: &Path) -> Result<Vec<(String, Rgba32FImage)>> {
+    ExrFile::open(path)?.extract_all()
+}
+
+fn split_channel_name(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => ("", name),
+    }
+}
+
+fn default_mapping(layer: &ExrLayerInfo) -> Result<[String; 3]> {
+    let find = |wanted: &str| {
+        layer
+            .channels
+            .iter()
+            .find(|c| c.eq_ignore_ascii_case(wanted))
+            .cloned()
+    };
+
+    if let (Some(r), Some(g), Some(b)) = (find("R"), find("G"), find("B")) {
+        return Ok([r, g, b]);
+    }
+
+    if let [single] = layer.channels.as_slice() {
+        return Ok([single.clone(), single.clone(), single.clone()]);
+    }
+
+    Err(Eq2cError::ChannelNotFound {
+        name: "R/G/B".to_string(),
+        layer: display_layer_name(&layer.name).to_string(),
+        available: layer.channels.join(", "),
+    })
+}
+
+fn display_layer_name(name: &str) -> &str {
+    if name.is_empty() { "<default>" } else { name }
+}
+
+fn format_layer_names(layers: &[ExrLayerInfo]) -> String {
+    layers
+        .iter()
+        .map(|l| display_layer_name(&l.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(name: &str, value: f32) -> FlatChannel {
+        FlatChannel {
+            name: name.to_string(),
+            width: 1,
+            height: 1,
+            samples: FlatSamples::F32(vec![value]),
+        }
+    }
+
+    fn test_file() -> ExrFile {
+        ExrFile {
+            channels: vec![
+                flat("B", 0.3),
+                flat("G", 0.2),
+                flat("R", 0.1),
+                flat("depth.Z", 5.0),
+                flat("diffuse.A", 0.5),
+                flat("diffuse.B", 3.0),
+                flat("diffuse.G", 2.0),
+                flat("diffuse.R", 1.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_layers_are_grouped_by_prefix() {
+        let names: Vec<String> = test_file().layers().into_iter().map(|l| l.name).collect();
+        assert_eq!(names, vec!["", "depth", "diffuse"]);
+    }
+
+    #[test]
+    fn test_extract_layer_and_grey_expansion() {
+        let file = test_file();
+
+        let diffuse = file
+            .extract(&ExrSelection {
+                layer: Some("diffuse".to_string()),
+                channels: None,
+            })
+            .unwrap();
+        assert_eq!(diffuse.get_pixel(0, 0).0, [1.0, 2.0, 3.0, 0.5]);
+
+        let depth = file
+            .extract(&ExrSelection {
+                layer: Some("depth".to_string()),
+                channels: None,
+            })
+            .unwrap();
+        assert_eq!(depth.get_pixel(0, 0).0, [5.0, 5.0, 5.0, 1.0]);
+    }
+
+    #[test]
+    fn test_explicit_channel_mapping() {
+        let swapped = test_file()
+            .extract(&ExrSelection {
+                layer: None,
+                channels: Some(["B".to_string(), "diffuse.G".to_string(), "R".to_string()]),
+            })
+            .unwrap();
+        assert_eq!(swapped.get_pixel(0, 0).0, [0.3, 2.0, 0.1, 1.0]);
+    }
+
+    #[test]
+    fn test_missing_layer_and_channel_errors() {
+        let file = test_file();
+
+        let missing_layer = file.extract(&ExrSelection {
+            layer: Some("specular".to_string()),
+            channels: None,
+        });
+        assert!(matches!(
+            missing_layer,
+            Err(Eq2cError::LayerNotFound { .. })
+        ));
+
+        let missing_channel = file.extract(&ExrSelection {
+            layer: Some("diffuse".to_string()),
+            channels: Some(["R".to_string(), "G".to_string(), "Z".to_string()]),
+        });
+        assert!(matches!(
+            missing_channel,
+            Err(Eq2cError::ChannelNotFound { .. })
+        ));
+
+        // `R` in the depth layer is `depth.R`, not the default layer's `R`.
+        let other_layer = file.extract(&ExrSelection {
+            layer: Some("depth".to_string()),
+            channels: Some(["R".to_string(), "G".to_string(), "B".to_string()]),
+        });
+        assert!(matches!(
+            other_layer,
+            Err(Eq2cError::ChannelNotFound { .. })
+        ));
+
+        let layered = ExrFile {
+            channels: vec![flat("diffuse.R", 1.0)],
+        };
+        assert!(matches!(
+            layered.extract(&ExrSelection::default()),
+            Err(Eq2cError::LayerNotFound { name, .. }) if name == "default RGB layer"
+        ));
+    }
+}
@@ -0,0 +1,192 @@
+use crate::color::TransferFunction;
+use crate::error::{Eq2cError, Result};
+use crate::image_utils::premultiply;
+
+use image::{DynamicImage, Rgb32FImage, Rgba32FImage};
+use rayon::prelude::*;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::path::Path;
+
+pub mod exr;
+pub mod pfm;
+pub mod tiff;
+
+/// Loads an equirectangular panorama from disk as linear-light, premultiplied RGBA32F.
+///
+/// Radiance HDR, OpenEXR, PFM and floating-point TIFF files are always taken as linear. Integer
+/// images (8/16-bit PNG, JPEG, integer TIFF, ...) are normalized to `0..=1` and then decoded with
+/// `transfer`, which defaults to sRGB; pass `Some(TransferFunction::Linear)` for integer data
+/// that is already linear.
+///
+/// OpenEXR data is premultiplied by convention and kept as is; every other format stores
+/// straight alpha and is premultiplied after decoding. Images without alpha are fully opaque.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be opened or decoded.
+pub fn load_image(path: &Path, transfer: Option<TransferFunction>) -> Result<Rgba32FImage> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+
+    let kind = match extension.as_deref() {
+        Some("pfm") => Some(FloatKind::Pfm),
+        Some("tif" | "tiff") => Some(FloatKind::Tiff),
+        Some("exr") => Some(FloatKind::Exr),
+        _ => None,
+    };
+    if let Some(kind) = kind
+        && let Some(img) = decode_float(kind, BufReader::new(File::open(path)?))?
+    {
+        return Ok(img);
+    }
+
+    Ok(decode_dynamic(image::open(path)?, transfer))
+}
+
+/// Like [`load_image`], for an encoded file in memory (e.g. read from stdin). The format is
+/// detected from the leading magic bytes instead of an extension.
+///
+/// # Errors
+///
+/// Returns an error if the data is in no supported format or cannot be decoded.
+pub fn load_image_from_memory(
+    data: &[u8],
+    transfer: Option<TransferFunction>,
+) -> Result<Rgba32FImage> {
+    let kind = if data.starts_with(b"PF") || data.starts_with(b"Pf") {
+        Some(FloatKind::Pfm)
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some(FloatKind::Tiff)
+    } else if is_exr(data) {
+        Some(FloatKind::Exr)
+    } else {
+        None
+    };
+    if let Some(kind) = kind
+        && let Some(img) = decode_float(kind, Cursor::new(data))?
+    {
+        return Ok(img);
+    }
+
+    Ok(decode_dynamic(image::load_from_memory(data)?, transfer))
+}
+
+/// Whether `data` starts with the OpenEXR magic number.
+pub fn is_exr(data: &[u8]) -> bool {
+    data.starts_with(&[0x76, 0x2f, 0x31, 0x01])
+}
+
+/// Formats that hold linear float data and are not decoded through [`decode_dynamic`].
+#[derive(Clone, Copy)]
+enum FloatKind {
+    Pfm,
+    Tiff,
+    Exr,
+}
+
+/// Decodes a float format; `None` for a TIFF with integer samples, which goes through `image`.
+fn decode_float(kind: FloatKind, reader: impl BufRead + Seek) -> Result<Option<Rgba32FImage>> {
+    match kind {
+        FloatKind::Pfm => Ok(Some(pfm::decode(reader)?)),
+        FloatKind::Tiff => tiff::decode_float(reader),
+        FloatKind::Exr => Ok(Some(
+            image::load(reader, image::ImageFormat::OpenExr)?.into_rgba32f(),
+        )),
+    }
+}
+
+/// Converts an already decoded straight-alpha image to linear, premultiplied RGBA32F. Float
+/// images are taken as linear; integer ones are decoded with `transfer` (sRGB when `None`).
+pub fn decode_dynamic(img: DynamicImage, transfer: Option<TransferFunction>) -> Rgba32FImage {
+    let is_float = matches!(
+        img,
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)
+    );
+
+    let transfer = if is_float {
+        TransferFunction::Linear
+    } else {
+        transfer.unwrap_or(TransferFunction::Srgb)
+    };
+
+    let mut img = linearize(img.into_rgba32f(), transfer);
+    premultiply(&mut img);
+    img
+}
+
+/// Takes an in-memory linear RGB panorama as fully opaque RGBA32F.
+pub fn from_rgb32f(img: Rgb32FImage) -> Rgba32FImage {
+    DynamicImage::ImageRgb32F(img).into_rgba32f()
+}
+
+/// Builds a linear, premultiplied RGBA32F image from a raw interleaved buffer of `channels`
+/// (3 for RGB, 4 for straight-alpha RGBA) floats per pixel, row by row.
+///
+/// # Errors
+///
+/// Returns `Eq2cError::InvalidDimensions` if `channels` is not 3 or 4 or `data` does not hold
+/// exactly `width * height` pixels.
+pub fn from_raw(width: u32, height: u32, channels: usize, data: &[f32]) -> Result<Rgba32FImage> {
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(channels));
+
+    if !matches!(channels, 3 | 4) || expected != Some(data.len()) {
+        return Err(Eq2cError::InvalidDimensions {
+            expected: format!("{}x{} pixels of 3 or 4 channels", width, height),
+            found: format!("{} values of {} channels", data.len(), channels),
+        });
+    }
+
+    let mut img = if channels == 3 {
+        let rgb =
+            Rgb32FImage::from_raw(width, height, data.to_vec()).expect("buffer size checked above");
+        from_rgb32f(rgb)
+    } else {
+        Rgba32FImage::from_raw(width, height, data.to_vec()).expect("buffer size checked above")
+    };
+    premultiply(&mut img);
+    Ok(img)
+}
+
+/// Decodes the colour channels with `transfer`; alpha is always linear.
+fn linearize(mut img: Rgba32FImage, transfer: TransferFunction) -> Rgba32FImage {
+    if transfer != TransferFunction::Linear {
+        img.par_chunks_mut(4).for_each(|p| {
+            for v in &mut p[..3] {
+                *v = transfer.to_linear(*v);
+            }
+        });
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_from_memory_detects_format() {
+        let mut pfm = b"PF\n1 1\n-1.0\n".to_vec();
+        for v in [2.0f32, 0.5, 0.25] {
+            pfm.extend_from_slice(&v.to_le_bytes());
+        }
+        let img = load_image_from_memory(&pfm, None).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [2.0, 0.5, 0.25, 1.0]);
+        // The input transfer only applies to integer images.
+        let img = load_image_from_memory(&pfm, Some(TransferFunction::Gamma22)).unwrap();
+        assert_eq!(img.get_pixel(0, 0).0, [2.0, 0.5, 0.25, 1.0]);
+
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(2, 1, image::Rgb([255, 0, 0]))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let img = load_image_from_memory(&png, None).unwrap();
+        assert_eq!(img.dimensions(), (2, 1));
+        assert_eq!(img.get_pixel(1, 0).0, [1.0, 0.0, 0.0, 1.0]);
+
+        assert!(load_image_from_memory(b"not an image", None).is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use crate::error::{Eq2cError, Result};
+
+use image::{Rgba, Rgba32FImage};
+use std::io::{BufRead, Read};
+
+/// Decodes a Portable Float Map (`PF` colour or `Pf` greyscale).
+///
+/// The sign of the scale line selects the byte order (negative = little endian) and rows are
+/// stored bottom-to-top, so they are flipped into the usual top-down order here. PFM has no
+/// alpha channel, so the result is fully opaque.
+pub fn decode<R: BufRead>(mut reader: R) -> Result<Rgba32FImage> {
+    let magic = read_token(&mut reader)?;
+    let channels = match magic.as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        other => {
+            return Err(Eq2cError::Decoding(format!(
+                "PFM: bad magic {:?}, expected \"PF\" or \"Pf\"",
+                other
+            )));
+        }
+    };
+
+    let width: u32 = parse_token(&mut reader, "width")?;
+    let height: u32 = parse_token(&mut reader, "height")?;
+    let scale: f32 = parse_token(&mut reader, "scale")?;
+    let little_endian = scale < 0.0;
+
+    let samples = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(channels))
+        .filter(|n| n.checked_mul(4).is_some())
+        .ok_or_else(|| Eq2cError::InvalidDimensions {
+            expected: "valid PFM dimensions".to_string(),
+            found: format!("{}x{}", width, height),
+        })?;
+
+    // Read through `take` rather than into a buffer sized from the header, so a bogus header on
+    // a short stream cannot allocate gigabytes.
+    let mut bytes = Vec::new();
+    reader.take(samples as u64 * 4).read_to_end(&mut bytes)?;
+    if bytes.len() < samples * 4 {
+        return Err(Eq2cError::Decoding(format!(
+            "PFM: truncated pixel data ({} of {} bytes)",
+            bytes.len(),
+            samples * 4
+        )));
+    }
+
+    let values: Vec<f32> = bytes
+        .chunks_exact(4)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if little_endian {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            }
+        })
+        .collect();
+
+    Ok(Rgba32FImage::from_fn(width, height, |x, y| {
+        let row = (height - 1 - y) as usize;
+        let base = (row * width as usize + x as usize) * channels;
+        if channels == 3 {
+            Rgba([values[base], values[base + 1], values[base + 2], 1.0])
+        } else {
+            Rgba([values[base], values[base], values[base], 1.0])
+        }
+    }))
+}
+
+/// Reads one whitespace-delimited header token, consuming exactly one trailing whitespace byte.
+fn read_token<R: BufRead>(reader: &mut R) -> Result<String> {
+    let mut token = Vec::new();
+    let mut byte = [0u8; 1];
+
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            break;
+        }
+        if byte[0].is_ascii_whitespace() {
+            if token.is_empty() {
+                continue;
+            }
+            break;
+        }
+        token.push(byte[0]);
+    }
+
+    if token.is_empty() {
+        return Err(Eq2cError::Decoding(
+            "PFM: unexpected end of header".to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&token).into_owned())
+}
+
+fn parse_token<R: BufRead, T: std::str::FromStr>(reader: &mut R, what: &str) -> Result<T> {
+    let token = read_token(reader)?;
+    token
+        .parse()
+        .map_err(|_| Eq2cError::Decoding(format!("PFM: invalid {} {:?}", what, token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_flips_rows_and_reads_le() {
+        let mut data = b"PF\n1 2\n-1.0\n".to_vec();
+        // Bottom row first.
+        for v in [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+
+        let img = decode(&data[..]).unwrap();
+
+        assert_eq!(img.dimensions(), (1, 2));
+        assert_eq!(img.get_pixel(0, 0).0, [4.0, 5.0, 6.0, 1.0]);
+        assert_eq!(img.get_pixel(0, 1).0, [1.0, 2.0, 3.0, 1.0]);
+    }
+
+    #[test]
+    fn test_decode_greyscale_big_endian() {
+        let mut data = b"Pf 1 1 1.0\n".to_vec();
+        data.extend_from_slice(&0.5f32.to_be_bytes());
+
+        let img = decode(&data[..]).unwrap();
+
+        assert_eq!(img.get_pixel(0, 0).0, [0.5, 0.5, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn test_decode_rejects_truncated_data() {
+        let data = b"PF\n2 2\n-1.0\n\0\0\0\0".to_vec();
+        assert!(matches!(decode(&data[..]), Err(Eq2cError::Decoding(_))));
+
+        // A huge header on a tiny stream fails without allocating the claimed size.
+        let data = b"PF\n100000 100000\n-1.0\n\0\0\0\0".to_vec();
+        assert!(matches!(decode(&data[..]), Err(Eq2cError::Decoding(_))));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::error::{Eq2cError, Result};
+use crate::image_utils::premultiply;
+
+use image::{Rgba, Rgba32FImage};
+use std::io::{Read, Seek};
+use tiff::ColorType;
+use tiff::decoder::{Decoder, DecodingResult};
+use tiff::tags::{SampleFormat, Tag};
+
+/// Decodes a floating-point TIFF (16, 32 or 64 bit samples) into linear, premultiplied RGBA32F.
+///
+/// Returns `Ok(None)` without decoding any pixels for TIFFs whose samples are not all floats
+/// (integer, palette, CMYK, ...), which are left to the generic `image` decoder. Greyscale images
+/// are expanded to RGB; images without alpha are fully opaque. Alpha marked as associated
+/// (`ExtraSamples` = 1) is taken as already premultiplied.
+pub fn decode_float<R: Read + Seek>(reader: R) -> Result<Option<Rgba32FImage>> {
+    let mut decoder = Decoder::new(reader).map_err(tiff_error)?;
+    let is_float = decoder
+        .find_tag_unsigned_vec::<u16>(Tag::SampleFormat)
+        .map_err(tiff_error)?
+        .is_some_and(|formats| {
+            formats
+                .iter()
+                .all(|&f| SampleFormat::from_u16(f) == Some(SampleFormat::IEEEFP))
+        });
+    if !is_float {
+        return Ok(None);
+    }
+
+    let (width, height) = decoder.dimensions().map_err(tiff_error)?;
+    let associated_alpha = decoder
+        .find_tag_unsigned_vec::<u16>(Tag::ExtraSamples)
+        .map_err(tiff_error)?
+        .is_some_and(|extra| extra.first() == Some(&1));
+
+    let channels = match decoder.colortype().map_err(tiff_error)? {
+        ColorType::Gray(_) => 1,
+        ColorType::GrayA(_) => 2,
+        ColorType::RGB(_) => 3,
+        ColorType::RGBA(_) => 4,
+        other => {
+            return Err(Eq2cError::UnsupportedFormat(format!(
+                "TIFF color type {:?}",
+                other
+            )));
+        }
+    };
+
+    let samples: Vec<f32> = match decoder.read_image().map_err(tiff_error)? {
+        DecodingResult::F16(v) => v.into_iter().map(|s| s.to_f32()).collect(),
+        DecodingResult::F32(v) => v,
+        DecodingResult::F64(v) => v.into_iter().map(|s| s as f32).collect(),
+        _ => return Ok(None),
+    };
+
+    let expected = width as usize * height as usize * channels;
+    if samples.len() < expected {
+        return Err(Eq2cError::InvalidDimensions {
+            expected: format!("{} TIFF samples", expected),
+            found: format!("{}", samples.len()),
+        });
+    }
+
+    let mut img = Rgba32FImage::from_fn(width, height, |x, y| {
+        let base = (y as usize * width as usize + x as usize) * channels;
+        let s = &samples[base..base + channels];
+        match channels {
+            1 => Rgba([s[0], s[0], s[0], 1.0]),
+            2 => Rgba([s[0], s[0], s[0], s[1]]),
+            3 => Rgba([s[0], s[1], s[2], 1.0]),
+            _ => Rgba([s[0], s[1], s[2], s[3]]),
+        }
+    });
+    if !associated_alpha {
+        premultiply(&mut img);
+    }
+    Ok(Some(img))
+}
+
+fn tiff_error(e: tiff::TiffError) -> Eq2cError {
+    Eq2cError::Decoding(format!("TIFF: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, Rgb32FImage, RgbImage};
+    use std::io::Cursor;
+
+    fn tiff(image: impl Into<image::DynamicImage>) -> Vec<u8> {
+        let mut data = Vec::new();
+        image
+            .into()
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Tiff)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn test_integer_tiff_is_left_to_image() {
+        let data = tiff(RgbImage::from_pixel(2, 2, image::Rgb([255, 128, 0])));
+        assert!(decode_float(Cursor::new(data)).unwrap().is_none());
+
+        let data = tiff(Rgb32FImage::from_pixel(2, 2, image::Rgb([2.0, 0.5, 0.0])));
+        let img = decode_float(Cursor::new(data)).unwrap().unwrap();
+        assert_eq!(img.get_pixel(1, 1).0, [2.0, 0.5, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn test_alpha_is_premultiplied_once() {
+        let rgba = |extra_samples: u16| {
+            let mut data = Vec::new();
+            let mut encoder = tiff::encoder::TiffEncoder::new(Cursor::new(&mut data)).unwrap();
+            let mut image = encoder
+                .new_image::<tiff::encoder::colortype::RGBA32Float>(1, 1)
+                .unwrap();
+            image
+                .encoder()
+                .write_tag(Tag::ExtraSamples, extra_samples)
+                .unwrap();
+            image.write_data(&[1.0, 0.5, 0.25, 0.5]).unwrap();
+            decode_float(Cursor::new(data)).unwrap().unwrap()
+        };
+
+        assert_eq!(rgba(2).get_pixel(0, 0).0, [0.5, 0.25, 0.125, 0.5]);
+        assert_eq!(rgba(1).get_pixel(0, 0).0, [1.0, 0.5, 0.25, 0.5]);
+    }
+}
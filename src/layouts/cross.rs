@@ -1,18 +1,22 @@
-use super::{LayoutOutput, SkyboxLayout, render_all_faces};
+use super::{LayoutOutput, SkyboxLayout};
 use crate::math::CubeFace;
-use image::{ImageBuffer, Rgb32FImage};
+use image::{ImageBuffer, Rgba, Rgba32FImage};
+use std::borrow::Cow;
 
 pub struct CrossLayout;
 
 impl SkyboxLayout for CrossLayout {
-    fn generate(&self, source: &Rgb32FImage, face_size: u32) -> LayoutOutput {
-        let rendered_faces = render_all_faces(source, face_size);
-
+    fn arrange(
+        &self,
+        rendered_faces: Cow<'_, [(CubeFace, Rgba32FImage)]>,
+        face_size: u32,
+    ) -> LayoutOutput {
         let width = face_size * 4;
         let height = face_size * 3;
-        let mut final_image = ImageBuffer::new(width, height);
+        // Cells without a face are opaque black, so the layout only has alpha if a face does.
+        let mut final_image = ImageBuffer::from_pixel(width, height, Rgba([0.0, 0.0, 0.0, 1.0]));
 
-        for (face, buffer) in rendered_faces {
+        for (face, buffer) in rendered_faces.iter() {
             let (col, row) = match face {
                 CubeFace::Left => (0, 1),
                 CubeFace::Front => (1, 1),
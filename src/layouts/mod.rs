@@ -1,13 +1,13 @@
-use image::Rgb32FImage;
+use image::Rgba32FImage;
 pub mod cross;
 pub mod separate;
 pub mod strip;
 
-use crate::image_utils::sample_bilinear;
+use crate::image_utils::{self, sample_bilinear};
 
 pub enum LayoutOutput {
-    Single(Rgb32FImage),
-    Frames(Vec<(CubeFace, Rgb32FImage)>),
+    Single(Rgba32FImage),
+    Frames(Vec<(CubeFace, Rgba32FImage)>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -18,12 +18,68 @@ pub enum LayoutType {
     Separate,
 }
 
+impl LayoutType {
+    pub const ALL: [LayoutType; 4] = [
+        LayoutType::Cross,
+        LayoutType::StripHorizontal,
+        LayoutType::StripVertical,
+        LayoutType::Separate,
+    ];
+
+    /// The name used on the command line and in recipes.
+    pub fn name(self) -> &'static str {
+        match self {
+            LayoutType::Cross => "cross",
+            LayoutType::StripHorizontal => "strip-h",
+            LayoutType::StripVertical => "strip-v",
+            LayoutType::Separate => "separate",
+        }
+    }
+}
+
+impl std::str::FromStr for LayoutType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        LayoutType::ALL
+            .into_iter()
+            .find(|layout| layout.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let names: Vec<_> = LayoutType::ALL.iter().map(|l| l.name()).collect();
+                format!(
+                    "unknown layout '{}' (expected one of: {})",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
 pub trait SkyboxLayout {
-    fn generate(&self, source: &Rgb32FImage, face_size: u32) -> LayoutOutput;
+    /// Places already rendered faces into the layout. Borrowed faces are only copied by layouts
+    /// that return them as they are.
+    fn arrange(&self, faces: Cow<'_, [(CubeFace, Rgba32FImage)]>, face_size: u32) -> LayoutOutput;
+
+    fn generate(&self, source: &Rgba32FImage, face_size: u32) -> LayoutOutput {
+        self.arrange(Cow::Owned(render_all_faces(source, face_size)), face_size)
+    }
+}
+
+pub fn generate_layout(layout: LayoutType, source: &Rgba32FImage, face_size: u32) -> LayoutOutput {
+    processor(layout).generate(source, face_size)
+}
+
+/// Like [`generate_layout`], but for faces that were rendered (and possibly processed) already.
+pub fn arrange_layout(
+    layout: LayoutType,
+    faces: Cow<'_, [(CubeFace, Rgba32FImage)]>,
+    face_size: u32,
+) -> LayoutOutput {
+    processor(layout).arrange(faces, face_size)
 }
 
-pub fn generate_layout(layout: LayoutType, source: &Rgb32FImage, face_size: u32) -> LayoutOutput {
-    let processor: Box<dyn SkyboxLayout> = match layout {
+fn processor(layout: LayoutType) -> Box<dyn SkyboxLayout> {
+    match layout {
         LayoutType::Cross => Box::new(cross::CrossLayout),
 
         LayoutType::StripHorizontal => Box::new(strip::StripLayout {
@@ -35,41 +91,38 @@ pub fn generate_layout(layout: LayoutType, source: &Rgb32FImage, face_size: u32)
         }),
 
         LayoutType::Separate => Box::new(separate::SeparateLayout),
-    };
-
-    processor.generate(source, face_size)
+    }
 }
 
 // --- SHARED HELPERS ---
 use crate::math::{self, CubeFace};
 use image::ImageBuffer;
 use rayon::prelude::*;
+use std::borrow::Cow;
 
-pub fn render_all_faces(source: &Rgb32FImage, face_size: u32) -> Vec<(CubeFace, Rgb32FImage)> {
-    let faces = vec![
-        CubeFace::Right,
-        CubeFace::Left,
-        CubeFace::Top,
-        CubeFace::Bottom,
-        CubeFace::Front,
-        CubeFace::Back,
-    ];
-
-    faces
+pub fn render_all_faces(source: &Rgba32FImage, face_size: u32) -> Vec<(CubeFace, Rgba32FImage)> {
+    let source = prefilter(source, face_size);
+    CubeFace::ALL
         .par_iter()
         .map(|&face| {
-            let buffer = extract_single_face(source, face, face_size);
+            let buffer = render_face(&source, face, face_size, 0);
             (face, buffer)
         })
         .collect()
 }
 
-fn extract_single_face(source: &Rgb32FImage, face: CubeFace, size: u32) -> Rgb32FImage {
-    let mut buffer = ImageBuffer::new(size, size);
+/// Renders one face of `size` pixels with `pad` extra pixels on every side. The padding continues
+/// the face's projection past its edges, so it shows the neighbouring faces' content.
+///
+/// `source` should already be [`prefilter`]ed for `size`; bilinear sampling alone aliases when
+/// many source texels fall into one face pixel.
+pub fn render_face(source: &Rgba32FImage, face: CubeFace, size: u32, pad: u32) -> Rgba32FImage {
+    let padded = size + 2 * pad;
+    let mut buffer = ImageBuffer::new(padded, padded);
 
     buffer.par_enumerate_pixels_mut().for_each(|(x, y, pixel)| {
-        let u = (x as f32 + 0.5) / size as f32;
-        let v = (y as f32 + 0.5) / size as f32;
+        let u = (x as f32 - pad as f32 + 0.5) / size as f32;
+        let v = (y as f32 - pad as f32 + 0.5) / size as f32;
 
         let source_uv = math::calculate_source_uv(face, u, v);
 
@@ -80,3 +133,41 @@ fn extract_single_face(source: &Rgb32FImage, face: CubeFace, size: u32) -> Rgb32
 
     buffer
 }
+
+/// Box-filters `source` down by halves until about one texel falls into each pixel of a
+/// `face_size` face (a face spans a quarter of the equirect's width). Sources that are small
+/// enough already are borrowed unchanged.
+pub fn prefilter(source: &Rgba32FImage, face_size: u32) -> Cow<'_, Rgba32FImage> {
+    let mut filtered = Cow::Borrowed(source);
+
+    while filtered.width() / 2 >= 4 * face_size.max(1) {
+        filtered = Cow::Owned(image_utils::downsample_half(&filtered));
+    }
+
+    filtered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_small_faces_average_fine_detail() {
+        // One-texel stripes, far finer than the face pixels.
+        let source = Rgba32FImage::from_fn(1024, 512, |x, _| {
+            let value = (x % 2) as f32;
+            Rgba([value, value, value, 1.0])
+        });
+
+        let filtered = prefilter(&source, 16);
+        assert_eq!(filtered.width(), 64);
+        assert!(matches!(prefilter(&source, 256), Cow::Borrowed(_)));
+
+        let face = render_face(&filtered, CubeFace::Front, 16, 0);
+        for pixel in face.pixels() {
+            assert!((pixel[0] - 0.5).abs() < 0.15, "aliased: {}", pixel[0]);
+            assert!((pixel[3] - 1.0).abs() < 1e-5);
+        }
+    }
+}
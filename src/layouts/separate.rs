@@ -1,12 +1,12 @@
-use super::{LayoutOutput, SkyboxLayout, render_all_faces};
-use image::Rgb32FImage;
+use super::{LayoutOutput, SkyboxLayout};
+use crate::math::CubeFace;
+use image::Rgba32FImage;
+use std::borrow::Cow;
 
 pub struct SeparateLayout;
 
 impl SkyboxLayout for SeparateLayout {
-    fn generate(&self, source: &Rgb32FImage, face_size: u32) -> LayoutOutput {
-        let faces = render_all_faces(source, face_size);
-
-        LayoutOutput::Frames(faces)
+    fn arrange(&self, faces: Cow<'_, [(CubeFace, Rgba32FImage)]>, _face_size: u32) -> LayoutOutput {
+        LayoutOutput::Frames(faces.into_owned())
     }
 }
@@ -1,6 +1,7 @@
-use super::{LayoutOutput, SkyboxLayout, render_all_faces};
+use super::{LayoutOutput, SkyboxLayout};
 use crate::math::CubeFace;
-use image::{ImageBuffer, Rgb32FImage};
+use image::{ImageBuffer, Rgba32FImage};
+use std::borrow::Cow;
 
 #[derive(Clone, Copy)]
 pub enum StripDirection {
@@ -13,9 +14,11 @@ pub struct StripLayout {
 }
 
 impl SkyboxLayout for StripLayout {
-    fn generate(&self, source: &Rgb32FImage, face_size: u32) -> LayoutOutput {
-        let rendered_faces = render_all_faces(source, face_size);
-
+    fn arrange(
+        &self,
+        rendered_faces: Cow<'_, [(CubeFace, Rgba32FImage)]>,
+        face_size: u32,
+    ) -> LayoutOutput {
         let (width, height) = match self.direction {
             StripDirection::Horizontal => (face_size * 6, face_size),
             StripDirection::Vertical => (face_size, face_size * 6),
@@ -23,7 +26,7 @@ impl SkyboxLayout for StripLayout {
 
         let mut final_image = ImageBuffer::new(width, height);
 
-        for (face, buffer) in rendered_faces {
+        for (face, buffer) in rendered_faces.iter() {
             let index = match face {
                 CubeFace::Right => 0,  // +X
                 CubeFace::Left => 1,   // -X
@@ -1,34 +1,192 @@
+pub mod analysis;
+pub mod batch;
 pub mod codecs;
+pub mod color;
+pub mod contact_sheet;
 pub mod error;
 pub mod image_utils;
+pub mod input;
 pub mod layouts;
+pub mod local_tonemap;
 pub mod math;
+pub mod output;
 mod paths;
+pub mod pipeline;
+pub mod progress;
+pub mod recipe;
+pub mod sanitize;
+pub mod watch;
 
-pub use codecs::{OutputFormat, ToneMapType, get_encoder};
+pub use analysis::{AnalysisReport, AutoExposure, ExposureMethod, analyze};
+pub use batch::{BatchOptions, BatchReport, BatchStatus, run_batch};
+pub use codecs::{
+    ChromaSubsampling, Dither, EncoderSettings, OutputFormat, ToneMapType, get_encoder,
+};
+pub use color::{
+    ColorSpace, GamutMapping, HdrTransfer, LogShaper, LutInterpolation, LutSettings,
+    TransferFunction,
+};
+pub use contact_sheet::{ContactSheet, SheetSource};
 pub use error::{Eq2cError, Result};
+pub use input::exr::ExrSelection;
 pub use layouts::{LayoutType, generate_layout};
+pub use local_tonemap::LocalToneMap;
 pub use math::CubeFace;
+pub use output::{OutputSet, Overwrite};
+pub use paths::SizeNaming;
+pub use pipeline::{
+    Conversion, ConversionReport, ConvertOptions, Encoded, EncodedImage, convert,
+    convert_with_progress,
+};
+pub use progress::{NoProgress, Phase, Progress};
+pub use recipe::Recipe;
+pub use sanitize::{InvalidPixels, SanitizeMode};
+pub use watch::FileWatcher;
 
-use std::path::PathBuf;
+use image::Rgba32FImage;
+use input::exr::ExrFile;
+use log::{debug, info, warn};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Config {
     pub input: PathBuf,
+    /// Transfer function of integer (LDR) inputs; `None` picks sRGB for integer images and
+    /// linear for float images.
+    pub input_transfer: Option<TransferFunction>,
+    /// Layer and channel selection for OpenEXR inputs.
+    pub exr: ExrSelection,
+    /// Convert every layer of an OpenEXR input; each layer is written next to `output` with the
+    /// layer name appended.
+    pub all_layers: bool,
     pub output: PathBuf,
-    pub format: OutputFormat,
-    pub layout: LayoutType,
-    pub tonemap: ToneMapType,
-    pub exposure: f32,
-    pub size: u32,
+    /// `.cube` 3D LUT applied by the LDR encoders before quantization.
+    pub lut: Option<PathBuf>,
+    pub lut_interpolation: LutInterpolation,
+    /// Feed the LUT log2-encoded scene values instead of tone-mapped display values.
+    pub lut_shaper: Option<LogShaper>,
+    /// Processing and encoding options; `options.lut` is filled in from `lut` by [`run`].
+    pub options: ConvertOptions,
+    /// Outputs rendered from the one loaded image instead of `options`; `output` is then the
+    /// directory the recipe's paths are relative to.
+    pub recipe: Option<Recipe>,
+    /// Render every one of these face sizes (instead of `options.size`) from the one loaded image,
+    /// into outputs told apart by `size_naming`. Leave empty for a single size.
+    pub sizes: Vec<u32>,
+    pub size_naming: SizeNaming,
+    /// Existing output files are replaced with a warning by default. Outputs are staged and only
+    /// moved into place once the whole run has succeeded.
+    pub overwrite: Overwrite,
+}
+
+impl Config {
+    /// The files [`run`] writes, or `None` if that depends on the input (`all_layers`).
+    pub fn output_paths(&self) -> Option<Vec<PathBuf>> {
+        if self.all_layers {
+            return None;
+        }
+
+        if let Some(recipe) = &self.recipe {
+            let mut paths = Vec::new();
+            for target in &recipe.targets {
+                let output = self.recipe_output(target).ok()?;
+                paths.extend(output_files(&output, &target.options));
+            }
+            return Some(paths);
+        }
+
+        if !self.sizes.is_empty() {
+            return Some(
+                self.sizes
+                    .iter()
+                    .flat_map(|&size| {
+                        let output = paths::sized_path(&self.output, size, self.size_naming);
+                        output_files(&output, &self.options)
+                    })
+                    .collect(),
+            );
+        }
+
+        Some(output_files(&self.output, &self.options))
+    }
+
+    /// Where a recipe target is written.
+    fn recipe_output(&self, target: &recipe::RecipeTarget) -> Result<PathBuf> {
+        let name = batch::output_name(
+            &target.path,
+            &self.input,
+            target.options.format,
+            target.options.effective_layout(),
+            target.options.size,
+        )?;
+        Ok(self.output.join(name))
+    }
+}
+
+/// The files written for `output` with `options`: one, or one per face.
+fn output_files(output: &Path, options: &ConvertOptions) -> Vec<PathBuf> {
+    if options.contact_sheet.is_none() && options.effective_layout() == LayoutType::Separate {
+        return CubeFace::ALL
+            .iter()
+            .map(|&face| paths::append_suffix(output, paths::face_suffix(face)))
+            .collect();
+    }
+
+    vec![output.to_path_buf()]
+}
+
+/// A file written by [`run`].
+#[derive(Debug, Clone)]
+pub struct WrittenFile {
+    /// `-` for stdout; faces streamed to stdout are named by their tar entry.
+    pub path: PathBuf,
+    pub bytes: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Time spent converting one source image, step by step.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StepTimings {
+    pub analyze: Duration,
+    pub layout: Duration,
+    pub encode: Duration,
+    pub write: Duration,
+}
+
+/// What [`run`] did with one source image; `all_layers` produces one per EXR layer.
+#[derive(Debug, Clone)]
+pub struct SourceReport {
+    /// EXR layer name, with `all_layers`.
+    pub layer: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub conversion: ConversionReport,
+    pub files: Vec<WrittenFile>,
+    pub timings: StepTimings,
+}
+
+/// Result of [`run`].
+#[derive(Debug, Clone)]
+pub struct RunReport {
+    /// Time spent reading and decoding the input.
+    pub load: Duration,
+    pub sources: Vec<SourceReport>,
 }
 
 /// Runs the export pipeline: loads the input image, optionally reports very high brightness,
 /// generates the chosen layout, encodes the resulting buffer(s) with the selected tone-mapping
 /// and exposure, and writes output files.
 ///
-/// On success, the function completes after writing one or more output files. On failure, an error
-/// is returned (for example, if the image cannot be opened or encoding fails).
+/// This is a thin file-based wrapper around the in-memory steps in [`pipeline`]; use
+/// [`convert`] to work with buffers directly. Messages go to the [`log`] facade, so nothing is
+/// printed unless a logger is installed.
+///
+/// On success, the function returns a [`RunReport`] after writing one or more output files. On
+/// failure, an error is returned (for example, if the image cannot be opened or encoding fails).
 ///
 /// # Examples
 ///
@@ -36,66 +194,554 @@ pub struct Config {
 /// // Build a Config for your run (fill fields appropriately)
 /// let config = Config {
 ///     input: std::path::PathBuf::from("input.hdr"),
+///     input_transfer: None,
+///     exr: ExrSelection::default(),
+///     all_layers: false,
 ///     output: std::path::PathBuf::from("output.png"),
-///     format: OutputFormat::Png,
-///     layout: LayoutType::Equirectangular,
-///     tonemap: ToneMapType::Reinhard,
-///     exposure: 1.0,
-///     size: 1024,
+///     lut: None,
+///     lut_interpolation: LutInterpolation::Tetrahedral,
+///     lut_shaper: None,
+///     recipe: None,
+///     sizes: Vec::new(),
+///     size_naming: SizeNaming::Suffix,
+///     overwrite: Overwrite::Warn,
+///     options: ConvertOptions {
+///         input_space: ColorSpace::AcesCg,
+///         sanitize: Some(SanitizeMode::Inpaint),
+///         layout: LayoutType::Equirectangular,
+///         tonemap: ToneMapType::Reinhard,
+///         gamut_mapping: GamutMapping::Desaturate,
+///         dither: Dither::BlueNoise,
+///         size: 1024,
+///         ..Default::default()
+///     },
 /// };
 ///
 /// // Execute the pipeline
 /// run(config).unwrap();
 /// ```
-pub fn run(config: Config) -> Result<()> {
-    use rayon::prelude::*;
-
-    println!("Loading {}...", config.input.display());
-    let img = image::open(&config.input)?.into_rgb32f();
-
-    let raw_pixels = img.as_raw();
-    if !raw_pixels.is_empty() {
-        let max_brightness = raw_pixels
-            .par_iter()
-            .cloned()
-            .reduce(|| 0.0f32, |a, b| a.max(b));
-
-        if max_brightness > 10.0 {
-            let suggested = 1.0 / (max_brightness * 0.1);
-            println!(
-                "Note: Max Brightness = {:.2}. Recommended exposure: ~{:.4}",
-                max_brightness, suggested
-            );
+pub fn run(config: Config) -> Result<RunReport> {
+    run_with_progress(config, &NoProgress)
+}
+
+/// Like [`run`], reporting the load, analyze, layout and encode phases to `progress`.
+///
+/// # Errors
+///
+/// See [`run`].
+pub fn run_with_progress(mut config: Config, progress: &dyn Progress) -> Result<RunReport> {
+    info!("Loading {}...", config.input.display());
+
+    // Stdin is checked by its magic number once it has been read (see `open_exr`).
+    let is_exr = paths::is_stdio(&config.input) || has_exr_extension(&config.input);
+
+    if (config.all_layers || !config.exr.is_default()) && !is_exr {
+        return Err(not_exr());
+    }
+    if config.all_layers && config.recipe.is_some() {
+        return Err(Eq2cError::ArgumentError(
+            "recipes cannot be combined with all layers".to_string(),
+        ));
+    }
+    if paths::is_stdio(&config.output)
+        && (config.all_layers || config.recipe.is_some() || !config.sizes.is_empty())
+    {
+        return Err(Eq2cError::ArgumentError(
+            "stdout takes a single output; all layers, recipes and several sizes need files"
+                .to_string(),
+        ));
+    }
+    if !config.sizes.is_empty()
+        && (config.all_layers || config.recipe.is_some() || config.options.contact_sheet.is_some())
+    {
+        return Err(Eq2cError::ArgumentError(
+            "several sizes cannot be combined with all layers, a recipe or a contact sheet"
+                .to_string(),
+        ));
+    }
+    check_sizes(&config.sizes)?;
+
+    // Check the options before the (possibly slow) LUT parse; the LUT only adds constraints that
+    // are re-checked below.
+    config.options.validate()?;
+
+    if let Some(path) = &config.lut {
+        let lut = color::Lut3D::open(path)?;
+        info!(
+            "Loaded {}x{}x{} LUT {}",
+            lut.size,
+            lut.size,
+            lut.size,
+            lut.title.as_deref().unwrap_or_default()
+        );
+        config.options.lut = Some(LutSettings {
+            lut: Arc::new(lut),
+            interpolation: config.lut_interpolation,
+            shaper: config.lut_shaper,
+        });
+        config.options.validate()?;
+    }
+    if let Some(recipe) = &mut config.recipe {
+        recipe.set_lut(config.options.lut.clone());
+    }
+
+    // Fail before the conversion when the outputs are known up front; every file is checked
+    // again as it is written.
+    for path in config.output_paths().unwrap_or_default() {
+        if !paths::is_stdio(&path) {
+            config.overwrite.check(&path)?;
+        }
+    }
+
+    let mut outputs = OutputSet::new(config.overwrite);
+    let report = convert_sources(&config, &mut outputs, progress)?;
+    outputs.commit()?;
+    Ok(report)
+}
+
+/// Loads the input and converts every source image of it, staging the files in `outputs`.
+fn convert_sources(
+    config: &Config,
+    outputs: &mut OutputSet,
+    progress: &dyn Progress,
+) -> Result<RunReport> {
+    if config.all_layers {
+        let start = Instant::now();
+        progress.start(Phase::Load, 1);
+        let layers = open_exr(&config.input)?.extract_all()?;
+        progress.advance(Phase::Load, 1);
+        progress.finish(Phase::Load);
+        let load = start.elapsed();
+
+        let suffixes = paths::unique_components(layers.iter().map(|(layer, _)| layer.as_str()));
+        let mut sources = Vec::with_capacity(layers.len());
+        for ((layer, img), suffix) in layers.into_iter().zip(suffixes) {
+            info!("Layer '{}':", layer);
+            let output = if layer.is_empty() {
+                config.output.clone()
+            } else {
+                paths::append_suffix(&config.output, &suffix)
+            };
+            let mut source = convert_to_file(img, &config.options, &output, outputs, progress)?;
+            source.layer = Some(layer);
+            sources.push(source);
+        }
+        return Ok(RunReport { load, sources });
+    }
+
+    let start = Instant::now();
+    progress.start(Phase::Load, 1);
+    let img = load_input(&config.input, config.input_transfer, &config.exr)?;
+    progress.advance(Phase::Load, 1);
+    progress.finish(Phase::Load);
+    let load = start.elapsed();
+
+    if let Some(recipe) = &config.recipe {
+        let paths = recipe
+            .targets
+            .iter()
+            .map(|target| config.recipe_output(target))
+            .collect::<Result<Vec<_>>>()?;
+        let targets: Vec<ConvertOptions> =
+            recipe.targets.iter().map(|t| t.options.clone()).collect();
+
+        info!("Rendering {} recipe outputs...", targets.len());
+        let sources = convert_targets_to_files(img, &targets, &paths, outputs, progress)?;
+        return Ok(RunReport { load, sources });
+    }
+
+    if !config.sizes.is_empty() {
+        let paths: Vec<PathBuf> = config
+            .sizes
+            .iter()
+            .map(|&size| paths::sized_path(&config.output, size, config.size_naming))
+            .collect();
+        let targets: Vec<ConvertOptions> = config
+            .sizes
+            .iter()
+            .map(|&size| ConvertOptions {
+                size,
+                ..config.options.clone()
+            })
+            .collect();
+
+        info!("Rendering sizes {:?}...", config.sizes);
+        let sources = convert_targets_to_files(img, &targets, &paths, outputs, progress)?;
+        return Ok(RunReport { load, sources });
+    }
+
+    let source = convert_to_file(img, &config.options, &config.output, outputs, progress)?;
+    Ok(RunReport {
+        load,
+        sources: vec![source],
+    })
+}
+
+/// Rejects a zero or repeated size in [`Config::sizes`]; each size must name its own outputs.
+fn check_sizes(sizes: &[u32]) -> Result<()> {
+    for (i, size) in sizes.iter().enumerate() {
+        if *size == 0 {
+            return Err(Eq2cError::ArgumentError(
+                "face size must be at least 1 pixel".to_string(),
+            ));
+        }
+        if sizes[..i].contains(size) {
+            return Err(Eq2cError::ArgumentError(format!(
+                "face size {} is given more than once",
+                size
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Loads an equirectangular image and computes its [`AnalysisReport`].
+///
+/// Integer inputs are linearized with `input_transfer` (see [`Config::input_transfer`]), and
+/// luminance is weighted for the primaries of `input_space`. For OpenEXR inputs, `exr` picks
+/// the layer or channels to analyze (see [`Config::exr`]).
+///
+/// # Errors
+///
+/// Returns `Eq2cError::ArgumentError` if `exr` selects a layer or channels of an input that is
+/// not OpenEXR, and propagates errors from loading the image.
+pub fn analyze_file(
+    input: &Path,
+    input_transfer: Option<TransferFunction>,
+    input_space: ColorSpace,
+    exr: &ExrSelection,
+) -> Result<AnalysisReport> {
+    let img = load_input(input, input_transfer, exr)?;
+    Ok(analyze(&img, input_space.luminance()))
+}
+
+/// Loads `path`, or stdin (detecting the format from its content) if it is `-`. A non-default
+/// `selection` reads that layer or those channels of an OpenEXR input.
+fn load_input(
+    path: &Path,
+    transfer: Option<TransferFunction>,
+    selection: &ExrSelection,
+) -> Result<Rgba32FImage> {
+    if !selection.is_default() {
+        return open_exr(path)?.extract(selection);
+    }
+    if !paths::is_stdio(path) {
+        return input::load_image(path, transfer);
+    }
+
+    input::load_image_from_memory(&read_stdin()?, transfer)
+}
+
+/// Opens an OpenEXR file for layer selection, or reads one from stdin if `path` is `-`.
+fn open_exr(path: &Path) -> Result<ExrFile> {
+    if !paths::is_stdio(path) {
+        if !has_exr_extension(path) {
+            return Err(not_exr());
         }
+        return ExrFile::open(path);
+    }
+
+    let data = read_stdin()?;
+    if !input::is_exr(&data) {
+        return Err(not_exr());
     }
+    ExrFile::from_memory(&data)
+}
 
-    println!("Generating layout...");
+fn read_stdin() -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    std::io::stdin().lock().read_to_end(&mut data)?;
+    debug!("Read {} bytes from stdin", data.len());
+    Ok(data)
+}
+
+fn has_exr_extension(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("exr"))
+}
 
-    let layout_output = generate_layout(config.layout, &img, config.size);
+fn not_exr() -> Eq2cError {
+    Eq2cError::ArgumentError("layer and channel selection require an OpenEXR input".to_string())
+}
+
+/// Converts one loaded source image and writes the result next to `output`, logging each step.
+fn convert_to_file(
+    mut img: Rgba32FImage,
+    options: &ConvertOptions,
+    output: &Path,
+    outputs: &mut OutputSet,
+    progress: &dyn Progress,
+) -> Result<SourceReport> {
+    let (width, height) = img.dimensions();
+    debug!("Input is {}x{}", width, height);
+    let mut timings = StepTimings::default();
+    let mut files = Vec::new();
+
+    let target = options.target_space();
+    if options.input_space != target {
+        info!("Converting {:?} -> {:?}...", options.input_space, target);
+    }
 
-    println!(
-        "Encoding to output (Tone Map: {:?}, Exposure: {})...",
-        config.tonemap, config.exposure
-    );
+    let start = Instant::now();
+    let report = pipeline::prepare(&mut img, options, progress)?;
+    timings.analyze = start.elapsed();
+    log_report(&report, options);
 
-    let encoder = get_encoder(config.format, config.tonemap, config.exposure);
+    let source = |files, timings| SourceReport {
+        layer: None,
+        width,
+        height,
+        conversion: report,
+        files,
+        timings,
+    };
+
+    if let Some(sheet) = &options.contact_sheet {
+        info!("Rendering contact sheet...");
+        let start = Instant::now();
+        let image = pipeline::encode_contact_sheet(&img, options, sheet, &report, progress)?;
+        timings.encode = start.elapsed();
+
+        let start = Instant::now();
+        files.push(write_output(output, &image, outputs)?);
+        timings.write = start.elapsed();
+        info!("Success! Saved contact sheet to {}", output.display());
+        return Ok(source(files, timings));
+    }
+
+    info!("Generating layout...");
+    if let Some(local) = &options.local_tonemap {
+        info!(
+            "Applying local tonemap (contrast {} stops, detail {}, radius {})...",
+            local.contrast, local.detail, local.radius
+        );
+    }
+    let start = Instant::now();
+    let layout = pipeline::render_layout(&img, options, options.effective_layout(), progress);
+    timings.layout = start.elapsed();
+    drop(img);
+
+    match options.hdr {
+        Some(hdr) => info!(
+            "Encoding to output (HDR: {:?}, Peak: {} nits, Exposure: {})...",
+            hdr, options.peak_nits, report.exposure
+        ),
+        None => info!(
+            "Encoding to output (Tone Map: {}, Exposure: {}, Transfer: {:?})...",
+            options.tonemap, report.exposure, options.output_transfer
+        ),
+    }
+
+    let start = Instant::now();
+    let encoded = pipeline::encode_layout(&layout, options, &report, progress)?;
+    timings.encode = start.elapsed();
+
+    let start = Instant::now();
+    files.extend(write_encoded(output, encoded, options.format, outputs)?);
+    timings.write = start.elapsed();
+
+    Ok(source(files, timings))
+}
+
+/// Converts one loaded source image with each of `targets` (see [`recipe::convert_targets`]) and
+/// writes each to its entry of `outputs`, one [`SourceReport`] per target.
+fn convert_targets_to_files(
+    img: Rgba32FImage,
+    targets: &[ConvertOptions],
+    paths: &[PathBuf],
+    outputs: &mut OutputSet,
+    progress: &dyn Progress,
+) -> Result<Vec<SourceReport>> {
+    let (width, height) = img.dimensions();
+    debug!("Input is {}x{}", width, height);
+
+    let converted = recipe::convert_targets(img, targets, progress)?;
+
+    let mut sources = Vec::with_capacity(converted.len());
+    for ((options, output), converted) in targets.iter().zip(paths).zip(converted) {
+        let report = converted.conversion.report;
+        log_report(&report, options);
+
+        let mut timings = converted.timings;
+        let start = Instant::now();
+        let files = write_encoded(output, converted.conversion.output, options.format, outputs)?;
+        timings.write = start.elapsed();
+
+        sources.push(SourceReport {
+            layer: None,
+            width,
+            height,
+            conversion: report,
+            files,
+            timings,
+        });
+    }
 
-    match layout_output {
-        layouts::LayoutOutput::Single(buffer) => {
-            encoder.encode(&buffer, &config.output)?;
-            println!("Success! Saved to {}", config.output.display());
+    Ok(sources)
+}
+
+/// Writes an encoded output to `output`, or next to it with a face suffix per face. Faces
+/// written to stdout (`-`) go into one tar stream.
+fn write_encoded(
+    output: &Path,
+    encoded: Encoded,
+    format: OutputFormat,
+    outputs: &mut OutputSet,
+) -> Result<Vec<WrittenFile>> {
+    match encoded {
+        Encoded::Single(image) => {
+            let file = write_output(output, &image, outputs)?;
+            info!("Success! Saved to {}", output.display());
+            Ok(vec![file])
         }
 
-        layouts::LayoutOutput::Frames(faces) => {
-            for (face, buffer) in faces {
+        Encoded::Faces(faces) if paths::is_stdio(output) => {
+            write_tar(std::io::stdout().lock(), faces, format)
+        }
+
+        Encoded::Faces(faces) => {
+            let mut files = Vec::with_capacity(faces.len());
+            for (face, image) in faces {
                 let suffix = paths::face_suffix(face);
-                let new_path = paths::append_suffix(&config.output, suffix);
+                let new_path = paths::append_suffix(output, suffix);
 
-                encoder.encode(&buffer, &new_path)?;
-                println!("Saved {}", new_path.display());
+                files.push(write_output(&new_path, &image, outputs)?);
+                info!("Saved {}", new_path.display());
             }
+            Ok(files)
         }
     }
+}
 
-    Ok(())
+fn write_output(path: &Path, image: &EncodedImage, outputs: &mut OutputSet) -> Result<WrittenFile> {
+    if paths::is_stdio(path) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&image.data)?;
+        stdout.flush()?;
+    } else {
+        outputs.write(path, &image.data)?;
+    }
+    debug!("Wrote {} bytes to {}", image.data.len(), path.display());
+
+    Ok(WrittenFile {
+        path: path.to_path_buf(),
+        bytes: image.data.len() as u64,
+        width: image.width,
+        height: image.height,
+    })
+}
+
+/// Streams faces to `writer` (stdout) as a tar archive with one `<face>.<ext>` entry each
+/// (e.g. `px.png`); the written files are reported by entry name.
+fn write_tar(
+    writer: impl Write,
+    faces: Vec<(CubeFace, EncodedImage)>,
+    format: OutputFormat,
+) -> Result<Vec<WrittenFile>> {
+    let mtime = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let mut archive = tar::Builder::new(writer);
+    let mut files = Vec::with_capacity(faces.len());
+
+    for (face, image) in faces {
+        let name = format!("{}.{}", paths::face_suffix(face), format.extension());
+        let mut header = tar::Header::new_ustar();
+        header.set_size(image.data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        archive.append_data(&mut header, &name, image.data.as_slice())?;
+        debug!(
+            "Wrote {} bytes to the archive as {}",
+            image.data.len(),
+            name
+        );
+
+        files.push(WrittenFile {
+            path: PathBuf::from(name),
+            bytes: image.data.len() as u64,
+            width: image.width,
+            height: image.height,
+        });
+    }
+
+    archive.into_inner()?.flush()?;
+    Ok(files)
+}
+
+/// Logs the sanitize outcome and the exposure decision of [`pipeline::prepare`].
+fn log_report(report: &ConversionReport, options: &ConvertOptions) {
+    if !report.invalid.is_empty() {
+        match options.sanitize {
+            Some(mode) if report.sanitized => info!("Sanitized {} ({:?})", report.invalid, mode),
+            _ => warn!(
+                "input has {}; use --sanitize to repair them",
+                report.invalid
+            ),
+        }
+    }
+
+    debug!("Max brightness {:.4}", report.max_brightness);
+
+    match (&options.auto_exposure, report.measured_luminance) {
+        (Some(auto), Some(measured)) => info!(
+            "Auto exposure: {:.4} ({:?} luminance {:.4}, key {}, {:+} EV)",
+            report.exposure, auto.method, measured, auto.key, auto.compensation
+        ),
+        _ if report.max_brightness > 10.0 => {
+            let suggested = 1.0 / (report.max_brightness * 0.1);
+            info!(
+                "Note: Max Brightness = {:.2}. Recommended exposure: ~{:.4}",
+                report.max_brightness, suggested
+            );
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_sizes() {
+        assert!(check_sizes(&[256, 512, 1024]).is_ok());
+        for sizes in [&[256, 512, 256][..], &[0, 512]] {
+            assert!(matches!(
+                check_sizes(sizes),
+                Err(Eq2cError::ArgumentError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_write_tar_round_trip() {
+        let faces = [CubeFace::Right, CubeFace::Back].map(|face| {
+            let image = EncodedImage {
+                data: paths::face_suffix(face).as_bytes().to_vec(),
+                width: 4,
+                height: 4,
+            };
+            (face, image)
+        });
+
+        let mut archive = Vec::new();
+        let files = write_tar(&mut archive, faces.to_vec(), OutputFormat::Png).unwrap();
+        assert_eq!(files[1].path, PathBuf::from("nz.png"));
+
+        let mut entries = Vec::new();
+        for entry in tar::Archive::new(archive.as_slice()).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut data = String::new();
+            entry.read_to_string(&mut data).unwrap();
+            entries.push((entry.path().unwrap().display().to_string(), data));
+        }
+        assert_eq!(
+            entries,
+            [
+                ("px.png".into(), "px".into()),
+                ("nz.png".into(), "nz".into())
+            ]
+        );
+    }
 }
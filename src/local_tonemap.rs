@@ -0,0 +1,306 @@
+use crate::image_utils::unpremultiply;
+use crate::layouts::{prefilter, render_face};
+use crate::math::CubeFace;
+use crate::progress::{Phase, Progress};
+
+use glam::Vec3;
+use image::Rgba32FImage;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Largest face size the base layer is computed at; bigger faces upsample it.
+const MAX_WORKING_SIZE: u32 = 512;
+/// Range sigma of the bilateral filter in stops (Durand & Dorsey use 0.4 in log10).
+const SIGMA_RANGE_STOPS: f32 = 1.33;
+/// Floor for log luminance so black pixels stay finite.
+const MIN_LUMINANCE: f32 = 1e-6;
+/// Percentiles of the base layer treated as its darkest and brightest values.
+const RANGE_PERCENTILES: (f32, f32) = (0.01, 0.99);
+
+/// Durand-style local tone mapping: a bilateral-filtered base layer of the log luminance is
+/// compressed while the detail layer on top of it is kept.
+///
+/// The base layer is filtered on padded face renders, so every face sees its neighbours across
+/// the cube edges and the result stays seamless.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LocalToneMap {
+    /// Dynamic range, in stops, the base layer is compressed to (never expanded).
+    pub contrast: f32,
+    /// Detail layer multiplier; 1.0 keeps local detail as is.
+    pub detail: f32,
+    /// Spatial sigma of the filter as a fraction of the face size.
+    pub radius: f32,
+}
+
+impl Default for LocalToneMap {
+    fn default() -> Self {
+        Self {
+            contrast: 6.0,
+            detail: 1.0,
+            radius: 0.02,
+        }
+    }
+}
+
+impl LocalToneMap {
+    /// Checks that the contrast, detail and radius are positive.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the offending parameter.
+    pub fn validate(&self) -> Result<(), String> {
+        let params = [
+            ("contrast", self.contrast),
+            ("detail", self.detail),
+            ("radius", self.radius),
+        ];
+        match params
+            .into_iter()
+            .find(|(_, value)| !(value.is_finite() && *value > 0.0))
+        {
+            Some((key, _)) => Err(format!("local tonemap needs a positive '{}'", key)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Log2 luminance of a padded face, filtered into its base layer.
+struct BaseLayer {
+    size: usize,
+    pad: usize,
+    /// Working (unpadded) face size.
+    working: usize,
+    values: Vec<f32>,
+}
+
+impl BaseLayer {
+    /// Samples the base layer at normalized face coordinates (0-1 inside the face).
+    fn sample(&self, u: f32, v: f32) -> f32 {
+        let x =
+            (u * self.working as f32 - 0.5 + self.pad as f32).clamp(0.0, (self.size - 1) as f32);
+        let y =
+            (v * self.working as f32 - 0.5 + self.pad as f32).clamp(0.0, (self.size - 1) as f32);
+
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.size - 1), (y0 + 1).min(self.size - 1));
+        let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+        let at = |x: usize, y: usize| self.values[y * self.size + x];
+        let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+        let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+
+        top + (bottom - top) * ty
+    }
+
+    /// Values inside the face, without the padding.
+    fn interior(&self) -> impl Iterator<Item = f32> + '_ {
+        (self.pad..self.pad + self.working).flat_map(move |y| {
+            let row = y * self.size;
+            self.values[row + self.pad..row + self.pad + self.working]
+                .iter()
+                .copied()
+        })
+    }
+}
+
+fn log_luminance(p: &[f32], luminance: Vec3) -> f32 {
+    let [r, g, b, _] = unpremultiply([p[0], p[1], p[2], p[3]]);
+    Vec3::new(r, g, b).dot(luminance).max(MIN_LUMINANCE).log2()
+}
+
+/// Separable bilateral filter (horizontal, then vertical) of a square grid.
+fn bilateral(values: &[f32], size: usize, sigma: f32) -> Vec<f32> {
+    let radius = (2.0 * sigma).ceil() as isize;
+    let spatial: Vec<f32> = (-radius..=radius)
+        .map(|k| (-(k * k) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let range_denominator = 2.0 * SIGMA_RANGE_STOPS * SIGMA_RANGE_STOPS;
+
+    let pass = |input: &[f32], stride: usize, step: usize| {
+        let mut output = vec![0.0; input.len()];
+        output
+            .par_chunks_mut(size)
+            .enumerate()
+            .for_each(|(line, out)| {
+                for (i, out) in out.iter_mut().enumerate() {
+                    // `line` walks rows for the horizontal pass and columns for the vertical one.
+                    let index = |i: usize| line * stride + i * step;
+                    let center = input[index(i)];
+                    let (mut sum, mut weight) = (0.0, 0.0);
+
+                    for (k, w_s) in (-radius..=radius).zip(&spatial) {
+                        let j = (i as isize + k).clamp(0, size as isize - 1) as usize;
+                        let value = input[index(j)];
+                        let diff = value - center;
+                        let w = w_s * (-(diff * diff) / range_denominator).exp();
+                        sum += w * value;
+                        weight += w;
+                    }
+
+                    *out = sum / weight;
+                }
+            });
+        output
+    };
+
+    let horizontal = pass(values, size, 1);
+    let vertical = pass(&horizontal, 1, size);
+
+    // The vertical pass wrote column `x` into row `x`; transpose back.
+    let mut base = vec![0.0; values.len()];
+    for y in 0..size {
+        for x in 0..size {
+            base[y * size + x] = vertical[x * size + y];
+        }
+    }
+    base
+}
+
+impl LocalToneMap {
+    /// Renders the six faces of `source` at `face_size` with the local operator applied.
+    ///
+    /// `luminance` are the luminance weights of the image's colour space. The output keeps the
+    /// premultiplied linear format, so any global tonemap still runs afterwards. `progress` is
+    /// advanced as each final face is done.
+    pub fn render_faces(
+        &self,
+        source: &Rgba32FImage,
+        face_size: u32,
+        luminance: Vec3,
+        progress: &dyn Progress,
+    ) -> Vec<(CubeFace, Rgba32FImage)> {
+        let working = face_size.min(MAX_WORKING_SIZE);
+        let sigma = (self.radius * working as f32).max(0.5);
+        let pad = (2.0 * sigma).ceil() as u32;
+        let source = prefilter(source, face_size);
+        let working_source = prefilter(&source, working);
+
+        let bases: Vec<BaseLayer> = CubeFace::ALL
+            .par_iter()
+            .map(|&face| {
+                let padded = render_face(&working_source, face, working, pad);
+                let size = padded.width() as usize;
+                let log: Vec<f32> = padded
+                    .as_raw()
+                    .chunks_exact(4)
+                    .map(|p| log_luminance(p, luminance))
+                    .collect();
+
+                BaseLayer {
+                    size,
+                    pad: pad as usize,
+                    working: working as usize,
+                    values: bilateral(&log, size, sigma),
+                }
+            })
+            .collect();
+
+        // One anchor and compression factor for all faces, so they match at the seams.
+        let mut all: Vec<f32> = bases.iter().flat_map(BaseLayer::interior).collect();
+        let anchor = all.iter().sum::<f32>() / all.len().max(1) as f32;
+        let low = percentile(&mut all, RANGE_PERCENTILES.0);
+        let high = percentile(&mut all, RANGE_PERCENTILES.1);
+        let compression = if high - low > self.contrast {
+            self.contrast / (high - low)
+        } else {
+            1.0
+        };
+
+        let done = AtomicUsize::new(0);
+        CubeFace::ALL
+            .into_par_iter()
+            .zip(bases)
+            .map(|(face, base)| {
+                let mut buffer = render_face(&source, face, face_size, 0);
+
+                buffer
+                    .par_chunks_mut(4 * face_size as usize)
+                    .enumerate()
+                    .for_each(|(y, row)| {
+                        let v = (y as f32 + 0.5) / face_size as f32;
+                        for (x, p) in row.chunks_exact_mut(4).enumerate() {
+                            let u = (x as f32 + 0.5) / face_size as f32;
+                            let log = log_luminance(p, luminance);
+                            let b = base.sample(u, v);
+                            let target =
+                                anchor + (b - anchor) * compression + (log - b) * self.detail;
+                            let gain = (target - log).exp2();
+
+                            p[0] *= gain;
+                            p[1] *= gain;
+                            p[2] *= gain;
+                        }
+                    });
+
+                progress.advance(Phase::Layout, done.fetch_add(1, Ordering::Relaxed) + 1);
+                (face, buffer)
+            })
+            .collect()
+    }
+}
+
+/// The value at fraction `q` (0-1) of `values`, reordering them, or 0 if there are none.
+fn percentile(values: &mut [f32], q: f32) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    let index = ((values.len() - 1) as f32 * q).round() as usize;
+    *values.select_nth_unstable_by(index, f32::total_cmp).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+
+    const LUMA: Vec3 = Vec3::new(0.2126, 0.7152, 0.0722);
+
+    /// Equirect with a bright upper hemisphere and a dark lower one, plus a sun.
+    fn test_sky() -> Rgba32FImage {
+        Rgba32FImage::from_fn(64, 32, |x, y| {
+            let v = if (20..24).contains(&x) && (8..11).contains(&y) {
+                2000.0
+            } else if y < 16 {
+                4.0
+            } else {
+                0.02
+            };
+            image::Rgba([v, v, v, 1.0])
+        })
+    }
+
+    #[test]
+    fn test_compresses_range() {
+        let faces = LocalToneMap::default().render_faces(&test_sky(), 16, LUMA, &NoProgress);
+        let face = |f: CubeFace| &faces.iter().find(|(face, _)| *face == f).unwrap().1;
+
+        // The upper rows of the equirect project onto the -Y face.
+        let sky = face(CubeFace::Bottom).get_pixel(8, 8)[1];
+        let ground = face(CubeFace::Top).get_pixel(8, 8)[1];
+
+        // The 7.6 stop sky/ground difference is squeezed into the 6 stop budget.
+        assert!((sky / ground).log2() < 6.5, "{sky} / {ground}");
+        assert!(sky > ground);
+    }
+
+    #[test]
+    fn test_seamless_across_faces() {
+        let faces = LocalToneMap::default().render_faces(&test_sky(), 16, LUMA, &NoProgress);
+        let plain = crate::layouts::render_all_faces(&test_sky(), 16);
+        let gain = |f: CubeFace, x: u32, y: u32| {
+            let a = &faces.iter().find(|(face, _)| *face == f).unwrap().1;
+            let b = &plain.iter().find(|(face, _)| *face == f).unwrap().1;
+            a.get_pixel(x, y)[1] / b.get_pixel(x, y)[1]
+        };
+
+        // Front's right edge meets Right's left edge.
+        for y in 0..16 {
+            let front = gain(CubeFace::Front, 15, y);
+            let right = gain(CubeFace::Right, 0, y);
+            assert!(
+                (front / right).log2().abs() < 0.25,
+                "row {y}: {front} vs {right}"
+            );
+        }
+    }
+}
@@ -1,8 +1,17 @@
-use clap::{Parser, ValueEnum};
-use std::path::PathBuf;
-use std::time::Instant;
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{Level, LevelFilter, Log, Metadata, Record, error, info, warn};
+use serde_json::{Value, json};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-use eq2c::{self, Eq2cError, codecs::ToneMapType};
+use eq2c::{
+    self, AutoExposure, BatchStatus, ChromaSubsampling, ColorSpace, ContactSheet, Dither,
+    Eq2cError, ExposureMethod, FileWatcher, GamutMapping, HdrTransfer, LocalToneMap, LogShaper,
+    LutInterpolation, Overwrite, Phase, Progress, SanitizeMode, SheetSource, SizeNaming,
+    TransferFunction, codecs::ToneMapType,
+};
 
 #[derive(Parser)]
 #[command(
@@ -13,14 +22,106 @@ use eq2c::{self, Eq2cError, codecs::ToneMapType};
         # Convert HDR to 6 Separate PNGs\n  \
         eq2c -i input.exr -o skybox.png --layout separate\n\n  \
         # Convert to Horizontal Strip\n  \
-        eq2c -i input.hdr -o strip.png --layout strip-h"
+        eq2c -i input.hdr -o strip.png --layout strip-h\n\n  \
+        # Web preview as six 4:4:4 JPEG faces\n  \
+        eq2c -i input.hdr -o sky.jpg -f jpeg --quality 85 --per-face\n\n  \
+        # HDR10 AVIF cross for a 1000 nit display\n  \
+        eq2c -i input.exr -o sky.avif -f avif --hdr pq --peak-nits 1000\n\n  \
+        # Compare every tonemap at -2, 0 and +2 EV\n  \
+        eq2c -i input.hdr -o sheet.png --contact-sheet --bracket -2,0,2\n\n  \
+        # LOD set: 256, 512, 1024 and 2048 pixel faces from one decode\n  \
+        eq2c -i input.exr -o sky.png --size 256,512,1024,2048 --size-naming directory\n\n  \
+        # Convert a whole directory, four images at a time\n  \
+        eq2c -i hdris/ -O skyboxes/ -f jpeg --name-template '{stem}-{size}.{ext}' -j 4\n\n  \
+        # Stream through a pipe, faces as a tar archive\n  \
+        curl -s https://example.com/sky.hdr | eq2c -i - -o - -f exr --layout separate | tar x\n\n  \
+        # Inspect an HDRI before converting it\n  \
+        eq2c analyze input.exr --json",
+    subcommand_negates_reqs = true
 )]
 struct Cli {
-    #[arg(short, long)]
-    input: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Only print warnings and errors, and no progress bar
+    #[arg(long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print more details (repeat for even more)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Input image (`-` for stdin); with --output-dir any number of files, directories and glob
+    /// patterns
+    #[arg(short, long, required = true, num_args = 1..)]
+    input: Vec<PathBuf>,
+
+    /// Transfer function of integer inputs (default: srgb for 8/16-bit images, linear for float)
+    #[arg(long, value_enum)]
+    input_transfer: Option<TransferFunction>,
+
+    /// Color space (primaries and white point) of the input
+    #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+    input_space: ColorSpace,
+
+    /// Repair NaN, infinite and negative input values before projection
+    #[arg(long, value_enum)]
+    sanitize: Option<SanitizeMode>,
+
+    /// Fail if the input contains NaN, infinite or negative values
+    #[arg(long, conflicts_with = "sanitize")]
+    strict: bool,
+
+    /// EXR layer (or part) to read instead of the default RGB
+    #[arg(long)]
+    layer: Option<String>,
+
+    /// EXR channels mapped to red, green and blue, e.g. `diffuse.R,diffuse.G,diffuse.B`
+    #[arg(long, value_parser = parse_channels)]
+    channels: Option<[String; 3]>,
+
+    /// Convert every EXR layer into its own set of outputs
+    #[arg(long, conflicts_with_all = ["layer", "channels"])]
+    all_layers: bool,
+
+    /// Output file, or `-` for stdout (separate faces as a tar stream)
+    #[arg(
+        short,
+        long,
+        required_unless_present = "output_dir",
+        conflicts_with = "output_dir"
+    )]
+    output: Option<PathBuf>,
 
-    #[arg(short, long)]
-    output: PathBuf,
+    /// Batch mode: convert every input into this directory
+    #[arg(short = 'O', long)]
+    output_dir: Option<PathBuf>,
+
+    /// Output file name in batch mode; placeholders: {stem}, {ext}, {layout}, {size}
+    #[arg(long, default_value = eq2c::batch::DEFAULT_NAME_TEMPLATE, requires = "output_dir")]
+    name_template: String,
+
+    /// Number of images converted at the same time in batch mode (each one already uses all
+    /// cores)
+    #[arg(short, long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..), requires = "output_dir")]
+    jobs: u16,
+
+    /// Convert every input in batch mode, even if its outputs are newer than the input
+    #[arg(long, requires = "output_dir")]
+    rebuild: bool,
+
+    /// Fail instead of replacing existing output files
+    #[arg(long, conflicts_with = "force")]
+    no_clobber: bool,
+
+    /// Replace existing output files without a warning
+    #[arg(long)]
+    force: bool,
+
+    /// TOML or JSON file listing several outputs rendered from one decode of each input, written
+    /// into --output-dir; settings an output leaves out come from the command line
+    #[arg(long, requires = "output_dir", conflicts_with_all = ["contact_sheet", "all_layers"])]
+    recipe: Option<PathBuf>,
 
     #[arg(short, long, value_enum, default_value_t = FormatArg::Png)]
     format: FormatArg,
@@ -28,20 +129,191 @@ struct Cli {
     #[arg(short, long, value_enum, default_value_t = LayoutArg::Cross)]
     layout: LayoutArg,
 
-    #[arg(short, long, value_enum, default_value_t = ToneMapType::Aces)]
+    /// Tonemap operator with optional parameters, e.g. `hable:white=8` or `agx:look=punchy`
+    /// (reinhard, reinhard-extended, aces, khronos, agx, hable, lottes, uchimura, none)
+    #[arg(short, long, default_value_t = ToneMapType::Aces)]
     tonemap: ToneMapType,
 
+    /// Compress the scene's range locally (bilateral base/detail), seamlessly across faces
+    #[arg(long)]
+    local_tonemap: bool,
+
+    /// Dynamic range in stops the local tonemap compresses the base layer to
+    #[arg(long, default_value_t = 6.0, requires = "local_tonemap")]
+    local_contrast: f32,
+
+    /// Local detail multiplier
+    #[arg(long, default_value_t = 1.0, requires = "local_tonemap")]
+    local_detail: f32,
+
+    /// Local tonemap filter radius as a fraction of the face size
+    #[arg(long, default_value_t = 0.02, requires = "local_tonemap")]
+    local_radius: f32,
+
+    /// Write a labeled grid of the layout (or the equirect) through every tonemap instead
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "layout")]
+    contact_sheet: Option<SheetSource>,
+
+    /// Exposure offsets in stops for the contact sheet, e.g. `-2,0,2`
+    #[arg(
+        long,
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        requires = "contact_sheet"
+    )]
+    bracket: Vec<f32>,
+
+    /// Width of one contact sheet tile in pixels
+    #[arg(long, default_value_t = 384, requires = "contact_sheet")]
+    sheet_width: u32,
+
     #[arg(short, long, default_value_t = 1.0)]
     exposure: f32,
 
-    #[arg(short, long, default_value_t = 512)]
-    size: u32,
+    /// Pick the exposure from the scene's solid-angle-weighted luminance
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "log-average",
+        conflicts_with = "exposure"
+    )]
+    auto_exposure: Option<ExposureMethod>,
+
+    /// Value the measured luminance is mapped to by auto exposure
+    #[arg(long, default_value_t = 0.18, value_parser = parse_key, requires = "auto_exposure")]
+    key: f32,
+
+    /// Luminance percentile (0-100) used by `--auto-exposure percentile`
+    #[arg(
+        long,
+        default_value_t = 50.0,
+        value_parser = parse_percentile,
+        requires = "auto_exposure"
+    )]
+    percentile: f32,
+
+    /// Exposure compensation in stops on top of auto exposure
+    #[arg(
+        long,
+        default_value_t = 0.0,
+        allow_hyphen_values = true,
+        requires = "auto_exposure"
+    )]
+    ev: f32,
+
+    /// Transfer function used to encode LDR outputs; anything but srgb needs PNG output
+    #[arg(long, value_enum, default_value_t = TransferFunction::Srgb)]
+    output_transfer: TransferFunction,
+
+    /// Color space to convert the output to
+    #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+    output_space: ColorSpace,
+
+    /// Handling of colors outside the output gamut after conversion
+    #[arg(long, value_enum, default_value_t = GamutMapping::None)]
+    gamut_mapping: GamutMapping,
+
+    /// 3D LUT (.cube) applied to LDR outputs after tone mapping
+    #[arg(long)]
+    lut: Option<PathBuf>,
+
+    /// Interpolation between LUT lattice points
+    #[arg(long, value_enum, default_value_t = LutInterpolation::Tetrahedral, requires = "lut")]
+    lut_interpolation: LutInterpolation,
+
+    /// Feed the LUT log2 scene values spanning MIN,MAX stops around 18% grey, e.g. `-6.5,6.5`
+    /// (replaces the tonemap and transfer function)
+    #[arg(long, value_parser = parse_log_shaper, allow_hyphen_values = true, requires = "lut")]
+    lut_shaper: Option<LogShaper>,
+
+    /// Write BT.2020 HDR with this curve instead of tone-mapped SDR (png, avif)
+    #[arg(long, value_enum)]
+    hdr: Option<HdrTransfer>,
+
+    /// Peak luminance of the HDR target display in nits
+    #[arg(long, default_value_t = 1000.0, requires = "hdr")]
+    peak_nits: f32,
+
+    /// Dithering applied when rounding LDR outputs to 8 bits
+    #[arg(long, value_enum, default_value_t = Dither::None)]
+    dither: Dither,
+
+    /// Face size in pixels; a comma-separated list renders every size from one decode
+    #[arg(short, long, value_delimiter = ',', default_value = "512", value_parser = clap::value_parser!(u32).range(1..))]
+    size: Vec<u32>,
+
+    /// How the outputs of several sizes are named: `sky_256.png` or `256/sky.png`
+    #[arg(long, value_enum, default_value_t = SizeNaming::Suffix)]
+    size_naming: SizeNaming,
+
+    /// Quality for lossy formats (jpeg, webp, avif)
+    #[arg(short, long, default_value_t = 90, value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: u8,
+
+    /// Chroma subsampling for JPEG (default: 444); WebP only supports 420 and AVIF is always 444
+    #[arg(long, value_enum)]
+    subsampling: Option<ChromaSubsampling>,
+
+    /// Encode each face to its own file so lossy compression never crosses a seam; implies the
+    /// separate layout
+    #[arg(long, conflicts_with = "layout")]
+    per_face: bool,
+
+    /// Print one JSON document with the settings, analysis, written files, timings and any error
+    /// to stdout
+    #[arg(long)]
+    json: bool,
+
+    /// Keep running and convert again whenever the input, LUT or recipe changes; each pass
+    /// replaces the outputs of the previous one, so --no-clobber cannot be used
+    #[arg(long, conflicts_with_all = ["json", "no_clobber"])]
+    watch: bool,
+
+    /// Milliseconds a changed file must stay unchanged before converting in watch mode
+    #[arg(long, default_value_t = 500, requires = "watch")]
+    debounce: u64,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print luminance statistics, a histogram and invalid value counts of an equirectangular image
+    Analyze(AnalyzeArgs),
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    /// Image to analyze, or `-` for stdin
+    input: PathBuf,
+
+    /// Transfer function of integer inputs (default: srgb for 8/16-bit images, linear for float)
+    #[arg(long, value_enum)]
+    input_transfer: Option<TransferFunction>,
+
+    /// Color space of the input, which sets the luminance weights
+    #[arg(long, value_enum, default_value_t = ColorSpace::Srgb)]
+    input_space: ColorSpace,
+
+    /// EXR layer (or part) to analyze instead of the default RGB
+    #[arg(long)]
+    layer: Option<String>,
+
+    /// EXR channels mapped to red, green and blue, e.g. `diffuse.R,diffuse.G,diffuse.B`
+    #[arg(long, value_parser = parse_channels)]
+    channels: Option<[String; 3]>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum FormatArg {
     Png,
     Exr,
+    Jpeg,
+    Webp,
+    Avif,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -52,12 +324,188 @@ enum LayoutArg {
     Separate,
 }
 
-/// Entry point for the CLI application that parses arguments, builds an eq2c configuration, runs the conversion, and exits with a status code on error.
+/// Parses a comma-separated `R,G,B` channel mapping.
+fn parse_channels(value: &str) -> Result<[String; 3], String> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+
+    match parts.as_slice() {
+        [r, g, b] if !r.is_empty() && !g.is_empty() && !b.is_empty() => {
+            Ok([r.to_string(), g.to_string(), b.to_string()])
+        }
+        _ => Err(format!(
+            "expected three comma-separated channel names, got '{}'",
+            value
+        )),
+    }
+}
+
+/// Parses a positive `--key`.
+fn parse_key(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(key) if key.is_finite() && key > 0.0 => Ok(key),
+        _ => Err(format!("expected a positive number, got '{}'", value)),
+    }
+}
+
+/// Parses a `--percentile` between 0 and 100.
+fn parse_percentile(value: &str) -> Result<f32, String> {
+    match value.parse::<f32>() {
+        Ok(percentile) if (0.0..=100.0).contains(&percentile) => Ok(percentile),
+        _ => Err(format!("expected a number from 0 to 100, got '{}'", value)),
+    }
+}
+
+/// Parses `MIN,MAX` stops for `--lut-shaper`.
+fn parse_log_shaper(value: &str) -> Result<LogShaper, String> {
+    let parsed = value
+        .split_once(',')
+        .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)));
+
+    match parsed {
+        Some((min_stops, max_stops)) if min_stops < max_stops => Ok(LogShaper {
+            min_stops,
+            max_stops,
+        }),
+        _ => Err(format!(
+            "expected MIN,MAX stops with MIN below MAX, got '{}'",
+            value
+        )),
+    }
+}
+
+/// Log target of the CLI's own messages; the library logs under `eq2c` and its modules.
+const CLI: &str = "cli";
+
+/// Writes log records to stderr, above the progress bar.
+struct CliLogger {
+    bars: MultiProgress,
+    /// Most verbose level shown for the library's messages (the CLI's own use the global level).
+    library_level: LevelFilter,
+}
+
+impl Log for CliLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Encoder dependencies log too; only show our own messages.
+        let target = metadata.target();
+        target == CLI
+            || (target == "eq2c" || target.starts_with("eq2c::"))
+                && metadata.level() <= self.library_level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = match record.level() {
+            Level::Error => format!("\x1b[31mError:\x1b[0m {}", record.args()),
+            Level::Warn => format!("\x1b[33mWarning:\x1b[0m {}", record.args()),
+            _ => record.args().to_string(),
+        };
+        self.bars.suspend(|| eprintln!("{}", line));
+    }
+
+    fn flush(&self) {}
+}
+
+/// Shows one progress bar per phase of the conversion.
+struct ProgressBars {
+    bars: MultiProgress,
+    current: Mutex<Option<ProgressBar>>,
+}
+
+impl Progress for ProgressBars {
+    fn start(&self, phase: Phase, total: usize) {
+        let style = ProgressStyle::with_template("{spinner} {msg:16} [{bar:30}] {pos}/{len}")
+            .expect("valid progress template")
+            .progress_chars("=> ");
+        let bar = self
+            .bars
+            .add(ProgressBar::new(total as u64).with_style(style));
+        bar.set_message(phase.to_string());
+        bar.enable_steady_tick(Duration::from_millis(100));
+
+        if let Some(previous) = self.current.lock().unwrap().replace(bar) {
+            previous.finish_and_clear();
+        }
+    }
+
+    fn advance(&self, _phase: Phase, done: usize) {
+        if let Some(bar) = &*self.current.lock().unwrap() {
+            bar.set_position(done as u64);
+        }
+    }
+
+    fn finish(&self, _phase: Phase) {
+        if let Some(bar) = self.current.lock().unwrap().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+impl ProgressBars {
+    /// A bar counting the images of a batch.
+    fn batch_bar(&self, total: usize) -> ProgressBar {
+        let style = ProgressStyle::with_template(
+            "{spinner} Converting [{bar:30}] {pos}/{len} images, {elapsed} (ETA {eta})",
+        )
+        .expect("valid progress template")
+        .progress_chars("=> ");
+        let bar = self
+            .bars
+            .add(ProgressBar::new(total as u64).with_style(style));
+        bar.enable_steady_tick(Duration::from_millis(100));
+        bar
+    }
+}
+
+/// Installs the stderr logger for the chosen verbosity and returns the progress bars, which are
+/// hidden in quiet mode (and by indicatif when stderr is not a terminal).
+///
+/// Batch mode converts several images at once, so the step by step messages of each one are only
+/// shown with `--verbose`.
+fn init_output(quiet: bool, verbose: u8, batch: bool) -> ProgressBars {
+    let bars = if quiet {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    };
+
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::Warn,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+
+    let library_level = if batch && verbose == 0 {
+        level.min(LevelFilter::Warn)
+    } else {
+        level
+    };
+
+    log::set_logger(Box::leak(Box::new(CliLogger {
+        bars: bars.clone(),
+        library_level,
+    })))
+    .expect("logger is only installed once");
+    log::set_max_level(level);
+
+    ProgressBars {
+        bars,
+        current: Mutex::new(None),
+    }
+}
+
+/// Entry point for the CLI application that parses arguments, builds an eq2c configuration, runs the conversion (or the `analyze` subcommand), and exits with a status code on error.
 ///
-/// On success prints the total execution time. On failure prints the error in red and exits with a specific status code depending on the error kind:
+/// On success prints the total execution time. On failure prints the error in red and exits with a specific status code depending on the error kind (see [`exit_code`]):
 /// - `Eq2cError::Io(_)` -> exit code 74
-/// - `Eq2cError::Image(_)` -> exit code 65
-/// - `Eq2cError::InvalidDimensions { .. }` -> exit code 64
+/// - `Eq2cError::Image(_)` / `Eq2cError::Exr(_)` / `InvalidLut { .. }` / `InvalidValues(_)` -> exit code 65
+/// - `Eq2cError::InvalidDimensions { .. }`, `LayerNotFound { .. }`, `ChannelNotFound { .. }` -> exit code 64
+/// - `Eq2cError::OutputExists(_)` -> exit code 73
+/// - `Eq2cError::InvalidRecipe(_)` -> exit code 78
+/// - interrupted (Ctrl-C) -> exit code 130, after removing the run's partial outputs
 /// - other errors -> exit code 1
 ///
 /// # Examples
@@ -69,35 +517,581 @@ enum LayoutArg {
 fn main() {
     let args = Cli::parse();
     let start = Instant::now();
+    let batch = args.output_dir.is_some();
+    let progress = init_output(args.quiet, args.verbose, batch);
+
+    let interrupted = ctrlc::set_handler(|| {
+        let rolled_back = eq2c::output::roll_back_pending();
+        error!(target: CLI, "Interrupted; rolled back {} output files", rolled_back);
+        std::process::exit(130);
+    });
+    if let Err(e) = interrupted {
+        warn!(target: CLI, "cannot clean up on Ctrl-C: {}", e);
+    }
+
+    if let Some(Command::Analyze(analyze)) = args.command {
+        let exr = eq2c::ExrSelection {
+            layer: analyze.layer,
+            channels: analyze.channels,
+        };
+        match eq2c::analyze_file(
+            &analyze.input,
+            analyze.input_transfer,
+            analyze.input_space,
+            &exr,
+        ) {
+            Ok(report) if analyze.json => println!("{}", report.to_json()),
+            Ok(report) => print!("{}", report),
+            Err(e) => exit_with(e),
+        }
+        return;
+    }
+
+    // Checked before anything is converted, but reported like any other error, so `--json`
+    // still prints its document.
+    let checked = check_inputs(&args, batch);
+
+    let mut document = args.json.then(|| {
+        json!({
+            "input": {
+                "paths": args.input,
+                "transfer": args.input_transfer.map(|t| value_name(&t)),
+                "space": value_name(&args.input_space),
+                "layer": args.layer,
+                "channels": args.channels,
+                "all_layers": args.all_layers,
+            },
+            "settings": settings_json(&args),
+        })
+    });
+
+    let exit = match checked {
+        Err(e) => Err((exit_code(&e), Some(e))),
+        Ok(()) => {
+            let batch_options = args
+                .output_dir
+                .clone()
+                .map(|output_dir| eq2c::BatchOptions {
+                    output_dir,
+                    name_template: args.name_template.clone(),
+                    jobs: args.jobs as usize,
+                    rebuild: args.rebuild,
+                });
+            let patterns = args.input.clone();
+            let recipe = args.recipe.clone();
+            let watch = args.watch.then(|| Duration::from_millis(args.debounce));
+            let mut config = build_config(args);
+
+            if let Some(debounce) = watch {
+                watch_and_convert(
+                    &mut config,
+                    recipe.as_deref(),
+                    &patterns,
+                    batch_options,
+                    debounce,
+                    &progress,
+                );
+            }
+
+            convert(
+                &mut config,
+                recipe.as_deref(),
+                &patterns,
+                batch_options.as_ref(),
+                &progress,
+                document.as_mut(),
+            )
+        }
+    };
+    let total = start.elapsed();
+
+    if let Some(mut document) = document {
+        let fields = document.as_object_mut().expect("document is an object");
+        fields.insert("success".into(), json!(exit.is_ok()));
+        fields.insert(
+            "exit_code".into(),
+            json!(exit.as_ref().err().map_or(0, |(code, _)| *code)),
+        );
+        fields
+            .entry("timings")
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("timings is an object")
+            .insert("total".into(), json!(total.as_secs_f64()));
+        fields.insert(
+            "error".into(),
+            exit.as_ref()
+                .err()
+                .and_then(|(_, e)| e.as_ref())
+                .map_or(Value::Null, error_json),
+        );
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&document).expect("JSON values always serialize")
+        );
+    }
+
+    match exit {
+        Ok(()) => info!(target: CLI, "Total Time: {:?}", total),
+        Err((_, Some(e))) => {
+            progress.finish(Phase::Encode);
+            exit_with(e);
+        }
+        Err((code, None)) => std::process::exit(code),
+    }
+}
+
+/// Checks the input and output combinations clap cannot express.
+fn check_inputs(args: &Cli, batch: bool) -> eq2c::Result<()> {
+    let argument = |message: &str| Err(Eq2cError::ArgumentError(message.to_string()));
+
+    if !batch && args.input.len() != 1 {
+        return argument("several inputs need --output-dir instead of --output");
+    }
+
+    let stdin = args.input.iter().any(|path| path.as_os_str() == "-");
+    let stdout = args
+        .output
+        .as_ref()
+        .is_some_and(|path| path.as_os_str() == "-");
+    if stdin && (batch || args.watch) {
+        return argument("stdin (-) cannot be batch converted or watched");
+    }
+    if stdout && args.json {
+        return argument("--json and output to stdout (-) both need stdout");
+    }
+    Ok(())
+}
+
+/// Exit status and the error to report, if there is a single one.
+type Exit = Result<(), (i32, Option<Eq2cError>)>;
+
+/// Loads the recipe, if there is one, and converts the input (or every input in batch mode).
+fn convert(
+    config: &mut eq2c::Config,
+    recipe: Option<&Path>,
+    patterns: &[PathBuf],
+    batch_options: Option<&eq2c::BatchOptions>,
+    progress: &ProgressBars,
+    document: Option<&mut Value>,
+) -> Exit {
+    config.recipe = recipe
+        .map(|path| eq2c::Recipe::open(path, &config.options))
+        .transpose()
+        .map_err(|e| (exit_code(&e), Some(e)))?;
+
+    match batch_options {
+        Some(options) => run_batch(config, patterns, options, progress, document),
+        None => run_single(config.clone(), progress, document),
+    }
+}
+
+/// Converts, then converts again whenever a source file changes, until interrupted. Errors are
+/// reported without exiting.
+fn watch_and_convert(
+    config: &mut eq2c::Config,
+    recipe: Option<&Path>,
+    patterns: &[PathBuf],
+    mut batch_options: Option<eq2c::BatchOptions>,
+    debounce: Duration,
+    progress: &ProgressBars,
+) -> ! {
+    loop {
+        // Started before converting, so saves during a slow conversion trigger the next one.
+        let mut watcher = FileWatcher::new(watched_paths(config, recipe, patterns));
+        watcher.debounce = debounce;
+
+        let start = Instant::now();
+        match convert(
+            config,
+            recipe,
+            patterns,
+            batch_options.as_ref(),
+            progress,
+            None,
+        ) {
+            Ok(()) => info!(target: CLI, "Total Time: {:?}", start.elapsed()),
+            Err((_, Some(e))) => {
+                progress.finish(Phase::Encode);
+                print_error(&e);
+            }
+            // Batch failures were already logged one by one.
+            Err((_, None)) => {}
+        }
+
+        info!(target: CLI,
+            "Watching {} files for changes (Ctrl-C to stop)...",
+            watcher.paths().len()
+        );
+        let changed = watcher.wait();
+        for path in &changed {
+            info!(target: CLI, "{} changed", path.display());
+        }
 
-    let config = eq2c::Config {
-        input: args.input,
-        output: args.output,
-        format: match args.format {
-            FormatArg::Png => eq2c::OutputFormat::Png,
-            FormatArg::Exr => eq2c::OutputFormat::Exr,
+        // Up-to-date checks only look at inputs and the LUT.
+        if let Some(options) = &mut batch_options {
+            options.rebuild = recipe.is_some_and(|recipe| changed.iter().any(|p| p == recipe));
+        }
+    }
+}
+
+/// The files whose changes trigger a new conversion in watch mode.
+fn watched_paths(
+    config: &eq2c::Config,
+    recipe: Option<&Path>,
+    patterns: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut paths = if config.output.as_os_str().is_empty() {
+        eq2c::batch::expand_inputs(patterns).unwrap_or_else(|_| patterns.to_vec())
+    } else {
+        vec![config.input.clone()]
+    };
+    paths.extend(config.lut.clone());
+    paths.extend(recipe.map(Path::to_path_buf));
+    paths
+}
+
+/// Converts one input to `--output`.
+fn run_single(config: eq2c::Config, progress: &ProgressBars, document: Option<&mut Value>) -> Exit {
+    let result = eq2c::run_with_progress(config, progress);
+
+    if let Some(document) = document {
+        let fields = document.as_object_mut().expect("document is an object");
+        fields.insert(
+            "sources".into(),
+            result.as_ref().map_or(json!([]), |report| {
+                report.sources.iter().map(source_json).collect()
+            }),
+        );
+        fields.insert(
+            "timings".into(),
+            json!({ "load": result.as_ref().ok().map(|report| report.load.as_secs_f64()) }),
+        );
+    }
+
+    result.map(|_| ()).map_err(|e| (exit_code(&e), Some(e)))
+}
+
+/// Converts every input into `--output-dir` and logs a summary. Fails with the exit code of the
+/// failed inputs when they agree, and 1 otherwise.
+fn run_batch(
+    template: &eq2c::Config,
+    patterns: &[PathBuf],
+    options: &eq2c::BatchOptions,
+    progress: &ProgressBars,
+    document: Option<&mut Value>,
+) -> Exit {
+    let inputs = eq2c::batch::expand_inputs(patterns).map_err(|e| (exit_code(&e), Some(e)))?;
+    info!(target: CLI,
+        "Converting {} images into {} ({} at a time)...",
+        inputs.len(),
+        options.output_dir.display(),
+        options.jobs
+    );
+
+    let bar = progress.batch_bar(inputs.len());
+    let on_done = |item: &eq2c::batch::BatchItem| {
+        match &item.status {
+            BatchStatus::Converted(_) => info!(target: CLI,
+                "Converted {} -> {} ({:.2?})",
+                item.input.display(),
+                item.output.display(),
+                item.elapsed
+            ),
+            BatchStatus::Skipped => {
+                info!(target: CLI, "Skipped {} (up to date)", item.input.display())
+            }
+            BatchStatus::Failed(e) => error!(target: CLI, "{}: {}", item.input.display(), e),
+        }
+        bar.inc(1);
+    };
+
+    let report = eq2c::run_batch(template, &inputs, options, &on_done);
+    bar.finish_and_clear();
+    let report = report.map_err(|e| (exit_code(&e), Some(e)))?;
+
+    info!(target: CLI,
+        "Converted {}, skipped {}, failed {} of {} images",
+        report.converted(),
+        report.skipped(),
+        report.failed(),
+        report.items.len()
+    );
+
+    if let Some(document) = document {
+        let fields = document.as_object_mut().expect("document is an object");
+        fields.insert(
+            "batch".into(),
+            json!({
+                "output_dir": options.output_dir,
+                "name_template": options.name_template,
+                "jobs": options.jobs,
+                "rebuild": options.rebuild,
+            }),
+        );
+        fields.insert(
+            "results".into(),
+            report.items.iter().map(batch_item_json).collect(),
+        );
+        fields.insert(
+            "summary".into(),
+            json!({
+                "converted": report.converted(),
+                "skipped": report.skipped(),
+                "failed": report.failed(),
+            }),
+        );
+    }
+
+    match report.exit_code(exit_code) {
+        None => Ok(()),
+        Some(code) => Err((code, None)),
+    }
+}
+
+/// Turns the parsed arguments into a library configuration. `input` and `output` are the first
+/// input and `--output` (empty in batch mode, where each input gets its own).
+fn build_config(args: Cli) -> eq2c::Config {
+    eq2c::Config {
+        input: args.input.into_iter().next().unwrap_or_default(),
+        input_transfer: args.input_transfer,
+        exr: eq2c::ExrSelection {
+            layer: args.layer,
+            channels: args.channels,
+        },
+        all_layers: args.all_layers,
+        output: args.output.unwrap_or_default(),
+        lut: args.lut,
+        lut_interpolation: args.lut_interpolation,
+        lut_shaper: args.lut_shaper,
+        recipe: None,
+        sizes: if args.size.len() > 1 {
+            args.size.clone()
+        } else {
+            Vec::new()
         },
-        layout: match args.layout {
-            LayoutArg::Cross => eq2c::LayoutType::Cross,
-            LayoutArg::StripH => eq2c::LayoutType::StripHorizontal,
-            LayoutArg::StripV => eq2c::LayoutType::StripVertical,
-            LayoutArg::Separate => eq2c::LayoutType::Separate,
+        size_naming: args.size_naming,
+        overwrite: match (args.force, args.no_clobber) {
+            (true, _) => Overwrite::Force,
+            (_, true) => Overwrite::NoClobber,
+            _ => Overwrite::Warn,
         },
-        tonemap: args.tonemap,
-        exposure: args.exposure,
-        size: args.size,
+        options: eq2c::ConvertOptions {
+            input_space: args.input_space,
+            sanitize: args.sanitize,
+            strict: args.strict,
+            format: match args.format {
+                FormatArg::Png => eq2c::OutputFormat::Png,
+                FormatArg::Exr => eq2c::OutputFormat::Exr,
+                FormatArg::Jpeg => eq2c::OutputFormat::Jpeg,
+                FormatArg::Webp => eq2c::OutputFormat::Webp,
+                FormatArg::Avif => eq2c::OutputFormat::Avif,
+            },
+            layout: match args.layout {
+                LayoutArg::Cross => eq2c::LayoutType::Cross,
+                LayoutArg::StripH => eq2c::LayoutType::StripHorizontal,
+                LayoutArg::StripV => eq2c::LayoutType::StripVertical,
+                LayoutArg::Separate => eq2c::LayoutType::Separate,
+            },
+            tonemap: args.tonemap,
+            local_tonemap: args.local_tonemap.then_some(LocalToneMap {
+                contrast: args.local_contrast,
+                detail: args.local_detail,
+                radius: args.local_radius,
+            }),
+            contact_sheet: args.contact_sheet.map(|source| ContactSheet {
+                source,
+                bracket: args.bracket,
+                tile_width: args.sheet_width,
+            }),
+            exposure: args.exposure,
+            auto_exposure: args.auto_exposure.map(|method| AutoExposure {
+                method,
+                key: args.key,
+                percentile: args.percentile,
+                compensation: args.ev,
+            }),
+            output_transfer: args.output_transfer,
+            output_space: args.output_space,
+            gamut_mapping: args.gamut_mapping,
+            lut: None,
+            hdr: args.hdr,
+            peak_nits: args.peak_nits,
+            dither: args.dither,
+            size: args.size[0],
+            quality: args.quality,
+            subsampling: args.subsampling,
+            per_face: args.per_face,
+        },
+    }
+}
+
+/// The command line name of a value, e.g. `strip-h`.
+fn value_name<T: ValueEnum>(value: &T) -> String {
+    value
+        .to_possible_value()
+        .map(|v| v.get_name().to_string())
+        .unwrap_or_default()
+}
+
+/// The processing settings of a run, as given on the command line (with defaults filled in).
+fn settings_json(args: &Cli) -> Value {
+    json!({
+        "recipe": args.recipe,
+        "no_clobber": args.no_clobber,
+        "force": args.force,
+        "format": value_name(&args.format),
+        "layout": value_name(&args.layout),
+        "size": args.size[0],
+        "sizes": args.size,
+        "size_naming": value_name(&args.size_naming),
+        "per_face": args.per_face,
+        "tonemap": args.tonemap.to_string(),
+        "exposure": args.exposure,
+        "auto_exposure": args.auto_exposure.map(|method| json!({
+            "method": value_name(&method),
+            "key": args.key,
+            "percentile": args.percentile,
+            "ev": args.ev,
+        })),
+        "local_tonemap": args.local_tonemap.then(|| json!({
+            "contrast": args.local_contrast,
+            "detail": args.local_detail,
+            "radius": args.local_radius,
+        })),
+        "contact_sheet": args.contact_sheet.map(|source| json!({
+            "source": value_name(&source),
+            "bracket": args.bracket,
+            "tile_width": args.sheet_width,
+        })),
+        "output_transfer": value_name(&args.output_transfer),
+        "output_space": value_name(&args.output_space),
+        "gamut_mapping": value_name(&args.gamut_mapping),
+        "lut": args.lut.as_ref().map(|path| json!({
+            "path": path,
+            "interpolation": value_name(&args.lut_interpolation),
+            "shaper": args.lut_shaper.map(|s| [s.min_stops, s.max_stops]),
+        })),
+        "hdr": args.hdr.map(|transfer| json!({
+            "transfer": value_name(&transfer),
+            "peak_nits": args.peak_nits,
+        })),
+        "dither": value_name(&args.dither),
+        "quality": args.quality,
+        "subsampling": args.subsampling.map(|s| value_name(&s)),
+        "sanitize": args.sanitize.map(|mode| value_name(&mode)),
+        "strict": args.strict,
+    })
+}
+
+/// The brightness analysis, written files and step timings of one converted source image.
+fn source_json(source: &eq2c::SourceReport) -> Value {
+    let conversion = &source.conversion;
+
+    json!({
+        "layer": source.layer,
+        "width": source.width,
+        "height": source.height,
+        "analysis": {
+            "max_brightness": conversion.max_brightness,
+            "exposure": conversion.exposure,
+            "measured_luminance": conversion.measured_luminance,
+            "invalid_pixels": {
+                "nan": conversion.invalid.nan,
+                "infinite": conversion.invalid.infinite,
+                "negative": conversion.invalid.negative,
+            },
+            "sanitized": conversion.sanitized,
+        },
+        "files": source.files.iter().map(|file| json!({
+            "path": file.path,
+            "bytes": file.bytes,
+            "width": file.width,
+            "height": file.height,
+        })).collect::<Vec<_>>(),
+        "timings": {
+            "analyze": source.timings.analyze.as_secs_f64(),
+            "layout": source.timings.layout.as_secs_f64(),
+            "encode": source.timings.encode.as_secs_f64(),
+            "write": source.timings.write.as_secs_f64(),
+        },
+    })
+}
+
+/// One input of a batch: its status, outputs and error.
+fn batch_item_json(item: &eq2c::batch::BatchItem) -> Value {
+    let (status, sources, error) = match &item.status {
+        BatchStatus::Converted(report) => (
+            "converted",
+            report.sources.iter().map(source_json).collect(),
+            Value::Null,
+        ),
+        BatchStatus::Skipped => ("skipped", json!([]), Value::Null),
+        BatchStatus::Failed(e) => ("failed", json!([]), error_json(e)),
     };
 
-    if let Err(e) = eq2c::run(config) {
-        eprintln!("\x1b[31mError:\x1b[0m {}", e);
+    json!({
+        "input": item.input,
+        "output": item.output,
+        "status": status,
+        "sources": sources,
+        "elapsed": item.elapsed.as_secs_f64(),
+        "error": error,
+    })
+}
 
-        match e {
-            Eq2cError::Io(_) => std::process::exit(74),
-            Eq2cError::Image(_) => std::process::exit(65),
-            Eq2cError::InvalidDimensions { .. } => std::process::exit(64),
-            _ => std::process::exit(1),
-        }
+fn error_json(e: &Eq2cError) -> Value {
+    json!({
+        "kind": error_kind(e),
+        "message": e.to_string(),
+        "exit_code": exit_code(e),
+    })
+}
+
+/// Prints `e` in red and exits with its [`exit_code`].
+fn exit_with(e: Eq2cError) -> ! {
+    print_error(&e);
+    std::process::exit(exit_code(&e))
+}
+
+fn print_error(e: &Eq2cError) {
+    eprintln!("\x1b[31mError:\x1b[0m {}", e);
+}
+
+/// Exit status for an error, following sysexits.h.
+fn exit_code(e: &Eq2cError) -> i32 {
+    match e {
+        Eq2cError::Io(_) => 74,
+        Eq2cError::Image(_)
+        | Eq2cError::Exr(_)
+        | Eq2cError::InvalidLut { .. }
+        | Eq2cError::InvalidValues(_) => 65,
+        Eq2cError::InvalidDimensions { .. }
+        | Eq2cError::LayerNotFound { .. }
+        | Eq2cError::ChannelNotFound { .. } => 64,
+        Eq2cError::OutputExists(_) => 73,
+        Eq2cError::InvalidRecipe(_) => 78,
+        _ => 1,
     }
+}
 
-    println!("Total Time: {:?}", start.elapsed());
+/// Stable name of an error's kind for `--json`.
+fn error_kind(e: &Eq2cError) -> &'static str {
+    match e {
+        Eq2cError::Io(_) => "io",
+        Eq2cError::Image(_) => "image",
+        Eq2cError::Exr(_) => "exr",
+        Eq2cError::LayerNotFound { .. } => "layer_not_found",
+        Eq2cError::ChannelNotFound { .. } => "channel_not_found",
+        Eq2cError::UnsupportedFormat(_) => "unsupported_format",
+        Eq2cError::InvalidDimensions { .. } => "invalid_dimensions",
+        Eq2cError::Decoding(_) => "decoding",
+        Eq2cError::Encoding(_) => "encoding",
+        Eq2cError::InvalidLut { .. } => "invalid_lut",
+        Eq2cError::InvalidValues(_) => "invalid_values",
+        Eq2cError::ToneMapError(_) => "tone_map",
+        Eq2cError::ArgumentError(_) => "argument",
+        Eq2cError::InvalidRecipe(_) => "invalid_recipe",
+        Eq2cError::OutputExists(_) => "output_exists",
+    }
 }
@@ -11,6 +11,17 @@ pub enum CubeFace {
     Back,   // -Z
 }
 
+impl CubeFace {
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::Right,
+        CubeFace::Left,
+        CubeFace::Top,
+        CubeFace::Bottom,
+        CubeFace::Front,
+        CubeFace::Back,
+    ];
+}
+
 pub fn calculate_source_uv(face: CubeFace, u: f32, v: f32) -> Vec2 {
     let direction = face_uv_to_dir(face, u, v);
     dir_to_equirect_uv(direction)
@@ -44,6 +55,13 @@ fn dir_to_equirect_uv(dir: Vec3) -> Vec2 {
     Vec2::new(u, v)
 }
 
+/// Relative solid angle covered by the pixels of row `y` in an equirectangular image of `height`
+/// rows, i.e. the cosine of the row's latitude.
+pub fn equirect_row_weight(y: u32, height: u32) -> f32 {
+    let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+    latitude.cos()
+}
+
 pub fn bilerp(v00: Vec3, v10: Vec3, v01: Vec3, v11: Vec3, tx: f32, ty: f32) -> Vec3 {
     let top = v00.lerp(v10, tx);
 
@@ -0,0 +1,380 @@
+use crate::error::{Eq2cError, Result};
+
+use log::{debug, warn};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// Outputs of unfinished runs, rolled back by [`roll_back_pending`]. Files are only written and
+/// renamed while this is locked, so a rollback never races a write.
+static PENDING: Mutex<Pending> = Mutex::new(Pending {
+    staged: Vec::new(),
+    dirs: Vec::new(),
+    interrupted: false,
+});
+
+struct Pending {
+    staged: Vec<Staged>,
+    dirs: Vec<PathBuf>,
+    /// Set by [`roll_back_pending`]; nothing is written afterwards.
+    interrupted: bool,
+}
+
+/// What happens when an output file exists already.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overwrite {
+    /// Replace it and log a warning.
+    #[default]
+    Warn,
+    /// Replace it quietly.
+    Force,
+    /// Fail with `Eq2cError::OutputExists` before anything is written.
+    NoClobber,
+}
+
+impl Overwrite {
+    /// Fails for an existing `path` with [`Overwrite::NoClobber`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::OutputExists`.
+    pub fn check(self, path: &Path) -> Result<()> {
+        if self == Overwrite::NoClobber && path.exists() {
+            return Err(Eq2cError::OutputExists(path.to_path_buf()));
+        }
+        Ok(())
+    }
+}
+
+/// One output file and how far it has been moved into place.
+#[derive(Debug, Clone)]
+struct Staged {
+    temp: PathBuf,
+    path: PathBuf,
+    /// Where the file that existed at `path` was moved while committing.
+    backup: Option<PathBuf>,
+    /// `temp` is (being) renamed to `path`.
+    moved: bool,
+}
+
+impl Staged {
+    /// Removes the temporary or the new file and puts a replaced file back.
+    fn roll_back(&self) {
+        let _ = std::fs::remove_file(&self.temp);
+        if self.moved {
+            let _ = std::fs::remove_file(&self.path);
+        }
+        if let Some(backup) = &self.backup {
+            let _ = std::fs::rename(backup, &self.path);
+        }
+    }
+}
+
+/// The output files of one run. Each file is written to a hidden temporary next to its
+/// destination, and [`OutputSet::commit`] renames them all into place once the run has
+/// succeeded. Dropping an uncommitted set deletes its temporaries and the directories it
+/// created, so a failed run leaves no partial outputs and existing files untouched.
+#[derive(Debug)]
+pub struct OutputSet {
+    overwrite: Overwrite,
+    staged: Vec<Staged>,
+    /// Missing parent directories created for the outputs, in creation order.
+    dirs: Vec<PathBuf>,
+}
+
+impl OutputSet {
+    pub fn new(overwrite: Overwrite) -> Self {
+        Self {
+            overwrite,
+            staged: Vec::new(),
+            dirs: Vec::new(),
+        }
+    }
+
+    /// Stages `data` for `path`, creating missing parent directories; a second write to the
+    /// same path replaces the first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::OutputExists` (see [`Overwrite::check`]) or `Eq2cError::Io`.
+    pub fn write(&mut self, path: &Path, data: &[u8]) -> Result<()> {
+        self.overwrite.check(path)?;
+
+        let temp = sibling(path, "tmp");
+        let mut pending = lock_pending()?;
+        if !self.staged.iter().any(|staged| staged.temp == temp) {
+            let staged = Staged {
+                temp: temp.clone(),
+                path: path.to_path_buf(),
+                backup: None,
+                moved: false,
+            };
+            pending.staged.push(staged.clone());
+            self.staged.push(staged);
+        }
+
+        self.create_parents(&mut pending, path)?;
+        std::fs::write(&temp, data)?;
+        drop(pending);
+        debug!("Staged {} as {}", path.display(), temp.display());
+        Ok(())
+    }
+
+    fn create_parents(&mut self, pending: &mut Pending, path: &Path) -> Result<()> {
+        let missing: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .collect();
+
+        for dir in missing.into_iter().rev() {
+            std::fs::create_dir(dir)?;
+            debug!("Created {}", dir.display());
+            self.dirs.push(dir.to_path_buf());
+            pending.dirs.push(dir.to_path_buf());
+        }
+        Ok(())
+    }
+
+    /// Moves every staged file into place. Existing files are moved aside first and only
+    /// deleted once everything is in place; if anything fails, the new files are removed and
+    /// the old ones restored.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::OutputExists` if a file appeared at a destination with
+    /// [`Overwrite::NoClobber`], or `Eq2cError::Io` if a destination is a directory or a rename
+    /// fails.
+    pub fn commit(mut self) -> Result<()> {
+        let mut staged = std::mem::take(&mut self.staged);
+        let result = self.move_into_place(&mut staged);
+
+        // An interrupt has rolled everything back already.
+        let mut pending = lock_pending()?;
+        match &result {
+            Ok(()) => {
+                for backup in staged.iter().filter_map(|s| s.backup.as_ref()) {
+                    let _ = std::fs::remove_file(backup);
+                }
+            }
+            Err(_) => {
+                staged.iter().rev().for_each(Staged::roll_back);
+                remove_dirs(&self.dirs);
+            }
+        }
+        forget_pending(&mut pending, &staged, &self.dirs);
+        self.dirs.clear();
+        result
+    }
+
+    fn move_into_place(&self, staged: &mut [Staged]) -> Result<()> {
+        // Check every destination before touching any of them.
+        for s in staged.iter() {
+            self.overwrite.check(&s.path)?;
+            if s.path.is_dir() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::IsADirectory,
+                    format!("{} is a directory", s.path.display()),
+                )
+                .into());
+            }
+        }
+
+        // The registry is updated before each rename, so an interrupt at any point can be
+        // rolled back.
+        for s in staged.iter_mut() {
+            let mut pending = lock_pending()?;
+            if s.path.exists() {
+                if self.overwrite == Overwrite::Warn {
+                    warn!("overwriting {}", s.path.display());
+                }
+                let backup = sibling(&s.path, "bak");
+                s.backup = Some(backup.clone());
+                update_pending(&mut pending, s);
+                std::fs::rename(&s.path, &backup)?;
+            }
+
+            s.moved = true;
+            update_pending(&mut pending, s);
+            std::fs::rename(&s.temp, &s.path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OutputSet {
+    fn drop(&mut self) {
+        if let Ok(mut pending) = lock_pending() {
+            self.staged.iter().for_each(Staged::roll_back);
+            remove_dirs(&self.dirs);
+            forget_pending(&mut pending, &self.staged, &self.dirs);
+        }
+    }
+}
+
+/// Rolls back every unfinished run in this process when it is interrupted: temporaries, partly
+/// committed outputs and created directories are removed and replaced files restored. Later
+/// writes and commits fail without touching any file. Returns how many outputs were rolled back.
+pub fn roll_back_pending() -> usize {
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    pending.interrupted = true;
+    let staged = std::mem::take(&mut pending.staged);
+    staged.iter().rev().for_each(Staged::roll_back);
+    remove_dirs(&std::mem::take(&mut pending.dirs));
+    staged.len()
+}
+
+/// Removes `dirs` deepest first, keeping any that are not empty (e.g. shared with another run).
+fn remove_dirs(dirs: &[PathBuf]) {
+    for dir in dirs.iter().rev() {
+        let _ = std::fs::remove_dir(dir);
+    }
+}
+
+/// Locks [`PENDING`], failing once [`roll_back_pending`] has run.
+fn lock_pending() -> Result<MutexGuard<'static, Pending>> {
+    let pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    if pending.interrupted {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Interrupted,
+            "interrupted; outputs were rolled back",
+        )
+        .into());
+    }
+    Ok(pending)
+}
+
+/// `.<name>.<pid>.<kind>` in the same directory, so renames stay on one file system.
+fn sibling(path: &Path, kind: &str) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(format!(".{}.{}", std::process::id(), kind));
+    path.with_file_name(name)
+}
+
+fn update_pending(pending: &mut Pending, staged: &Staged) {
+    if let Some(entry) = pending
+        .staged
+        .iter_mut()
+        .find(|entry| entry.temp == staged.temp)
+    {
+        *entry = staged.clone();
+    }
+}
+
+fn forget_pending(pending: &mut Pending, staged: &[Staged], dirs: &[PathBuf]) {
+    pending
+        .staged
+        .retain(|entry| !staged.iter().any(|s| s.temp == entry.temp));
+    pending.dirs.retain(|dir| !dirs.contains(dir));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("eq2c-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_failed_run_leaves_existing_files() {
+        let dir = scratch("output-drop");
+        std::fs::write(dir.join("sky_px.png"), b"old").unwrap();
+
+        let mut outputs = OutputSet::new(Overwrite::Force);
+        outputs.write(&dir.join("sky_px.png"), b"new").unwrap();
+        outputs.write(&dir.join("sky_nx.png"), b"new").unwrap();
+        assert_eq!(entries(&dir).len(), 3);
+        drop(outputs);
+
+        assert_eq!(entries(&dir), ["sky_px.png"]);
+        assert_eq!(std::fs::read(dir.join("sky_px.png")).unwrap(), b"old");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_run_removes_created_dirs() {
+        let dir = scratch("output-dirs");
+
+        let mut outputs = OutputSet::new(Overwrite::Force);
+        outputs.write(&dir.join("256/a/sky.png"), b"new").unwrap();
+        assert!(dir.join("256/a").is_dir());
+        drop(outputs);
+        assert!(entries(&dir).is_empty());
+
+        let mut outputs = OutputSet::new(Overwrite::Force);
+        outputs.write(&dir.join("256/sky.png"), b"new").unwrap();
+        outputs.commit().unwrap();
+        assert_eq!(std::fs::read(dir.join("256/sky.png")).unwrap(), b"new");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_commit_and_no_clobber() {
+        let dir = scratch("output-commit");
+
+        let mut outputs = OutputSet::new(Overwrite::NoClobber);
+        outputs.write(&dir.join("sky.png"), b"new").unwrap();
+        outputs.commit().unwrap();
+        assert_eq!(entries(&dir), ["sky.png"]);
+
+        let mut outputs = OutputSet::new(Overwrite::NoClobber);
+        assert!(matches!(
+            outputs.write(&dir.join("sky.png"), b"newer"),
+            Err(Eq2cError::OutputExists(_))
+        ));
+        assert_eq!(std::fs::read(dir.join("sky.png")).unwrap(), b"new");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_commit_restores_replaced_files() {
+        let dir = scratch("output-restore");
+        let faces = ["px", "nx", "py", "ny", "pz", "nz"].map(|f| dir.join(format!("sky_{f}.png")));
+        for path in &faces {
+            std::fs::write(path, b"old").unwrap();
+        }
+
+        let mut outputs = OutputSet::new(Overwrite::Force);
+        for path in &faces {
+            outputs.write(path, b"new").unwrap();
+        }
+        // A destination that cannot be replaced.
+        std::fs::remove_file(&faces[3]).unwrap();
+        std::fs::create_dir(&faces[3]).unwrap();
+        assert!(outputs.commit().is_err());
+
+        for path in faces.iter().filter(|p| p.is_file()) {
+            assert_eq!(std::fs::read(path).unwrap(), b"old");
+        }
+        assert_eq!(entries(&dir).len(), 6);
+
+        // Rolling back part way through a commit (as on Ctrl-C) restores the old file too.
+        std::fs::remove_dir(&faces[3]).unwrap();
+        let staged = Staged {
+            temp: sibling(&faces[0], "tmp"),
+            path: faces[0].clone(),
+            backup: Some(sibling(&faces[0], "bak")),
+            moved: true,
+        };
+        std::fs::rename(&faces[0], staged.backup.as_ref().unwrap()).unwrap();
+        std::fs::write(&faces[0], b"new").unwrap();
+        staged.roll_back();
+        assert_eq!(std::fs::read(&faces[0]).unwrap(), b"old");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
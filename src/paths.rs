@@ -1,7 +1,35 @@
+use std::collections::HashSet;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 
 use crate::math::CubeFace;
+use clap::ValueEnum;
+
+/// How the outputs of several face sizes are told apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum SizeNaming {
+    /// `sky_256.png`, `sky_512.png`
+    #[default]
+    Suffix,
+    /// `256/sky.png`, `512/sky.png`
+    Directory,
+}
+
+/// Where the output for face size `size` goes.
+pub fn sized_path(path: &Path, size: u32, naming: SizeNaming) -> PathBuf {
+    match naming {
+        SizeNaming::Suffix => append_suffix(path, &size.to_string()),
+        SizeNaming::Directory => {
+            let name = path.file_name().unwrap_or_default();
+            path.with_file_name(size.to_string()).join(name)
+        }
+    }
+}
+
+/// Whether `path` is `-`, which stands for stdin or stdout.
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
 
 pub fn face_suffix(face: CubeFace) -> &'static str {
     match face {
@@ -28,3 +56,65 @@ pub fn append_suffix(path: &Path, suffix: &str) -> PathBuf {
 
     path.with_file_name(name)
 }
+
+/// Makes an arbitrary name (e.g. an EXR layer like `light/key`) safe to use inside a file name.
+pub fn sanitize_component(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Sanitizes each of `names` with [`sanitize_component`], numbering repeats (`a_b`, `a_b-2`)
+/// so names such as `a.b` and `a_b` still get files of their own.
+pub fn unique_components<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let mut used = HashSet::new();
+    names
+        .into_iter()
+        .map(|name| {
+            let base = sanitize_component(name);
+            let mut component = base.clone();
+            let mut n = 1;
+            while !used.insert(component.clone()) {
+                n += 1;
+                component = format!("{}-{}", base, n);
+            }
+            component
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sized_path() {
+        let path = Path::new("out/sky.png");
+        assert_eq!(
+            sized_path(path, 256, SizeNaming::Suffix),
+            Path::new("out/sky_256.png")
+        );
+        assert_eq!(
+            sized_path(path, 256, SizeNaming::Directory),
+            Path::new("out/256/sky.png")
+        );
+        assert_eq!(
+            sized_path(Path::new("sky.png"), 64, SizeNaming::Directory),
+            Path::new("64/sky.png")
+        );
+    }
+
+    #[test]
+    fn test_unique_components() {
+        assert_eq!(
+            unique_components(["", "a.b", "a_b", "a/b", "c"]),
+            ["", "a_b", "a_b-2", "a_b-3", "c"]
+        );
+    }
+}
@@ -0,0 +1,715 @@
+use crate::analysis::AutoExposure;
+use crate::codecs::png::{PngColorInfo, write_png};
+use crate::codecs::{
+    ChromaSubsampling, Dither, EncoderSettings, OutputFormat, SkyboxEncoder, ToneMapType,
+    get_encoder,
+};
+use crate::color::{self, ColorSpace, GamutMapping, HdrTransfer, LutSettings, TransferFunction};
+use crate::contact_sheet::{ContactSheet, SheetSource};
+use crate::error::{Eq2cError, Result};
+use crate::layouts::{self, LayoutOutput, LayoutType};
+use crate::local_tonemap::LocalToneMap;
+use crate::math::CubeFace;
+use crate::progress::{NoProgress, Phase, Progress};
+use crate::sanitize::{self, InvalidPixels, SanitizeMode};
+
+use image::Rgba32FImage;
+use rayon::prelude::*;
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Everything a conversion needs apart from where the image comes from and where it goes.
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// Colour space of the input's linear data.
+    pub input_space: ColorSpace,
+    /// Repair NaN, infinite and negative input values before projection.
+    pub sanitize: Option<SanitizeMode>,
+    /// Fail with `Eq2cError::InvalidValues` instead when the input has any.
+    pub strict: bool,
+    pub format: OutputFormat,
+    pub layout: LayoutType,
+    pub tonemap: ToneMapType,
+    /// Seam-aware local tone mapping applied to the faces before the global tonemap.
+    pub local_tonemap: Option<LocalToneMap>,
+    /// Write a labeled grid of every tonemap instead of a cubemap; PNG only.
+    pub contact_sheet: Option<ContactSheet>,
+    pub exposure: f32,
+    /// Derive the exposure from the scene instead of using `exposure`.
+    pub auto_exposure: Option<AutoExposure>,
+    /// Output transfer function of LDR formats.
+    pub output_transfer: TransferFunction,
+    /// Colour space written to the output. HDR output is always BT.2020.
+    pub output_space: ColorSpace,
+    /// How colours outside the output gamut are handled after conversion.
+    pub gamut_mapping: GamutMapping,
+    /// 3D LUT applied by the LDR encoders before quantization.
+    pub lut: Option<LutSettings>,
+    /// Write display-referred HDR (BT.2020 with PQ or HLG) instead of tone-mapped SDR; PNG and
+    /// AVIF only.
+    pub hdr: Option<HdrTransfer>,
+    /// Peak luminance of the HDR target display, in nits.
+    pub peak_nits: f32,
+    /// Dithering used when LDR outputs are rounded to 8 bits.
+    pub dither: Dither,
+    pub size: u32,
+    /// Quality for lossy output formats (1-100).
+    pub quality: u8,
+    /// Chroma subsampling of JPEG output; `None` keeps each format's own (4:4:4 for JPEG, 4:2:0
+    /// for WebP, which supports nothing else; AVIF is always 4:4:4).
+    pub subsampling: Option<ChromaSubsampling>,
+    /// Encode every face on its own, as the separate layout does, so lossy compression never
+    /// mixes pixels from neighbouring faces across a seam.
+    pub per_face: bool,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            input_space: ColorSpace::Srgb,
+            sanitize: None,
+            strict: false,
+            format: OutputFormat::Png,
+            layout: LayoutType::Cross,
+            tonemap: ToneMapType::Aces,
+            local_tonemap: None,
+            contact_sheet: None,
+            exposure: 1.0,
+            auto_exposure: None,
+            output_transfer: TransferFunction::Srgb,
+            output_space: ColorSpace::Srgb,
+            gamut_mapping: GamutMapping::None,
+            lut: None,
+            hdr: None,
+            peak_nits: 1000.0,
+            dither: Dither::None,
+            size: 512,
+            quality: 90,
+            subsampling: None,
+            per_face: false,
+        }
+    }
+}
+
+impl ConvertOptions {
+    /// Checks that the options can be combined.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::ArgumentError` describing the first conflict.
+    pub fn validate(&self) -> Result<()> {
+        let argument = |message: &str| Err(Eq2cError::ArgumentError(message.to_string()));
+        let ldr = self.hdr.is_none() && !matches!(self.format, OutputFormat::Exr);
+
+        if self.size == 0 {
+            return argument("face size must be at least 1 pixel");
+        }
+
+        self.tonemap.validate().map_err(Eq2cError::ArgumentError)?;
+        if let Some(local) = &self.local_tonemap {
+            local.validate().map_err(Eq2cError::ArgumentError)?;
+        }
+
+        if self.hdr.is_some() && !matches!(self.format, OutputFormat::Png | OutputFormat::Avif) {
+            return argument("HDR output is only supported for PNG and AVIF");
+        }
+
+        if self.hdr.is_some()
+            && !matches!(self.output_space, ColorSpace::Srgb | ColorSpace::Rec2020)
+        {
+            return argument(
+                "HDR output is always BT.2020; other output color spaces cannot be used",
+            );
+        }
+
+        if !(self.peak_nits.is_finite() && self.peak_nits > 0.0) {
+            return argument("peak luminance must be a positive number of nits");
+        }
+
+        if let Some(auto) = &self.auto_exposure {
+            if !(auto.key.is_finite() && auto.key > 0.0) {
+                return argument("auto exposure key must be a positive number");
+            }
+            if !(0.0..=100.0).contains(&auto.percentile) {
+                return argument("auto exposure percentile must be between 0 and 100");
+            }
+        }
+
+        // JPEG, WebP and AVIF are written without colour metadata and read back as sRGB.
+        if ldr
+            && self.output_transfer != TransferFunction::Srgb
+            && matches!(
+                self.format,
+                OutputFormat::Jpeg | OutputFormat::Webp | OutputFormat::Avif
+            )
+        {
+            return argument(
+                "JPEG, WebP and AVIF outputs are always sRGB encoded; other output transfer \
+                 functions can only be recorded in PNG",
+            );
+        }
+
+        // The ACES RRT + ODT takes and produces Rec.709 (the Rec.709 ODT), as do the contact
+        // sheet tiles of it.
+        if ldr
+            && self.output_space != ColorSpace::Srgb
+            && (matches!(self.tonemap, ToneMapType::AcesRrt) || self.contact_sheet.is_some())
+        {
+            return argument(
+                "the aces-rrt tonemap (also used in contact sheets) outputs Rec.709; use the \
+                 srgb output color space",
+            );
+        }
+
+        if matches!(self.format, OutputFormat::Webp)
+            && self
+                .subsampling
+                .is_some_and(|s| s != ChromaSubsampling::Yuv420)
+        {
+            return argument("Lossy WebP always stores chroma at 4:2:0");
+        }
+
+        if matches!(self.format, OutputFormat::Avif)
+            && self
+                .subsampling
+                .is_some_and(|s| s != ChromaSubsampling::Yuv444)
+        {
+            return argument("AVIF always stores chroma at 4:4:4");
+        }
+
+        if self.lut.is_some() && !ldr {
+            return argument("LUTs can only be applied to LDR outputs");
+        }
+
+        if self.local_tonemap.is_some() && !ldr {
+            return argument("Local tonemapping can only be applied to LDR outputs");
+        }
+
+        if let Some(sheet) = &self.contact_sheet {
+            if !matches!(self.format, OutputFormat::Png) || self.hdr.is_some() {
+                return argument("Contact sheets are written as SDR PNG");
+            }
+            if self.lut.as_ref().is_some_and(|lut| lut.shaper.is_some()) {
+                return argument(
+                    "A LUT shaper replaces the tonemap, so every contact sheet tile would match",
+                );
+            }
+            if sheet.source == SheetSource::Equirect && self.local_tonemap.is_some() {
+                return argument(
+                    "Local tonemapping works on cube faces; use the layout contact sheet",
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The colour space handed to the encoders.
+    pub fn target_space(&self) -> ColorSpace {
+        if self.hdr.is_some() {
+            ColorSpace::Rec2020
+        } else {
+            self.output_space
+        }
+    }
+
+    /// The layout actually rendered, taking `per_face` into account.
+    pub fn effective_layout(&self) -> LayoutType {
+        if self.per_face {
+            LayoutType::Separate
+        } else {
+            self.layout
+        }
+    }
+
+    /// Encoder settings for a prepared image, with its (possibly auto-computed) exposure.
+    pub fn encoder_settings(&self, report: &ConversionReport) -> EncoderSettings {
+        EncoderSettings {
+            tonemap: self.tonemap,
+            exposure: report.exposure,
+            transfer: self.output_transfer,
+            quality: self.quality,
+            subsampling: self.subsampling.unwrap_or(match self.format {
+                OutputFormat::Webp => ChromaSubsampling::Yuv420,
+                _ => ChromaSubsampling::Yuv444,
+            }),
+            color_space: self.target_space(),
+            lut: self.lut.clone(),
+            hdr: self.hdr,
+            peak_nits: self.peak_nits,
+            dither: self.dither,
+            alpha: report.has_alpha,
+        }
+    }
+}
+
+/// What [`prepare`] found and decided, for reporting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConversionReport {
+    /// Invalid pixels found in the input (repaired if `sanitize` was set).
+    pub invalid: InvalidPixels,
+    /// Whether the invalid pixels were repaired.
+    pub sanitized: bool,
+    /// Exposure the encoders use.
+    pub exposure: f32,
+    /// Luminance measured by auto exposure, if it ran.
+    pub measured_luminance: Option<f32>,
+    /// Brightest colour component of the (colour converted) input.
+    pub max_brightness: f32,
+    /// Whether any input pixel is not fully opaque.
+    pub has_alpha: bool,
+}
+
+/// One encoded output file and the pixel size of the image in it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+impl EncodedImage {
+    fn encode(encoder: &dyn SkyboxEncoder, buffer: &Rgba32FImage) -> Result<Self> {
+        Ok(Self {
+            width: buffer.width(),
+            height: buffer.height(),
+            data: encoder.encode_to_vec(buffer)?,
+        })
+    }
+}
+
+/// Encoded output files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Encoded {
+    Single(EncodedImage),
+    Faces(Vec<(CubeFace, EncodedImage)>),
+}
+
+/// Result of [`convert`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversion {
+    pub output: Encoded,
+    pub report: ConversionReport,
+}
+
+/// Sanitizes the input, converts it to the target colour space and picks the exposure.
+///
+/// # Errors
+///
+/// Returns `Eq2cError::InvalidValues` in strict mode if the input has NaN, infinite or negative
+/// values.
+pub fn prepare(
+    image: &mut Rgba32FImage,
+    options: &ConvertOptions,
+    progress: &dyn Progress,
+) -> Result<ConversionReport> {
+    progress.start(Phase::Analyze, 1);
+
+    let (invalid, sanitized) = match options.sanitize {
+        _ if options.strict => {
+            let found = InvalidPixels::count(image);
+            if !found.is_empty() {
+                return Err(Eq2cError::InvalidValues(found));
+            }
+            (found, false)
+        }
+        Some(mode) => (sanitize::sanitize(image, mode), true),
+        None => (InvalidPixels::count(image), false),
+    };
+
+    let target = options.target_space();
+    color::gamut::convert_image(image, options.input_space, target, options.gamut_mapping);
+
+    let (max_brightness, has_alpha) = image
+        .par_chunks(4)
+        .map(|p| (p[0].max(p[1]).max(p[2]), p[3] < 1.0))
+        .reduce(|| (0.0f32, false), |a, b| (a.0.max(b.0), a.1 || b.1));
+
+    let (exposure, measured_luminance) = match &options.auto_exposure {
+        Some(auto) => {
+            let (exposure, measured) = auto.compute(image, target.luminance());
+            (exposure, Some(measured))
+        }
+        None => (options.exposure, None),
+    };
+
+    progress.advance(Phase::Analyze, 1);
+    progress.finish(Phase::Analyze);
+
+    Ok(ConversionReport {
+        invalid,
+        sanitized: sanitized && !invalid.is_empty(),
+        exposure,
+        measured_luminance,
+        max_brightness,
+        has_alpha,
+    })
+}
+
+/// Renders the six faces of a prepared image at `options.size`, through the local tonemap when
+/// one is configured.
+///
+/// The buffers stay linear and premultiplied; nothing is tone mapped yet.
+pub fn render_faces(
+    image: &Rgba32FImage,
+    options: &ConvertOptions,
+    progress: &dyn Progress,
+) -> Vec<(CubeFace, Rgba32FImage)> {
+    progress.start(Phase::Layout, CubeFace::ALL.len());
+
+    let faces = match &options.local_tonemap {
+        Some(local) => {
+            let luminance = options.target_space().luminance();
+            local.render_faces(image, options.size, luminance, progress)
+        }
+        None => {
+            let image = layouts::prefilter(image, options.size);
+            let done = AtomicUsize::new(0);
+            CubeFace::ALL
+                .par_iter()
+                .map(|&face| {
+                    let buffer = layouts::render_face(&image, face, options.size, 0);
+                    progress.advance(Phase::Layout, done.fetch_add(1, Ordering::Relaxed) + 1);
+                    (face, buffer)
+                })
+                .collect()
+        }
+    };
+
+    progress.finish(Phase::Layout);
+    faces
+}
+
+/// Projects a prepared image into `layout` (see [`render_faces`]).
+pub fn render_layout(
+    image: &Rgba32FImage,
+    options: &ConvertOptions,
+    layout: LayoutType,
+    progress: &dyn Progress,
+) -> LayoutOutput {
+    let faces = render_faces(image, options, progress);
+    layouts::arrange_layout(layout, Cow::Owned(faces), options.size)
+}
+
+/// Encodes rendered layout buffers with the output format of `options`.
+///
+/// # Errors
+///
+/// Propagates encoder errors.
+pub fn encode_layout(
+    layout: &LayoutOutput,
+    options: &ConvertOptions,
+    report: &ConversionReport,
+    progress: &dyn Progress,
+) -> Result<Encoded> {
+    let encoder = get_encoder(options.format, &options.encoder_settings(report));
+
+    let encoded = match layout {
+        LayoutOutput::Single(buffer) => {
+            progress.start(Phase::Encode, 1);
+            let image = EncodedImage::encode(encoder.as_ref(), buffer)?;
+            progress.advance(Phase::Encode, 1);
+            Encoded::Single(image)
+        }
+        LayoutOutput::Frames(faces) => {
+            progress.start(Phase::Encode, faces.len());
+            Encoded::Faces(
+                faces
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (face, buffer))| {
+                        let image = EncodedImage::encode(encoder.as_ref(), buffer)?;
+                        progress.advance(Phase::Encode, i + 1);
+                        Ok((*face, image))
+                    })
+                    .collect::<Result<_>>()?,
+            )
+        }
+    };
+
+    progress.finish(Phase::Encode);
+    Ok(encoded)
+}
+
+/// Renders the contact sheet of a prepared image as a PNG.
+///
+/// # Errors
+///
+/// Propagates encoder errors.
+pub fn encode_contact_sheet(
+    image: &Rgba32FImage,
+    options: &ConvertOptions,
+    sheet: &ContactSheet,
+    report: &ConversionReport,
+    progress: &dyn Progress,
+) -> Result<EncodedImage> {
+    let rendered;
+    let source = match sheet.source {
+        SheetSource::Equirect => image,
+        SheetSource::Layout => {
+            // The separate layout has no single image; show its faces as a cross.
+            let layout = match options.layout {
+                LayoutType::Separate => LayoutType::Cross,
+                other => other,
+            };
+            let LayoutOutput::Single(buffer) = render_layout(image, options, layout, progress)
+            else {
+                unreachable!("only the separate layout produces frames");
+            };
+            rendered = buffer;
+            &rendered
+        }
+    };
+
+    progress.start(Phase::Encode, 1);
+    let settings = options.encoder_settings(report);
+    let sheet = sheet.render(source, &settings.ldr())?;
+    let info = PngColorInfo::for_transfer(settings.transfer).with_primaries(settings.color_space);
+
+    let mut data = Vec::new();
+    write_png(
+        &mut data,
+        sheet.width(),
+        sheet.height(),
+        sheet.as_raw(),
+        png::ColorType::Rgb,
+        png::BitDepth::Eight,
+        &info,
+    )?;
+
+    progress.advance(Phase::Encode, 1);
+    progress.finish(Phase::Encode);
+    Ok(EncodedImage {
+        width: sheet.width(),
+        height: sheet.height(),
+        data,
+    })
+}
+
+/// Converts a linear, premultiplied equirectangular image entirely in memory: prepares it,
+/// renders the layout (or contact sheet) and encodes it, without touching the file system or
+/// printing anything.
+///
+/// # Errors
+///
+/// Returns `Eq2cError::ArgumentError` for conflicting options, `Eq2cError::InvalidValues` in
+/// strict mode, and propagates encoder errors.
+///
+/// # Examples
+///
+/// ```
+/// use eq2c::{ConvertOptions, Encoded, LayoutType, OutputFormat};
+///
+/// let panorama = image::Rgba32FImage::from_pixel(64, 32, image::Rgba([0.5, 0.5, 0.5, 1.0]));
+/// let options = ConvertOptions {
+///     format: OutputFormat::Png,
+///     layout: LayoutType::StripHorizontal,
+///     size: 16,
+///     ..Default::default()
+/// };
+///
+/// let conversion = eq2c::convert(panorama, &options).unwrap();
+/// let Encoded::Single(png) = conversion.output else { unreachable!() };
+/// assert!(png.data.starts_with(b"\x89PNG"));
+/// assert_eq!((png.width, png.height), (96, 16));
+/// ```
+pub fn convert(image: Rgba32FImage, options: &ConvertOptions) -> Result<Conversion> {
+    convert_with_progress(image, options, &NoProgress)
+}
+
+/// Like [`convert`], reporting the analyze, layout and encode phases to `progress`.
+///
+/// # Errors
+///
+/// See [`convert`].
+pub fn convert_with_progress(
+    mut image: Rgba32FImage,
+    options: &ConvertOptions,
+    progress: &dyn Progress,
+) -> Result<Conversion> {
+    options.validate()?;
+    let report = prepare(&mut image, options, progress)?;
+
+    let output = match &options.contact_sheet {
+        Some(sheet) => Encoded::Single(encode_contact_sheet(
+            &image, options, sheet, &report, progress,
+        )?),
+        None => {
+            let layout = render_layout(&image, options, options.effective_layout(), progress);
+            encode_layout(&layout, options, &report, progress)?
+        }
+    };
+
+    Ok(Conversion { output, report })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panorama() -> Rgba32FImage {
+        Rgba32FImage::from_fn(32, 16, |x, _| {
+            let v = x as f32 / 32.0;
+            image::Rgba([v, 0.5, 1.0 - v, 1.0])
+        })
+    }
+
+    #[test]
+    fn test_convert_in_memory() {
+        let options = ConvertOptions {
+            layout: LayoutType::Separate,
+            format: OutputFormat::Jpeg,
+            size: 8,
+            ..Default::default()
+        };
+
+        let conversion = convert(panorama(), &options).unwrap();
+
+        let Encoded::Faces(faces) = conversion.output else {
+            panic!("expected one file per face");
+        };
+        assert_eq!(faces.len(), 6);
+        assert!(
+            faces
+                .iter()
+                .all(|(_, jpeg)| jpeg.width == 8 && jpeg.data.starts_with(&[0xFF, 0xD8]))
+        );
+        assert_eq!(conversion.report.exposure, 1.0);
+    }
+
+    #[test]
+    fn test_cross_has_alpha_only_for_transparent_input() {
+        let options = ConvertOptions {
+            size: 8,
+            ..Default::default()
+        };
+        // Byte 25 is the IHDR colour type: 2 for RGB, 6 for RGBA.
+        let color_type = |image| {
+            let Encoded::Single(png) = convert(image, &options).unwrap().output else {
+                panic!("expected a single cross image");
+            };
+            png.data[25]
+        };
+
+        assert_eq!(color_type(panorama()), 2);
+
+        let mut transparent = panorama();
+        transparent.put_pixel(0, 0, image::Rgba([0.0, 0.0, 0.0, 0.0]));
+        assert_eq!(color_type(transparent), 6);
+    }
+
+    #[test]
+    fn test_reports_progress_per_face() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<(Phase, usize)>>);
+
+        impl Progress for Recorder {
+            fn advance(&self, phase: Phase, done: usize) {
+                self.0.lock().unwrap().push((phase, done));
+            }
+        }
+
+        let options = ConvertOptions {
+            layout: LayoutType::Separate,
+            size: 8,
+            ..Default::default()
+        };
+        let recorder = Recorder::default();
+        convert_with_progress(panorama(), &options, &recorder).unwrap();
+
+        let events = recorder.0.into_inner().unwrap();
+        let count = |phase| events.iter().filter(|(p, _)| *p == phase).count();
+        assert_eq!(count(Phase::Analyze), 1);
+        assert_eq!(count(Phase::Layout), 6);
+        assert_eq!(count(Phase::Encode), 6);
+        assert!(events.contains(&(Phase::Layout, 6)));
+        assert_eq!(events.last(), Some(&(Phase::Encode, 6)));
+    }
+
+    #[test]
+    fn test_strict_rejects_invalid_values() {
+        let mut image = panorama();
+        image.put_pixel(3, 3, image::Rgba([f32::NAN, 0.0, 0.0, 1.0]));
+        let options = ConvertOptions {
+            strict: true,
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            convert(image, &options),
+            Err(Eq2cError::InvalidValues(InvalidPixels { nan: 1, .. }))
+        ));
+    }
+
+    #[test]
+    fn test_validate_rejects_unsupported_combinations() {
+        let invalid = [
+            ConvertOptions {
+                format: OutputFormat::Jpeg,
+                output_transfer: TransferFunction::Gamma22,
+                ..Default::default()
+            },
+            ConvertOptions {
+                format: OutputFormat::Webp,
+                subsampling: Some(ChromaSubsampling::Yuv444),
+                ..Default::default()
+            },
+            ConvertOptions {
+                auto_exposure: Some(AutoExposure {
+                    key: 0.0,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ConvertOptions {
+                auto_exposure: Some(AutoExposure {
+                    percentile: 150.0,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ConvertOptions {
+                format: OutputFormat::Avif,
+                subsampling: Some(ChromaSubsampling::Yuv420),
+                ..Default::default()
+            },
+            ConvertOptions {
+                tonemap: ToneMapType::AcesRrt,
+                output_space: ColorSpace::DisplayP3,
+                ..Default::default()
+            },
+            ConvertOptions {
+                size: 0,
+                ..Default::default()
+            },
+            ConvertOptions {
+                local_tonemap: Some(LocalToneMap {
+                    radius: 0.0,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ConvertOptions {
+                local_tonemap: Some(LocalToneMap {
+                    contrast: -1.0,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        ];
+        for options in invalid {
+            assert!(
+                matches!(options.validate(), Err(Eq2cError::ArgumentError(_))),
+                "{options:?}"
+            );
+        }
+
+        let png = ConvertOptions {
+            output_transfer: TransferFunction::Gamma22,
+            ..Default::default()
+        };
+        assert!(png.validate().is_ok());
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+
+/// A stage of a conversion, as reported to [`Progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Reading and decoding the input.
+    Load,
+    /// Sanitizing, colour conversion and exposure.
+    Analyze,
+    /// Rendering the cube faces (one step per face).
+    Layout,
+    /// Encoding the output (one step per file).
+    Encode,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Phase::Load => "Loading",
+            Phase::Analyze => "Analyzing",
+            Phase::Layout => "Rendering faces",
+            Phase::Encode => "Encoding",
+        })
+    }
+}
+
+/// Receives progress updates from [`crate::run_with_progress`] and the [`crate::pipeline`] steps.
+///
+/// Faces are rendered in parallel, so `advance` may be called from several threads at once. All
+/// methods default to doing nothing.
+pub trait Progress: Sync {
+    /// `phase` starts and will take `total` steps.
+    fn start(&self, _phase: Phase, _total: usize) {}
+
+    /// `done` of the current phase's steps are complete.
+    fn advance(&self, _phase: Phase, _done: usize) {}
+
+    /// `phase` is complete.
+    fn finish(&self, _phase: Phase) {}
+}
+
+/// Ignores all progress updates.
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
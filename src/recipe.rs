@@ -0,0 +1,422 @@
+use crate::StepTimings;
+use crate::batch;
+use crate::codecs::{OutputFormat, ToneMapType};
+use crate::color::{ColorSpace, HdrTransfer};
+use crate::error::{Eq2cError, Result};
+use crate::layouts::{self, LayoutType};
+use crate::math::CubeFace;
+use crate::pipeline::{self, Conversion, ConvertOptions};
+use crate::progress::Progress;
+
+use clap::ValueEnum;
+use image::Rgba32FImage;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Instant;
+
+/// Several outputs rendered from one loaded image.
+///
+/// A recipe is a TOML or JSON file with an `output` list. Every entry needs a `path` (a name
+/// template, see [`batch::output_name`]) and can override `format` (otherwise taken from the
+/// path's extension), `layout`, `size`, `tonemap`, `exposure` (which turns auto exposure off for
+/// that output), `quality`, `hdr` (`pq`, `hlg` or `none`) and `per_face`. Everything else comes
+/// from the base options.
+///
+/// ```toml
+/// [[output]]
+/// path = "{stem}_preview.png"
+/// size = 256
+///
+/// [[output]]
+/// path = "{stem}.exr"
+/// layout = "separate"
+/// size = 1024
+/// ```
+#[derive(Debug, Clone)]
+pub struct Recipe {
+    pub targets: Vec<RecipeTarget>,
+}
+
+/// One output of a [`Recipe`].
+#[derive(Debug, Clone)]
+pub struct RecipeTarget {
+    /// Output file name template, relative to the output directory.
+    pub path: String,
+    pub options: ConvertOptions,
+}
+
+/// The encoded output of one [`RecipeTarget`].
+#[derive(Debug, Clone)]
+pub struct RecipeOutput {
+    pub conversion: Conversion,
+    /// Work shared between targets is counted for the first target that needs it.
+    pub timings: StepTimings,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RecipeFile {
+    output: Vec<TargetFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TargetFile {
+    path: String,
+    format: Option<String>,
+    layout: Option<String>,
+    size: Option<u32>,
+    tonemap: Option<String>,
+    exposure: Option<f32>,
+    quality: Option<u8>,
+    hdr: Option<String>,
+    per_face: Option<bool>,
+}
+
+impl Recipe {
+    /// Reads a `.toml` or `.json` recipe; outputs start from `base`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Eq2cError::Io` if the file cannot be read and `Eq2cError::InvalidRecipe` if it
+    /// cannot be parsed or describes invalid outputs.
+    pub fn open(path: &Path, base: &ConvertOptions) -> Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let is_json = path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+
+        let parsed = if is_json {
+            Self::from_json(&text, base)
+        } else {
+            Self::from_toml(&text, base)
+        };
+        parsed.map_err(|e| match e {
+            Eq2cError::InvalidRecipe(message) => {
+                Eq2cError::InvalidRecipe(format!("{}: {}", path.display(), message))
+            }
+            other => other,
+        })
+    }
+
+    /// Parses a TOML recipe.
+    ///
+    /// # Errors
+    ///
+    /// See [`Recipe::open`].
+    pub fn from_toml(text: &str, base: &ConvertOptions) -> Result<Self> {
+        let file: RecipeFile =
+            toml::from_str(text).map_err(|e| Eq2cError::InvalidRecipe(e.message().to_string()))?;
+        Self::from_file(file, base)
+    }
+
+    /// Parses a JSON recipe.
+    ///
+    /// # Errors
+    ///
+    /// See [`Recipe::open`].
+    pub fn from_json(text: &str, base: &ConvertOptions) -> Result<Self> {
+        let file: RecipeFile =
+            serde_json::from_str(text).map_err(|e| Eq2cError::InvalidRecipe(e.to_string()))?;
+        Self::from_file(file, base)
+    }
+
+    fn from_file(file: RecipeFile, base: &ConvertOptions) -> Result<Self> {
+        if file.output.is_empty() {
+            return Err(Eq2cError::InvalidRecipe("no outputs".to_string()));
+        }
+        if base.contact_sheet.is_some() {
+            return Err(Eq2cError::ArgumentError(
+                "recipes cannot be combined with a contact sheet".to_string(),
+            ));
+        }
+
+        let targets = file
+            .output
+            .into_iter()
+            .map(|target| {
+                let path = target.path.clone();
+                RecipeTarget::new(target, base)
+                    .map_err(|message| Eq2cError::InvalidRecipe(format!("{}: {}", path, message)))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self { targets })
+    }
+
+    /// Loads the same LUT into every target.
+    pub fn set_lut(&mut self, lut: Option<crate::color::LutSettings>) {
+        for target in &mut self.targets {
+            target.options.lut = lut.clone();
+        }
+    }
+}
+
+impl RecipeTarget {
+    fn new(file: TargetFile, base: &ConvertOptions) -> std::result::Result<Self, String> {
+        let mut options = base.clone();
+
+        let extension = Path::new(&file.path)
+            .extension()
+            .and_then(|e| OutputFormat::from_extension(&e.to_string_lossy()));
+        options.format = match (&file.format, extension) {
+            (Some(format), _) => format.parse()?,
+            (None, Some(format)) => format,
+            (None, None) => base.format,
+        };
+
+        if let Some(layout) = &file.layout {
+            options.layout = layout.parse::<LayoutType>()?;
+            options.per_face = false;
+        }
+        if let Some(tonemap) = &file.tonemap {
+            options.tonemap = tonemap.parse::<ToneMapType>()?;
+        }
+        if let Some(exposure) = file.exposure {
+            options.exposure = exposure;
+            options.auto_exposure = None;
+        }
+        if let Some(hdr) = &file.hdr {
+            options.hdr = match hdr.as_str() {
+                "none" => None,
+                name => Some(HdrTransfer::from_str(name, true)?),
+            };
+        }
+        options.size = file.size.unwrap_or(options.size);
+        options.quality = file.quality.unwrap_or(options.quality);
+        options.per_face = file.per_face.unwrap_or(options.per_face);
+        if file.layout.is_some() && file.per_face == Some(true) {
+            return Err("per_face implies the separate layout; leave out layout".to_string());
+        }
+
+        if options.size == 0 {
+            return Err("size must be positive".to_string());
+        }
+        if !(1..=100).contains(&options.quality) {
+            return Err("quality must be between 1 and 100".to_string());
+        }
+
+        // Catch bad placeholders now rather than after rendering.
+        batch::output_name(
+            &file.path,
+            Path::new("input"),
+            options.format,
+            options.layout,
+            options.size,
+        )
+        .map_err(|e| e.to_string())?;
+        options.validate().map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            path: file.path,
+            options,
+        })
+    }
+}
+
+/// Faces can be shared by targets that agree on everything that goes into rendering them.
+#[derive(PartialEq, Eq, Hash)]
+struct FaceKey {
+    size: u32,
+    local_tonemap: Option<[u32; 3]>,
+}
+
+impl FaceKey {
+    fn new(options: &ConvertOptions) -> Self {
+        Self {
+            size: options.size,
+            local_tonemap: options
+                .local_tonemap
+                .as_ref()
+                .map(|l| [l.contrast, l.detail, l.radius].map(f32::to_bits)),
+        }
+    }
+}
+
+/// Renders and encodes every target of `recipe` from one loaded image (see [`convert_targets`]).
+///
+/// # Errors
+///
+/// Returns the first error of any target (see [`pipeline::convert`]).
+pub fn convert_recipe(
+    image: Rgba32FImage,
+    recipe: &Recipe,
+    progress: &dyn Progress,
+) -> Result<Vec<RecipeOutput>> {
+    let targets: Vec<ConvertOptions> = recipe.targets.iter().map(|t| t.options.clone()).collect();
+    convert_targets(image, &targets, progress)
+}
+
+/// Converts one loaded image with each of `targets`.
+///
+/// The image is prepared once per output colour space, and the faces are rendered once per face
+/// size (and local tonemap setting) and shared by every layout and format that needs them.
+///
+/// # Errors
+///
+/// Returns the first error of any target (see [`pipeline::convert`]).
+pub fn convert_targets(
+    mut image: Rgba32FImage,
+    targets: &[ConvertOptions],
+    progress: &dyn Progress,
+) -> Result<Vec<RecipeOutput>> {
+    for options in targets {
+        options.validate()?;
+    }
+
+    let mut spaces: Vec<ColorSpace> = Vec::new();
+    for options in targets {
+        let space = options.target_space();
+        if !spaces.contains(&space) {
+            spaces.push(space);
+        }
+    }
+
+    let mut outputs: Vec<Option<RecipeOutput>> = vec![None; targets.len()];
+
+    for (i, &space) in spaces.iter().enumerate() {
+        let mut prepared = if i + 1 == spaces.len() {
+            std::mem::take(&mut image)
+        } else {
+            image.clone()
+        };
+
+        let indices: Vec<usize> = (0..targets.len())
+            .filter(|&t| targets[t].target_space() == space)
+            .collect();
+
+        // Exposure is only a number passed to the encoders, so it is picked per target below.
+        let mut shared = targets[indices[0]].clone();
+        shared.auto_exposure = None;
+        let start = Instant::now();
+        let base_report = pipeline::prepare(&mut prepared, &shared, progress)?;
+        let mut analyze = start.elapsed();
+
+        let mut auto_exposure = HashMap::new();
+        let mut faces: HashMap<FaceKey, Vec<(CubeFace, Rgba32FImage)>> = HashMap::new();
+
+        for t in indices {
+            let options = &targets[t];
+            let mut timings = StepTimings {
+                analyze: std::mem::take(&mut analyze),
+                ..Default::default()
+            };
+
+            let mut report = base_report;
+            report.exposure = options.exposure;
+            if let Some(auto) = &options.auto_exposure {
+                let start = Instant::now();
+                let key = (
+                    auto.method,
+                    [auto.key, auto.percentile, auto.compensation].map(f32::to_bits),
+                );
+                let (exposure, measured) = *auto_exposure
+                    .entry(key)
+                    .or_insert_with(|| auto.compute(&prepared, space.luminance()));
+                report.exposure = exposure;
+                report.measured_luminance = Some(measured);
+                timings.analyze += start.elapsed();
+            }
+
+            let start = Instant::now();
+            let rendered = faces
+                .entry(FaceKey::new(options))
+                .or_insert_with(|| pipeline::render_faces(&prepared, options, progress));
+            let layout = layouts::arrange_layout(
+                options.effective_layout(),
+                Cow::Borrowed(rendered),
+                options.size,
+            );
+            timings.layout = start.elapsed();
+
+            let start = Instant::now();
+            let output = pipeline::encode_layout(&layout, options, &report, progress)?;
+            timings.encode = start.elapsed();
+
+            outputs[t] = Some(RecipeOutput {
+                conversion: Conversion { output, report },
+                timings,
+            });
+        }
+    }
+
+    Ok(outputs
+        .into_iter()
+        .map(|output| output.expect("every target belongs to one colour space"))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Encoded;
+    use crate::progress::Phase;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const RECIPE: &str = r#"
+        [[output]]
+        path = "{stem}_preview.png"
+        size = 8
+
+        [[output]]
+        path = "{stem}.exr"
+        layout = "separate"
+        size = 8
+
+        [[output]]
+        path = "{stem}_small.jpg"
+        layout = "strip-h"
+        size = 4
+        exposure = 0.5
+    "#;
+
+    #[test]
+    fn test_parse_recipe() {
+        let recipe = Recipe::from_toml(RECIPE, &ConvertOptions::default()).unwrap();
+
+        let formats: Vec<_> = recipe.targets.iter().map(|t| t.options.format).collect();
+        assert!(matches!(
+            formats[..],
+            [OutputFormat::Png, OutputFormat::Exr, OutputFormat::Jpeg]
+        ));
+        assert_eq!(recipe.targets[1].options.layout, LayoutType::Separate);
+        assert_eq!(recipe.targets[2].options.exposure, 0.5);
+
+        let unknown = Recipe::from_json(
+            r#"{"output": [{"path": "a.png", "tonemap": "aces", "sharpen": true}]}"#,
+            &ConvertOptions::default(),
+        );
+        assert!(matches!(unknown, Err(Eq2cError::InvalidRecipe(_))));
+    }
+
+    #[test]
+    fn test_faces_rendered_once_per_size() {
+        struct CountFaces(AtomicUsize);
+
+        impl Progress for CountFaces {
+            fn start(&self, phase: Phase, _total: usize) {
+                if phase == Phase::Layout {
+                    self.0.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let recipe = Recipe::from_toml(RECIPE, &ConvertOptions::default()).unwrap();
+        let image = Rgba32FImage::from_pixel(32, 16, image::Rgba([1.0, 0.5, 0.25, 1.0]));
+        let counter = CountFaces(AtomicUsize::new(0));
+
+        let outputs = convert_recipe(image.clone(), &recipe, &counter).unwrap();
+
+        // Sizes 8 and 4.
+        assert_eq!(counter.0.load(Ordering::Relaxed), 2);
+        assert!(matches!(outputs[0].conversion.output, Encoded::Single(_)));
+        assert!(matches!(&outputs[1].conversion.output, Encoded::Faces(f) if f.len() == 6));
+        assert_eq!(outputs[2].conversion.report.exposure, 0.5);
+
+        // Shared faces encode exactly like a conversion of its own.
+        let alone = pipeline::convert(image, &recipe.targets[0].options).unwrap();
+        assert_eq!(alone.output, outputs[0].conversion.output);
+    }
+}
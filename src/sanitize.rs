@@ -0,0 +1,210 @@
+use clap::ValueEnum;
+use image::Rgba32FImage;
+use rayon::prelude::*;
+use std::fmt;
+
+/// Inpainting grows inwards by one pixel per pass; holes still open after this many are zeroed.
+const MAX_INPAINT_PASSES: usize = 64;
+
+/// How NaN, infinite and negative components are repaired before projection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SanitizeMode {
+    /// Replace every invalid component with 0.
+    Zero,
+    /// NaN and negative components become 0, +Inf becomes the largest finite value in the image.
+    Clamp,
+    /// Replace invalid pixels with the average of their valid neighbours, filling holes from the
+    /// outside in.
+    Inpaint,
+}
+
+/// Number of pixels with at least one NaN, infinite or negative component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InvalidPixels {
+    pub nan: u64,
+    pub infinite: u64,
+    pub negative: u64,
+}
+
+impl InvalidPixels {
+    /// Counts the invalid pixels of an image.
+    pub fn count(image: &Rgba32FImage) -> Self {
+        image
+            .par_chunks(4)
+            .map(|p| Self {
+                nan: p.iter().any(|v| v.is_nan()) as u64,
+                infinite: p.iter().any(|v| v.is_infinite()) as u64,
+                negative: p.iter().any(|&v| v < 0.0) as u64,
+            })
+            .reduce(Self::default, |a, b| Self {
+                nan: a.nan + b.nan,
+                infinite: a.infinite + b.infinite,
+                negative: a.negative + b.negative,
+            })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl fmt::Display for InvalidPixels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} NaN, {} infinite and {} negative pixels",
+            self.nan, self.infinite, self.negative
+        )
+    }
+}
+
+fn is_invalid(p: &[f32]) -> bool {
+    p.iter().any(|&v| !v.is_finite() || v < 0.0)
+}
+
+/// Repairs invalid components of a premultiplied image in place and returns what was found.
+///
+/// Sampling mixes neighbouring texels, so a single NaN would otherwise spread into a black or
+/// white blotch on every face that sees it.
+pub fn sanitize(image: &mut Rgba32FImage, mode: SanitizeMode) -> InvalidPixels {
+    let found = InvalidPixels::count(image);
+    if found.is_empty() {
+        return found;
+    }
+
+    match mode {
+        SanitizeMode::Zero => replace_components(image, 0.0),
+        SanitizeMode::Clamp => {
+            let max = image
+                .par_iter()
+                .copied()
+                .filter(|v| v.is_finite())
+                .reduce(|| 0.0, f32::max);
+            replace_components(image, max);
+        }
+        SanitizeMode::Inpaint => inpaint(image),
+    }
+
+    found
+}
+
+/// Sets NaN, -Inf and negative components to 0 and +Inf to `infinity`.
+fn replace_components(image: &mut Rgba32FImage, infinity: f32) {
+    image.par_iter_mut().for_each(|v| {
+        if *v == f32::INFINITY {
+            *v = infinity;
+        } else if !v.is_finite() || *v < 0.0 {
+            *v = 0.0;
+        }
+    });
+}
+
+/// Fills invalid pixels with the mean of their valid 8-neighbours (wrapping horizontally, as the
+/// panorama does), one ring per pass.
+fn inpaint(image: &mut Rgba32FImage) {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    let mut holes: Vec<usize> = image
+        .as_raw()
+        .par_chunks(4)
+        .enumerate()
+        .filter(|(_, p)| is_invalid(p))
+        .map(|(i, _)| i)
+        .collect();
+    let mut valid: Vec<bool> = image.par_chunks(4).map(|p| !is_invalid(p)).collect();
+
+    for _ in 0..MAX_INPAINT_PASSES {
+        if holes.is_empty() {
+            break;
+        }
+
+        let raw = image.as_raw();
+        let filled: Vec<(usize, [f32; 4])> = holes
+            .par_iter()
+            .filter_map(|&i| {
+                let (x, y) = ((i % width) as isize, (i / width) as isize);
+                let mut sum = [0.0; 4];
+                let mut count = 0;
+
+                for dy in -1..=1 {
+                    let ny = y + dy;
+                    if !(0..height as isize).contains(&ny) {
+                        continue;
+                    }
+                    for dx in -1..=1 {
+                        let nx = (x + dx).rem_euclid(width as isize);
+                        let n = ny as usize * width + nx as usize;
+                        if n != i && valid[n] {
+                            for (s, v) in sum.iter_mut().zip(&raw[n * 4..n * 4 + 4]) {
+                                *s += v;
+                            }
+                            count += 1;
+                        }
+                    }
+                }
+
+                (count > 0).then(|| (i, sum.map(|s| s / count as f32)))
+            })
+            .collect();
+
+        if filled.is_empty() {
+            break;
+        }
+
+        for &(i, pixel) in &filled {
+            image.as_mut()[i * 4..i * 4 + 4].copy_from_slice(&pixel);
+            valid[i] = true;
+        }
+        holes.retain(|&i| !valid[i]);
+    }
+
+    // Nothing valid within reach (or an entirely invalid image).
+    for i in holes {
+        image.as_mut()[i * 4..i * 4 + 4].fill(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bad_image() -> Rgba32FImage {
+        let mut img = Rgba32FImage::from_pixel(4, 4, image::Rgba([2.0, 2.0, 2.0, 1.0]));
+        img.put_pixel(1, 1, image::Rgba([f32::NAN, 2.0, 2.0, 1.0]));
+        img.put_pixel(2, 1, image::Rgba([f32::INFINITY, 2.0, 2.0, 1.0]));
+        img.put_pixel(3, 3, image::Rgba([-1.0, 2.0, 2.0, 1.0]));
+        img
+    }
+
+    #[test]
+    fn test_zero_and_clamp() {
+        let mut zero = bad_image();
+        let found = sanitize(&mut zero, SanitizeMode::Zero);
+        assert_eq!(
+            found,
+            InvalidPixels {
+                nan: 1,
+                infinite: 1,
+                negative: 1
+            }
+        );
+        assert_eq!(zero.get_pixel(2, 1).0, [0.0, 2.0, 2.0, 1.0]);
+
+        let mut clamp = bad_image();
+        sanitize(&mut clamp, SanitizeMode::Clamp);
+        assert_eq!(clamp.get_pixel(2, 1).0, [2.0, 2.0, 2.0, 1.0]);
+        assert_eq!(clamp.get_pixel(3, 3).0, [0.0, 2.0, 2.0, 1.0]);
+        assert!(InvalidPixels::count(&clamp).is_empty());
+    }
+
+    #[test]
+    fn test_inpaint_fills_from_neighbours() {
+        let mut img = bad_image();
+        sanitize(&mut img, SanitizeMode::Inpaint);
+
+        assert!(InvalidPixels::count(&img).is_empty());
+        for (x, y) in [(1, 1), (2, 1), (3, 3)] {
+            assert_eq!(img.get_pixel(x, y).0, [2.0, 2.0, 2.0, 1.0]);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often files are checked by default.
+pub const DEFAULT_POLL: Duration = Duration::from_millis(250);
+
+/// How long files must stay unchanged by default before a change is reported.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Modification time and length; a file that is missing (e.g. while an editor replaces it) has
+/// none.
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Polls a set of files for modifications.
+///
+/// Applications save in several steps (write, rename, touch), so a change is only reported once
+/// the files have stayed the same for `debounce`. A file that is briefly missing while it is
+/// replaced resets the wait; one that stays missing is reported as changed.
+#[derive(Debug)]
+pub struct FileWatcher {
+    paths: Vec<PathBuf>,
+    stamps: Vec<Stamp>,
+    pub poll: Duration,
+    pub debounce: Duration,
+}
+
+impl FileWatcher {
+    /// Starts watching `paths` from their current state.
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let stamps = paths.iter().map(|path| stamp(path)).collect();
+        Self {
+            paths,
+            stamps,
+            poll: DEFAULT_POLL,
+            debounce: DEFAULT_DEBOUNCE,
+        }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Blocks until some files change and settle, and returns the changed ones. Later calls
+    /// report changes since the previous one.
+    pub fn wait(&mut self) -> Vec<PathBuf> {
+        loop {
+            std::thread::sleep(self.poll);
+            let mut current = self.current();
+            if current == self.stamps {
+                continue;
+            }
+
+            // Wait for the writer to finish.
+            let mut settled = Instant::now();
+            while settled.elapsed() < self.debounce {
+                std::thread::sleep(self.poll);
+                let next = self.current();
+                if next != current {
+                    current = next;
+                    settled = Instant::now();
+                }
+            }
+
+            let changed = self
+                .paths
+                .iter()
+                .zip(self.stamps.iter().zip(&current))
+                .filter(|(_, (old, new))| old != new)
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>();
+            self.stamps = current;
+
+            if !changed.is_empty() {
+                return changed;
+            }
+        }
+    }
+
+    fn current(&self) -> Vec<Stamp> {
+        self.paths.iter().map(|path| stamp(path)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports_settled_change() {
+        let dir = std::env::temp_dir().join(format!("eq2c-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let watched = dir.join("sky.hdr");
+        let other = dir.join("grade.cube");
+        std::fs::write(&watched, b"a").unwrap();
+        std::fs::write(&other, b"a").unwrap();
+
+        let mut watcher = FileWatcher::new(vec![watched.clone(), other.clone()]);
+        watcher.poll = Duration::from_millis(10);
+        watcher.debounce = Duration::from_millis(50);
+
+        let writer = {
+            let watched = watched.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(30));
+                // Replaced in two steps, like a save through a temporary file.
+                std::fs::remove_file(&watched).unwrap();
+                std::thread::sleep(Duration::from_millis(20));
+                std::fs::write(&watched, b"changed").unwrap();
+            })
+        };
+
+        assert_eq!(watcher.wait(), vec![watched.clone()]);
+        writer.join().unwrap();
+
+        // A file that is deleted for good is reported once the debounce has passed.
+        std::fs::remove_file(&other).unwrap();
+        assert_eq!(watcher.wait(), vec![other]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}